@@ -1,18 +1,33 @@
-use std::env;
+use std::{collections::HashMap, env};
 
 use askama::Template;
 use axum::{
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse},
     routing::get,
     Json, Router,
 };
 use axum_extra::extract::CookieJar;
+use opentelemetry::{propagation::TextMapPropagator, trace::TracerProvider};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
 use serde::Serialize;
 use tower_http::services::ServeDir;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{prelude::*, EnvFilter};
+
+// Header that carries the id of a request, shared with the auth service.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Longest request id passed on from a client. Longer ones are replaced by a new id.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
 
 #[tokio::main]
 async fn main() {
+    let tracer_provider = init_tracing();
+
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
@@ -22,6 +37,36 @@ async fn main() {
 
     println!("listening on {}", listener.local_addr().unwrap());
     axum::serve(listener, app).await.unwrap();
+
+    tracer_provider
+        .shutdown()
+        .expect("Failed to export the last spans");
+}
+
+// Log to stdout, and export spans to the OpenTelemetry collector at OTLP_ENDPOINT if
+// it is set. Spans get trace ids even when they are not exported, so that calls to
+// the auth service always carry a trace context.
+fn init_tracing() -> SdkTracerProvider {
+    let resource = Resource::builder().with_service_name("app-service").build();
+    let mut builder = SdkTracerProvider::builder().with_resource(resource);
+    if let Some(endpoint) = env::var("OTLP_ENDPOINT").ok().filter(|e| !e.is_empty()) {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+            .build()
+            .expect("Failed to build the span exporter");
+        builder = builder.with_batch_exporter(exporter);
+    }
+    let tracer_provider = builder.build();
+
+    let filter_layer = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("app-service")))
+        .init();
+
+    tracer_provider
 }
 
 #[derive(Template)]
@@ -46,7 +91,12 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
+#[tracing::instrument(name = "Protected", skip_all)]
+async fn protected(headers: HeaderMap, jar: CookieJar) -> impl IntoResponse {
+    // Join the trace of the caller, if it sent one.
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+    let _ = Span::current().set_parent(parent);
+
     let jwt_cookie = match jar.get("jwt") {
         Some(cookie) => cookie,
         None => {
@@ -54,23 +104,17 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
         }
     };
 
-    let api_client = reqwest::Client::builder().build().unwrap();
-
-    let verify_token_body = serde_json::json!({
-        "token": &jwt_cookie.value(),
-    });
-
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
     let url = format!("http://{}:3000/verify-token", auth_hostname);
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
-        Ok(response) => response,
+    let status = match verify_token(&url, jwt_cookie.value(), &headers).await {
+        Ok(status) => status,
         Err(_) => {
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
-    match response.status() {
+    match status {
         reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
             StatusCode::UNAUTHORIZED.into_response()
         }
@@ -86,3 +130,130 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
 pub struct ProtectedRouteResponse {
     pub img_url: String,
 }
+
+// Ask the auth service at `url` whether `token` is valid, passing on the trace context
+// and the request id so that its logs and spans join those of this request.
+async fn verify_token(
+    url: &str,
+    token: &str,
+    headers: &HeaderMap,
+) -> reqwest::Result<reqwest::StatusCode> {
+    let api_client = reqwest::Client::builder().build()?;
+
+    let verify_token_body = serde_json::json!({
+        "token": token,
+    });
+
+    let mut request = api_client.post(url).json(&verify_token_body);
+    for (name, value) in trace_context_headers(headers) {
+        request = request.header(name, value);
+    }
+
+    Ok(request.send().await?.status())
+}
+
+// The W3C trace context of the current span, and the id of the request: the one the
+// client sent, or a new one.
+fn trace_context_headers(headers: &HeaderMap) -> HashMap<String, String> {
+    let mut outgoing = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Span::current().context(), &mut outgoing);
+
+    let request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .map(str::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    outgoing.insert(REQUEST_ID_HEADER.to_owned(), request_id);
+
+    outgoing
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing::Instrument;
+    use wiremock::{
+        matchers::{header_exists, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    // A subscriber that gives spans trace ids, without exporting them.
+    fn subscriber() -> impl tracing::Subscriber + Send + Sync {
+        let tracer_provider = SdkTracerProvider::builder().build();
+        tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")))
+    }
+
+    #[tokio::test]
+    async fn verify_token_sends_a_trace_context_and_a_request_id_when_the_client_sent_none() {
+        let _guard = tracing::subscriber::set_default(subscriber());
+        let auth_service = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/verify-token"))
+            .and(header_exists("traceparent"))
+            .and(header_exists(REQUEST_ID_HEADER))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&auth_service)
+            .await;
+
+        let span = tracing::info_span!("Protected");
+        let trace_id = span.context().span().span_context().trace_id();
+        let url = format!("{}/verify-token", auth_service.uri());
+        let status = verify_token(&url, "token", &HeaderMap::new())
+            .instrument(span)
+            .await
+            .unwrap();
+
+        assert_eq!(status, reqwest::StatusCode::OK);
+        let requests = auth_service.received_requests().await.unwrap();
+        let traceparent = requests[0].headers["traceparent"].to_str().unwrap();
+        assert_eq!(
+            traceparent.split('-').nth(1),
+            Some(trace_id.to_string().as_str())
+        );
+        let request_id = requests[0].headers[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(request_id).is_ok());
+    }
+
+    #[tokio::test]
+    async fn verify_token_passes_on_the_trace_and_the_request_id_of_the_client() {
+        let _guard = tracing::subscriber::set_default(subscriber());
+        let auth_service = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/verify-token"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&auth_service)
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert(REQUEST_ID_HEADER, "request-1".parse().unwrap());
+        let span = tracing::info_span!("Protected");
+        let parent = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+        let _ = span.set_parent(parent);
+        let url = format!("{}/verify-token", auth_service.uri());
+        verify_token(&url, "token", &headers)
+            .instrument(span)
+            .await
+            .unwrap();
+
+        let requests = auth_service.received_requests().await.unwrap();
+        let traceparent = requests[0].headers["traceparent"].to_str().unwrap();
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert_ne!(
+            traceparent,
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(requests[0].headers[REQUEST_ID_HEADER], "request-1");
+    }
+}
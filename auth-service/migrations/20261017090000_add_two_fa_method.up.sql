@@ -0,0 +1,8 @@
+ALTER TABLE users
+   ADD COLUMN two_fa_method TEXT NOT NULL DEFAULT 'none'
+      CHECK (two_fa_method IN ('none', 'email', 'totp')),
+   ADD COLUMN totp_secret TEXT;
+
+UPDATE users SET two_fa_method = 'email' WHERE requires_2fa;
+
+ALTER TABLE users DROP COLUMN requires_2fa;
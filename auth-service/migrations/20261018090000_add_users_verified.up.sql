@@ -0,0 +1,4 @@
+-- Accounts created before email verification existed are trusted as verified.
+ALTER TABLE users ADD COLUMN verified BOOLEAN NOT NULL DEFAULT TRUE;
+
+ALTER TABLE users ALTER COLUMN verified SET DEFAULT FALSE;
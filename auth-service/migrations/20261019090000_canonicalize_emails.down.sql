@@ -0,0 +1,2 @@
+-- Canonicalized emails are left as they are: they are still valid.
+DROP INDEX IF EXISTS users_email_lower_key;
@@ -0,0 +1,31 @@
+-- Emails are canonicalized when parsed: trimmed, with the domain lowercased and in
+-- punycode, and with the local part lowercased unless EMAIL_LOCAL_PART_POLICY is
+-- `preserve`. Only the part every policy agrees on is done here: the service
+-- lowercases local parts at startup under the `lowercase` policy. Emails are not
+-- included in the errors, as they end up in logs.
+-- Redis keys named by an email that changes here (pending 2FA codes, failed login
+-- counts) are no longer found, and expire on their own within the hour.
+DO $$
+DECLARE
+   count INTEGER;
+BEGIN
+   -- Punycode cannot be computed here.
+   SELECT count(*) INTO count FROM users WHERE substring(email FROM '@([^@]*)$') ~ '[^[:ascii:]]';
+   IF count > 0 THEN
+      RAISE EXCEPTION '% accounts have an internationalized domain, convert them to punycode first. Find them with: SELECT email FROM users WHERE substring(email FROM ''@([^@]*)$'') ~ ''[^[:ascii:]]''', count;
+   END IF;
+
+   SELECT count(*) INTO count FROM (
+      SELECT lower(btrim(email)) FROM users GROUP BY 1 HAVING count(*) > 1
+   ) AS collisions;
+   IF count > 0 THEN
+      RAISE EXCEPTION '% emails belong to several accounts once canonicalized, merge or delete them first. Find them with: SELECT array_agg(email) FROM users GROUP BY lower(btrim(email)) HAVING count(*) > 1', count;
+   END IF;
+END $$;
+
+UPDATE users
+SET email = substring(btrim(email) FROM '^(.*@)') || lower(substring(btrim(email) FROM '@([^@]*)$'))
+WHERE email <> substring(btrim(email) FROM '^(.*@)') || lower(substring(btrim(email) FROM '@([^@]*)$'));
+
+-- Two accounts never differ only by case, even when the local part is preserved.
+CREATE UNIQUE INDEX users_email_lower_key ON users (lower(email));
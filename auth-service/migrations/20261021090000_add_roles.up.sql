@@ -0,0 +1,20 @@
+CREATE TABLE roles (
+   name TEXT NOT NULL PRIMARY KEY
+);
+
+CREATE TABLE role_permissions (
+   role TEXT NOT NULL REFERENCES roles (name) ON DELETE CASCADE,
+   permission TEXT NOT NULL,
+   PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE user_roles (
+   user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+   role TEXT NOT NULL REFERENCES roles (name) ON DELETE CASCADE,
+   PRIMARY KEY (user_id, role)
+);
+
+INSERT INTO roles (name) VALUES ('admin');
+INSERT INTO role_permissions (role, permission) VALUES
+   ('admin', 'users:read'),
+   ('admin', 'users:write');
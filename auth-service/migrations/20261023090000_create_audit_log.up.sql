@@ -0,0 +1,20 @@
+-- Entries are only ever appended. There is no foreign key on user_id, so that
+-- entries outlive the accounts they are about.
+CREATE TABLE audit_log (
+   sequence BIGINT NOT NULL PRIMARY KEY,
+   kind TEXT NOT NULL,
+   outcome TEXT NOT NULL,
+   reason TEXT,
+   actor TEXT NOT NULL,
+   user_id UUID,
+   ip TEXT,
+   user_agent TEXT,
+   request_id TEXT,
+   occurred_at BIGINT NOT NULL,
+   previous_hash TEXT NOT NULL,
+   hash TEXT NOT NULL
+);
+
+CREATE INDEX audit_log_user_id_occurred_at ON audit_log (user_id, occurred_at);
+
+INSERT INTO role_permissions (role, permission) VALUES ('admin', 'audit:read');
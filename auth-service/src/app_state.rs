@@ -1,40 +1,101 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio_util::task::TaskTracker;
 
-use crate::domain::{BannedTokenStore, EmailClient, UserStore, data_stores::TwoFACodeStore};
+use crate::domain::{
+    AuditSink, BannedTokenStore, EmailClient, UserStore,
+    data_stores::{RateLimitStore, RefreshTokenStore, SessionStore, TwoFACodeStore},
+};
+use crate::utils::{audit::AuditWriter, key_ring::KeyRing, settings::Settings};
 
 // Users
-pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
+pub type UserStoreType = Arc<dyn UserStore + Send + Sync>;
 
 // Expired tokens
-pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
+pub type BannedTokenStoreType = Arc<dyn BannedTokenStore + Send + Sync>;
 
 // 2FA codes
-pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type TwoFACodeStoreType = Arc<dyn TwoFACodeStore + Send + Sync>;
+
+// Refresh tokens
+pub type RefreshTokenStoreType = Arc<dyn RefreshTokenStore + Send + Sync>;
+
+// Logged in devices
+pub type SessionStoreType = Arc<dyn SessionStore + Send + Sync>;
+
+// Failed login and 2FA attempts
+pub type RateLimitStoreType = Arc<dyn RateLimitStore + Send + Sync>;
+
+// Authentication events
+pub type AuditSinkType = Arc<dyn AuditSink + Send + Sync>;
+
+// JWT signing and verification keys
+pub type KeyRingType = Arc<RwLock<KeyRing>>;
 
 // Email client
-pub type EmailClientType = Arc<RwLock<dyn EmailClient + Send + Sync>>;
+pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_tokens_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
+    pub rate_limit_store: RateLimitStoreType,
+    pub audit_sink: AuditSinkType,
+    // Appends to `audit_sink` in the background.
+    pub audit_writer: AuditWriter,
+    pub key_ring: KeyRingType,
     pub email_client: EmailClientType,
+    pub settings: Arc<Settings>,
+    // Work that outlives the request starting it, such as sending emails.
+    pub background_tasks: TaskTracker,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_store: UserStoreType,
         banned_tokens_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
+        refresh_token_store: RefreshTokenStoreType,
+        session_store: SessionStoreType,
+        rate_limit_store: RateLimitStoreType,
+        audit_sink: AuditSinkType,
+        key_ring: KeyRingType,
         email_client: EmailClientType,
+        settings: Arc<Settings>,
     ) -> Self {
         Self {
             user_store,
             banned_tokens_store,
             two_fa_code_store,
+            refresh_token_store,
+            session_store,
+            rate_limit_store,
+            audit_writer: AuditWriter::spawn(audit_sink.clone()),
+            audit_sink,
+            key_ring,
             email_client,
+            settings,
+            background_tasks: TaskTracker::new(),
         }
     }
+
+    // Close the connections of every store and of the email client, once the queued
+    // audit events are written. Closing a pool twice does no harm.
+    pub async fn close(&self) {
+        self.audit_writer.flush().await;
+        tokio::join!(
+            self.user_store.close(),
+            self.banned_tokens_store.close(),
+            self.two_fa_code_store.close(),
+            self.refresh_token_store.close(),
+            self.session_store.close(),
+            self.rate_limit_store.close(),
+            self.audit_sink.close(),
+            self.email_client.close(),
+        );
+    }
 }
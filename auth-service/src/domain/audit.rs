@@ -0,0 +1,348 @@
+use color_eyre::eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::net::IpAddr;
+
+use super::user_id::UserId;
+
+// `previousHash` of the first entry of a log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// What an audited request tried to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditEventKind {
+    #[serde(rename = "signup")]
+    Signup,
+    // A login that started a session, or failed.
+    #[serde(rename = "login")]
+    Login,
+    // A login whose password was right, and that now waits for a 2FA code.
+    #[serde(rename = "2fa_challenge")]
+    TwoFAChallenge,
+    // The 2FA code of a login, which starts a session when right.
+    #[serde(rename = "2fa_verification")]
+    TwoFAVerification,
+    #[serde(rename = "logout")]
+    Logout,
+    // Another service checking a token through `/verify-token`.
+    #[serde(rename = "token_verification")]
+    TokenVerification,
+    // Changes an admin made to an account, through the admin API.
+    #[serde(rename = "admin_user_creation")]
+    AdminUserCreation,
+    #[serde(rename = "admin_user_deletion")]
+    AdminUserDeletion,
+    #[serde(rename = "admin_password_reset")]
+    AdminPasswordReset,
+    #[serde(rename = "admin_2fa_change")]
+    AdminTwoFAChange,
+    #[serde(rename = "admin_lock")]
+    AdminLock,
+    #[serde(rename = "admin_unlock")]
+    AdminUnlock,
+    #[serde(rename = "admin_session_revocation")]
+    AdminSessionRevocation,
+    #[serde(rename = "signing_key_rotation")]
+    SigningKeyRotation,
+}
+
+impl AuditEventKind {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "signup" => Ok(Self::Signup),
+            "login" => Ok(Self::Login),
+            "2fa_challenge" => Ok(Self::TwoFAChallenge),
+            "2fa_verification" => Ok(Self::TwoFAVerification),
+            "logout" => Ok(Self::Logout),
+            "token_verification" => Ok(Self::TokenVerification),
+            "admin_user_creation" => Ok(Self::AdminUserCreation),
+            "admin_user_deletion" => Ok(Self::AdminUserDeletion),
+            "admin_password_reset" => Ok(Self::AdminPasswordReset),
+            "admin_2fa_change" => Ok(Self::AdminTwoFAChange),
+            "admin_lock" => Ok(Self::AdminLock),
+            "admin_unlock" => Ok(Self::AdminUnlock),
+            "admin_session_revocation" => Ok(Self::AdminSessionRevocation),
+            "signing_key_rotation" => Ok(Self::SigningKeyRotation),
+            _ => Err(eyre!("{} is not a valid audit event kind.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signup => "signup",
+            Self::Login => "login",
+            Self::TwoFAChallenge => "2fa_challenge",
+            Self::TwoFAVerification => "2fa_verification",
+            Self::Logout => "logout",
+            Self::TokenVerification => "token_verification",
+            Self::AdminUserCreation => "admin_user_creation",
+            Self::AdminUserDeletion => "admin_user_deletion",
+            Self::AdminPasswordReset => "admin_password_reset",
+            Self::AdminTwoFAChange => "admin_2fa_change",
+            Self::AdminLock => "admin_lock",
+            Self::AdminUnlock => "admin_unlock",
+            Self::AdminSessionRevocation => "admin_session_revocation",
+            Self::SigningKeyRotation => "signing_key_rotation",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "success" => Ok(Self::Success),
+            "failure" => Ok(Self::Failure),
+            _ => Err(eyre!("{} is not a valid audit outcome.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+// Who made an audited request: `anonymous`, `admin_api_key` or `user:<id>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum AuditActor {
+    Anonymous,
+    AdminApiKey,
+    User(UserId),
+}
+
+impl AuditActor {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "anonymous" => Ok(Self::Anonymous),
+            "admin_api_key" => Ok(Self::AdminApiKey),
+            _ => match s.strip_prefix("user:") {
+                Some(id) => Ok(Self::User(UserId::parse(id)?)),
+                None => Err(eyre!("{} is not a valid audit actor.", s)),
+            },
+        }
+    }
+}
+
+impl From<AuditActor> for String {
+    fn from(actor: AuditActor) -> Self {
+        match actor {
+            AuditActor::Anonymous => "anonymous".to_owned(),
+            AuditActor::AdminApiKey => "admin_api_key".to_owned(),
+            AuditActor::User(id) => format!("user:{}", id),
+        }
+    }
+}
+
+impl TryFrom<String> for AuditActor {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::parse(&s)
+    }
+}
+
+// Something that happened, as recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub kind: AuditEventKind,
+    pub outcome: AuditOutcome,
+    // Why the request failed, e.g. `Incorrect credentials`.
+    pub reason: Option<String>,
+    pub actor: AuditActor,
+    // The account the event is about, when there is one.
+    #[serde(rename = "userId")]
+    pub user_id: Option<UserId>,
+    pub ip: Option<IpAddr>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+    // Milliseconds since the epoch.
+    pub timestamp: i64,
+}
+
+// An event in its place in the log. Each entry carries the hash of the one before,
+// so that removing or changing an entry breaks the chain after it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub sequence: i64,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    #[serde(rename = "previousHash")]
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    // Chain the event after the entry `sequence - 1`, whose hash is `previous_hash`.
+    pub fn new(sequence: i64, previous_hash: String, event: AuditEvent) -> Self {
+        let hash = compute_hash(sequence, &previous_hash, &event);
+        Self {
+            sequence,
+            event,
+            previous_hash,
+            hash,
+        }
+    }
+
+    // Whether the hash of the entry matches its content.
+    pub fn is_intact(&self) -> bool {
+        compute_hash(self.sequence, &self.previous_hash, &self.event) == self.hash
+    }
+}
+
+// Hex SHA-256 over the position, the previous hash and the event.
+fn compute_hash(sequence: i64, previous_hash: &str, event: &AuditEvent) -> String {
+    let content =
+        serde_json::to_vec(&(sequence, previous_hash, event)).expect("events serialize to JSON");
+    let digest = ring::digest::digest(&ring::digest::SHA256, &content);
+    digest
+        .as_ref()
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+// Check a whole log, oldest entry first. On failure, returns the sequence number
+// of the first entry that was altered, or that follows removed entries.
+// Removing the latest entries leaves a valid chain: that only shows against the
+// hash of the last entry kept somewhere else, such as another system's logs.
+pub fn verify_chain(entries: &[AuditEntry]) -> Result<(), i64> {
+    let mut expected = (1, GENESIS_HASH);
+    for entry in entries {
+        if (entry.sequence, entry.previous_hash.as_str()) != expected || !entry.is_intact() {
+            return Err(entry.sequence);
+        }
+        expected = (entry.sequence + 1, entry.hash.as_str());
+    }
+    Ok(())
+}
+
+// Which entries to read from the log, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    pub user_id: Option<UserId>,
+    // Bounds on the timestamp, both included.
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.user_id.is_none_or(|id| event.user_id == Some(id))
+            && self.from.is_none_or(|from| event.timestamp >= from)
+            && self.to.is_none_or(|to| event.timestamp <= to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_event(timestamp: i64) -> AuditEvent {
+        AuditEvent {
+            kind: AuditEventKind::Login,
+            outcome: AuditOutcome::Failure,
+            reason: Some("Incorrect credentials".to_owned()),
+            actor: AuditActor::Anonymous,
+            user_id: Some(UserId::default()),
+            ip: Some("127.0.0.1".parse().unwrap()),
+            user_agent: None,
+            request_id: None,
+            timestamp,
+        }
+    }
+
+    fn get_chain(length: i64) -> Vec<AuditEntry> {
+        let mut entries: Vec<AuditEntry> = Vec::new();
+        for sequence in 1..=length {
+            let previous_hash = entries
+                .last()
+                .map_or(GENESIS_HASH.to_owned(), |entry| entry.hash.clone());
+            entries.push(AuditEntry::new(sequence, previous_hash, get_event(sequence)));
+        }
+        entries
+    }
+
+    #[test]
+    fn intact_chain_is_valid() {
+        assert_eq!(verify_chain(&get_chain(3)), Ok(()));
+        assert_eq!(verify_chain(&[]), Ok(()));
+    }
+
+    #[test]
+    fn removed_entry_breaks_the_chain() {
+        let mut entries = get_chain(3);
+        entries.remove(1);
+        assert_eq!(verify_chain(&entries), Err(3));
+
+        let mut entries = get_chain(3);
+        entries.remove(0);
+        assert_eq!(verify_chain(&entries), Err(2));
+    }
+
+    #[test]
+    fn altered_entry_breaks_the_chain() {
+        let mut entries = get_chain(3);
+        entries[1].event.outcome = AuditOutcome::Success;
+        assert_eq!(verify_chain(&entries), Err(2));
+    }
+
+    #[test]
+    fn entry_round_trips_through_json() {
+        let entry = get_chain(1).remove(0);
+        let json = serde_json::to_string(&entry).unwrap();
+        let parsed: AuditEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, entry);
+        assert!(parsed.is_intact());
+    }
+
+    #[test]
+    fn actor_round_trips_through_str() {
+        for actor in [
+            AuditActor::Anonymous,
+            AuditActor::AdminApiKey,
+            AuditActor::User(UserId::default()),
+        ] {
+            assert_eq!(AuditActor::parse(&String::from(actor)).unwrap(), actor);
+        }
+        assert!(AuditActor::parse("user:nobody").is_err());
+    }
+
+    #[test]
+    fn kind_round_trips_through_str() {
+        for kind in [
+            AuditEventKind::Signup,
+            AuditEventKind::Login,
+            AuditEventKind::TwoFAChallenge,
+            AuditEventKind::TwoFAVerification,
+            AuditEventKind::Logout,
+            AuditEventKind::TokenVerification,
+            AuditEventKind::AdminUserCreation,
+            AuditEventKind::AdminUserDeletion,
+            AuditEventKind::AdminPasswordReset,
+            AuditEventKind::AdminTwoFAChange,
+            AuditEventKind::AdminLock,
+            AuditEventKind::AdminUnlock,
+            AuditEventKind::AdminSessionRevocation,
+            AuditEventKind::SigningKeyRotation,
+        ] {
+            assert_eq!(AuditEventKind::parse(kind.as_str()).unwrap(), kind);
+            let json = serde_json::to_string(&kind).unwrap();
+            assert_eq!(json, format!("\"{}\"", kind.as_str()));
+        }
+    }
+}
@@ -1,22 +1,109 @@
 use color_eyre::eyre::{Context, Report, Result, eyre};
-use rand::Rng;
+use rand::{Rng, distributions::Alphanumeric};
 use secrecy::{ExposeSecret, Secret};
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::{User, email::Email, password::Password};
+use super::{
+    AuditEntry, AuditEvent, AuditQuery, Failures, Grants, RateLimitKey, Role, Session, TotpSecret,
+    TwoFAMethod, User, UserExport, UserId, UserPage, UserQuery, email::Email, password::Password,
+};
 
+// Users are found by email when they log in, and by id everywhere else.
 #[async_trait::async_trait]
 pub trait UserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: Email) -> Result<User, UserStoreError>;
+    async fn get_user_by_id(&self, id: UserId) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: Email, password: Password) -> Result<(), UserStoreError>;
+    async fn delete_user(&self, id: UserId) -> Result<(), UserStoreError>;
+    async fn export_user(&self, id: UserId) -> Result<UserExport, UserStoreError>;
+    async fn update_password(&self, id: UserId, password: Password) -> Result<(), UserStoreError>;
+    // Move the user to `new_email`, which must not belong to another user.
+    async fn update_email(&self, id: UserId, new_email: Email) -> Result<(), UserStoreError>;
+    async fn set_two_fa_method(
+        &self,
+        id: UserId,
+        method: TwoFAMethod,
+    ) -> Result<(), UserStoreError>;
+    async fn mark_email_verified(&self, id: UserId) -> Result<(), UserStoreError>;
+    async fn set_totp_secret(&self, id: UserId, secret: TotpSecret) -> Result<(), UserStoreError>;
+    async fn get_totp_secret(&self, id: UserId) -> Result<TotpSecret, UserStoreError>;
+    // Record `step` as the time step of the last TOTP code the user used, unless that
+    // one was as late. Returns whether it was recorded: a code is only accepted once.
+    async fn use_totp_step(&self, id: UserId, step: u64) -> Result<bool, UserStoreError>;
+    // The roles of the user and their permissions. Unknown users have none.
+    async fn get_grants(&self, id: UserId) -> Result<Grants, UserStoreError>;
+    // Granting a role the user already has, or removing one they lack, does nothing.
+    async fn add_role(&self, id: UserId, role: Role) -> Result<(), UserStoreError>;
+    async fn remove_role(&self, id: UserId, role: Role) -> Result<(), UserStoreError>;
+    // Search matches any part of the email, ignoring case.
+    async fn list_users(&self, query: UserQuery) -> Result<UserPage, UserStoreError>;
+    async fn set_locked(&self, id: UserId, locked: bool) -> Result<(), UserStoreError>;
+    // Check that the backend can be reached. Backends in memory always can.
+    async fn ping(&self) -> Result<(), UserStoreError> {
+        Ok(())
+    }
+    // Close the connections to the backend, once no request needs them.
+    async fn close(&self) {}
 }
 
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError>;
+    async fn add_token(&self, token: Secret<String>) -> Result<(), BannedTokenStoreError>;
     async fn has_token(&self, token: Secret<String>) -> Result<bool, BannedTokenStoreError>;
+    // Ban every token of a user issued at or before `timestamp` (milliseconds since the epoch).
+    async fn ban_tokens_issued_before(
+        &self,
+        user_id: &UserId,
+        timestamp: i64,
+    ) -> Result<(), BannedTokenStoreError>;
+    async fn get_tokens_banned_before(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<i64>, BannedTokenStoreError>;
+    async fn ping(&self) -> Result<(), BannedTokenStoreError> {
+        Ok(())
+    }
+    async fn close(&self) {}
+}
+
+#[async_trait::async_trait]
+pub trait RateLimitStore {
+    // Count an attempt at `now` (seconds since the epoch) as failed until it is
+    // released, and return the failures before it. Both happen in one step, so
+    // that concurrent attempts each see the ones before them.
+    async fn reserve_attempt(
+        &self,
+        key: &RateLimitKey,
+        now: i64,
+    ) -> Result<Option<Failures>, RateLimitStoreError>;
+    // Stop counting an attempt that did not fail. `previous` are the failures that
+    // `reserve_attempt` returned for it: unless another attempt was counted since,
+    // they are restored, so that the attempt does not delay the next one either.
+    async fn release_attempt(
+        &self,
+        key: &RateLimitKey,
+        previous: Option<Failures>,
+    ) -> Result<(), RateLimitStoreError>;
+    async fn clear_failures(&self, key: &RateLimitKey) -> Result<(), RateLimitStoreError>;
+    async fn ping(&self) -> Result<(), RateLimitStoreError> {
+        Ok(())
+    }
+    async fn close(&self) {}
+}
+
+// Append-only log of authentication events, hash-chained so that tampering shows.
+#[async_trait::async_trait]
+pub trait AuditSink {
+    // Append the event after the last entry, and return its entry.
+    async fn record(&self, event: AuditEvent) -> Result<AuditEntry, AuditSinkError>;
+    // Entries matching the query, oldest first.
+    async fn query(&self, query: AuditQuery) -> Result<Vec<AuditEntry>, AuditSinkError>;
+    async fn ping(&self) -> Result<(), AuditSinkError> {
+        Ok(())
+    }
+    async fn close(&self) {}
 }
 
 #[derive(Debug, Error)]
@@ -30,6 +117,12 @@ pub enum UserStoreError {
     #[error("Invalid credentials")]
     InvalidCredentials,
 
+    #[error("TOTP secret not found")]
+    TotpSecretNotFound,
+
+    #[error("Role not found")]
+    RoleNotFound,
+
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -41,11 +134,19 @@ impl PartialEq for UserStoreError {
             (Self::UserAlreadyExists, Self::UserAlreadyExists)
                 | (Self::UserNotFound, Self::UserNotFound)
                 | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::TotpSecretNotFound, Self::TotpSecretNotFound)
+                | (Self::RoleNotFound, Self::RoleNotFound)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
 }
 
+#[derive(Debug, Error)]
+pub enum AuditSinkError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
 #[derive(Debug, Error)]
 pub enum BannedTokenStoreError {
     #[error("Unexpected error")]
@@ -54,17 +155,22 @@ pub enum BannedTokenStoreError {
 
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
+    // Logins checked with an authenticator app have no code to remember.
     async fn add_code(
-        &mut self,
+        &self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code: Option<TwoFACode>,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(&self, email: &Email) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    ) -> Result<(LoginAttemptId, Option<TwoFACode>), TwoFACodeStoreError>;
+    async fn ping(&self) -> Result<(), TwoFACodeStoreError> {
+        Ok(())
+    }
+    async fn close(&self) {}
 }
 
 #[derive(Debug, Error)]
@@ -118,12 +224,9 @@ pub struct TwoFACode(Secret<String>);
 
 impl TwoFACode {
     pub fn parse(code: Secret<String>) -> Result<Self> {
-        let code_as_u32 = code
-            .expose_secret()
-            .parse::<u32>()
-            .wrap_err("Invalid 2FA code")?;
-
-        if (100_000..=999_999).contains(&code_as_u32) {
+        // Emailed codes never start with a zero, but authenticator (TOTP) codes can.
+        let digits = code.expose_secret();
+        if digits.len() == 6 && digits.chars().all(|c| c.is_ascii_digit()) {
             Ok(Self(code))
         } else {
             Err(eyre!("Invalid 2FA code"))
@@ -149,3 +252,206 @@ impl PartialEq for TwoFACode {
         self.0.expose_secret() == other.0.expose_secret()
     }
 }
+
+#[async_trait::async_trait]
+pub trait RefreshTokenStore {
+    async fn add_token(
+        &self,
+        token: RefreshToken,
+        entry: RefreshTokenEntry,
+    ) -> Result<(), RefreshTokenStoreError>;
+    async fn get_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenEntry, RefreshTokenStoreError>;
+    // Mark the token as used, and return its entry as it was before. Both happen in one
+    // step, so that of concurrent requests presenting a token, only one finds it unused.
+    async fn use_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenEntry, RefreshTokenStoreError>;
+    async fn revoke_family(
+        &self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<(), RefreshTokenStoreError>;
+    async fn is_family_revoked(
+        &self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<bool, RefreshTokenStoreError>;
+    // Revoke every family of the user, logging out all of their sessions.
+    async fn revoke_all_families(&self, user_id: &UserId) -> Result<(), RefreshTokenStoreError>;
+    async fn ping(&self) -> Result<(), RefreshTokenStoreError> {
+        Ok(())
+    }
+    async fn close(&self) {}
+}
+
+#[derive(Debug, Error)]
+pub enum RefreshTokenStoreError {
+    #[error("Refresh token not found")]
+    TokenNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+#[async_trait::async_trait]
+pub trait SessionStore {
+    async fn add_session(&self, session: Session) -> Result<(), SessionStoreError>;
+    async fn get_session(&self, id: &RefreshTokenFamilyId) -> Result<Session, SessionStoreError>;
+    async fn get_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError>;
+    // Record that the session was used at `now` (seconds since the epoch).
+    async fn touch_session(
+        &self,
+        id: &RefreshTokenFamilyId,
+        now: i64,
+    ) -> Result<(), SessionStoreError>;
+    async fn remove_session(&self, id: &RefreshTokenFamilyId) -> Result<(), SessionStoreError>;
+    async fn remove_user_sessions(&self, user_id: &UserId) -> Result<(), SessionStoreError>;
+    async fn ping(&self) -> Result<(), SessionStoreError> {
+        Ok(())
+    }
+    async fn close(&self) {}
+}
+
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for SessionStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SessionNotFound, Self::SessionNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RateLimitStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RateLimitStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+impl PartialEq for RefreshTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// What the server knows about an issued refresh token.
+// Every token rotated from the same login shares a family, so that a reused
+// token can revoke all of its descendants at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefreshTokenEntry {
+    pub user_id: UserId,
+    pub family_id: RefreshTokenFamilyId,
+    pub used: bool,
+}
+
+impl RefreshTokenEntry {
+    pub fn new(user_id: UserId, family_id: RefreshTokenFamilyId) -> Self {
+        Self {
+            user_id,
+            family_id,
+            used: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RefreshToken(Secret<String>);
+
+impl RefreshToken {
+    pub fn parse(token: Secret<String>) -> Result<Self> {
+        let value = token.expose_secret();
+        if value.len() == REFRESH_TOKEN_LENGTH && value.chars().all(|c| c.is_ascii_alphanumeric()) {
+            Ok(Self(token))
+        } else {
+            Err(eyre!("Invalid refresh token"))
+        }
+    }
+}
+
+const REFRESH_TOKEN_LENGTH: usize = 64;
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(REFRESH_TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+        Self(Secret::new(token))
+    }
+}
+
+impl AsRef<Secret<String>> for RefreshToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+impl PartialEq for RefreshToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RefreshTokenFamilyId(String);
+
+impl RefreshTokenFamilyId {
+    pub fn parse(id: String) -> Result<Self> {
+        let uuid = Uuid::parse_str(&id).wrap_err("Invalid refresh token family id")?;
+        Ok(Self(uuid.to_string()))
+    }
+}
+
+impl Default for RefreshTokenFamilyId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for RefreshTokenFamilyId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_refresh_token_is_parsed_successfully() {
+        let token = RefreshToken::default();
+        assert!(RefreshToken::parse(token.as_ref().clone()).is_ok());
+        assert_ne!(token, RefreshToken::default());
+    }
+
+    #[test]
+    fn malformed_refresh_token_is_rejected() {
+        assert!(RefreshToken::parse(Secret::new("".to_owned())).is_err());
+        assert!(RefreshToken::parse(Secret::new("a".repeat(63))).is_err());
+        assert!(RefreshToken::parse(Secret::new("!".repeat(64))).is_err());
+    }
+}
@@ -1,18 +1,61 @@
 use color_eyre::eyre::{Result, eyre};
 use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
 use std::hash::Hash;
 use validator::validate_email;
 
+use crate::utils::constants::DEFAULT_EMAIL_LOCAL_PART_POLICY;
+
+// Canonical email address: the same mailbox always parses to the same `Email`, so
+// that it identifies a single account however it was typed.
 #[derive(Debug, Clone)]
 pub struct Email(Secret<String>);
 
+// How the part of an address before the `@` is canonicalized. Only the receiving
+// server knows whether it is case-sensitive, though virtually none are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalPartPolicy {
+    // `Alice@example.com` and `alice@example.com` are the same account.
+    Lowercase,
+    // The local part is kept as typed, and must be typed the same way to log in.
+    Preserve,
+}
+
+impl LocalPartPolicy {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "lowercase" => Ok(Self::Lowercase),
+            "preserve" => Ok(Self::Preserve),
+            _ => Err(eyre!("Unknown local part policy: {}", s)),
+        }
+    }
+}
+
 impl Email {
+    // Parse with the default policy. Addresses typed by users are parsed with the
+    // policy of the settings instead.
     pub fn parse(s: Secret<String>) -> Result<Email> {
-        if validate_email(s.expose_secret()) {
-            Ok(Self(s))
-        } else {
-            Err(eyre!("{} is not a valid email.", s.expose_secret()))
+        Self::parse_with_policy(s, DEFAULT_EMAIL_LOCAL_PART_POLICY)
+    }
+
+    // Trim the address, and convert its domain to lowercase ASCII, with
+    // internationalized domain names in punycode.
+    pub fn parse_with_policy(s: Secret<String>, policy: LocalPartPolicy) -> Result<Email> {
+        let email = s.expose_secret().trim();
+        if !validate_email(email) {
+            return Err(eyre!("{} is not a valid email.", s.expose_secret()));
         }
+        let (local_part, domain) = email
+            .rsplit_once('@')
+            .ok_or_else(|| eyre!("{} is not a valid email.", s.expose_secret()))?;
+        let domain = idna::domain_to_ascii(domain)
+            .map_err(|_| eyre!("{} is not a valid email.", s.expose_secret()))?;
+        let local_part = match policy {
+            LocalPartPolicy::Lowercase => local_part.to_lowercase(),
+            LocalPartPolicy::Preserve => local_part.to_owned(),
+        };
+        Ok(Self(Secret::new(format!("{}@{}", local_part, domain))))
     }
 }
 
@@ -38,11 +81,11 @@ impl Eq for Email {}
 
 #[cfg(test)]
 mod tests {
-    use super::Email;
+    use super::{Email, LocalPartPolicy};
 
     use fake::Fake;
     use fake::faker::internet::en::SafeEmail;
-    use secrecy::Secret; // New!
+    use secrecy::{ExposeSecret, Secret}; // New!
 
     #[test]
     fn empty_string_is_rejected() {
@@ -60,6 +103,46 @@ mod tests {
         assert!(Email::parse(email).is_err());
     }
 
+    #[test]
+    fn email_is_trimmed_and_lowercased() {
+        let email = Email::parse(Secret::new("  Alice@Example.COM \n".to_string())).unwrap();
+        assert_eq!(email.as_ref().expose_secret(), "alice@example.com");
+        assert_eq!(
+            email,
+            Email::parse(Secret::new("alice@example.com".to_string())).unwrap()
+        );
+    }
+
+    #[test]
+    fn local_part_is_kept_with_preserve_policy() {
+        let email = Secret::new("Alice@Example.com".to_string());
+        let email = Email::parse_with_policy(email, LocalPartPolicy::Preserve).unwrap();
+        assert_eq!(email.as_ref().expose_secret(), "Alice@example.com");
+    }
+
+    #[test]
+    fn internationalized_domain_is_converted_to_punycode() {
+        let email = Email::parse(Secret::new("user@Bücher.example".to_string())).unwrap();
+        assert_eq!(email.as_ref().expose_secret(), "user@xn--bcher-kva.example");
+        assert_eq!(
+            email,
+            Email::parse(Secret::new("user@xn--bcher-kva.example".to_string())).unwrap()
+        );
+    }
+
+    #[test]
+    fn local_part_policy_is_parsed() {
+        assert_eq!(
+            LocalPartPolicy::parse("lowercase").unwrap(),
+            LocalPartPolicy::Lowercase
+        );
+        assert_eq!(
+            LocalPartPolicy::parse("preserve").unwrap(),
+            LocalPartPolicy::Preserve
+        );
+        assert!(LocalPartPolicy::parse("Lowercase").is_err());
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
 
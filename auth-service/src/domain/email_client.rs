@@ -5,4 +5,10 @@ use color_eyre::eyre::Result;
 #[async_trait::async_trait]
 pub trait EmailClient {
     async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()>;
+    // Check that the email provider can be reached and accepts our credentials.
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+    // Close the connections to the provider, once no email is being sent.
+    async fn close(&self) {}
 }
@@ -9,12 +9,34 @@ pub enum AuthAPIError {
     InvalidCredentials,
     #[error("Incorrect credentials")]
     IncorrectCredentials,
+    #[error("Email not verified")]
+    EmailNotVerified,
+    #[error("Account locked")]
+    AccountLocked,
+    #[error("User not found")]
+    UserNotFound,
+    // A request that is well-formed, but asks for something that cannot be done.
+    #[error("Invalid input: {0}")]
+    InvalidInput(&'static str),
+
+    #[error("Too many requests")]
+    TooManyRequests { retry_after: u64 },
+
+    // 2FA
+    #[error("TOTP already enabled")]
+    TotpAlreadyEnabled,
 
     // JWT
     #[error("Missing token")]
     MissingToken,
     #[error("Invalid token")]
     InvalidToken,
+    #[error("Missing permission")]
+    MissingPermission,
+
+    // Sessions
+    #[error("Session not found")]
+    SessionNotFound,
 
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
@@ -1,13 +1,25 @@
+pub mod audit;
 pub mod data_stores;
 pub mod email;
 pub mod email_client;
 pub mod error;
 pub mod password;
+pub mod rate_limit;
+pub mod role;
+pub mod session;
+pub mod totp;
 pub mod user;
+pub mod user_id;
 
+pub use audit::*;
 pub use data_stores::*;
 pub use email::*;
 pub use email_client::*;
 pub use error::*;
 pub use password::*;
+pub use rate_limit::*;
+pub use role::*;
+pub use session::*;
+pub use totp::*;
 pub use user::*;
+pub use user_id::*;
@@ -0,0 +1,129 @@
+use secrecy::ExposeSecret;
+use std::net::IpAddr;
+
+use super::{Email, LoginAttemptId};
+
+// How long failures are remembered after the last one. It is longer than any
+// delay, so that a lockout is not forgotten before it ends.
+pub const FAILURE_TTL_SECONDS: i64 = 3600; // 1 hour
+
+// Wrong 2FA codes accepted for a login attempt before it is dropped.
+pub const MAX_2FA_GUESSES: u32 = 5;
+
+// Each user gets a few tries for free, then has to wait longer after every failure.
+pub const EMAIL_RATE_LIMIT: RateLimitPolicy = RateLimitPolicy {
+    free_attempts: 5,
+    max_delay_seconds: 900, // 15 minutes
+};
+
+// Addresses may be shared by many users, e.g. behind a NAT.
+pub const IP_RATE_LIMIT: RateLimitPolicy = RateLimitPolicy {
+    free_attempts: 50,
+    max_delay_seconds: 900,
+};
+
+// What failed attempts are counted against.
+#[derive(Debug, Clone)]
+pub enum RateLimitKey {
+    Email(Email),
+    Ip(IpAddr),
+    LoginAttempt(LoginAttemptId),
+}
+
+impl RateLimitKey {
+    // Unique name of the key, e.g. in Redis.
+    pub fn name(&self) -> String {
+        match self {
+            Self::Email(email) => format!("email:{}", email.as_ref().expose_secret()),
+            Self::Ip(ip) => format!("ip:{}", ip),
+            Self::LoginAttempt(id) => format!("login_attempt:{}", id.as_ref().expose_secret()),
+        }
+    }
+}
+
+// Failed attempts since the last successful one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failures {
+    pub count: u32,
+    // Seconds since the epoch.
+    pub last_failure_at: i64,
+}
+
+impl Failures {
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.last_failure_at + FAILURE_TTL_SECONDS
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    pub free_attempts: u32,
+    // Longest delay between two attempts: the lockout once the delay stops doubling.
+    pub max_delay_seconds: i64,
+}
+
+impl RateLimitPolicy {
+    // Seconds to wait before the next attempt, if it is not allowed yet. The delay is
+    // one second after the last free attempt, and doubles with every failure after it.
+    pub fn retry_after(&self, failures: &Failures, now: i64) -> Option<u64> {
+        if failures.count < self.free_attempts || failures.is_expired(now) {
+            return None;
+        }
+        let doublings = (failures.count - self.free_attempts).min(32);
+        let delay = (1i64 << doublings).min(self.max_delay_seconds);
+        let wait = failures.last_failure_at + delay - now;
+        (wait > 0).then_some(wait as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RateLimitPolicy = RateLimitPolicy {
+        free_attempts: 3,
+        max_delay_seconds: 60,
+    };
+
+    fn failures(count: u32) -> Failures {
+        Failures {
+            count,
+            last_failure_at: 1_000,
+        }
+    }
+
+    #[test]
+    fn free_attempts_are_not_delayed() {
+        assert_eq!(POLICY.retry_after(&failures(0), 1_000), None);
+        assert_eq!(POLICY.retry_after(&failures(2), 1_000), None);
+    }
+
+    #[test]
+    fn delay_doubles_after_free_attempts() {
+        assert_eq!(POLICY.retry_after(&failures(3), 1_000), Some(1));
+        assert_eq!(POLICY.retry_after(&failures(4), 1_000), Some(2));
+        assert_eq!(POLICY.retry_after(&failures(6), 1_000), Some(8));
+        // The wait shrinks as time passes.
+        assert_eq!(POLICY.retry_after(&failures(6), 1_005), Some(3));
+        assert_eq!(POLICY.retry_after(&failures(6), 1_008), None);
+    }
+
+    #[test]
+    fn delay_is_capped_at_lockout() {
+        assert_eq!(POLICY.retry_after(&failures(10), 1_000), Some(60));
+        assert_eq!(POLICY.retry_after(&failures(1_000), 1_000), Some(60));
+    }
+
+    #[test]
+    fn expired_failures_are_forgotten() {
+        let now = 1_000 + FAILURE_TTL_SECONDS;
+        assert!(failures(10).is_expired(now));
+        assert_eq!(POLICY.retry_after(&failures(10), now), None);
+    }
+
+    #[test]
+    fn keys_are_named_by_kind() {
+        let ip = RateLimitKey::Ip("127.0.0.1".parse().unwrap());
+        assert_eq!(ip.name(), "ip:127.0.0.1");
+    }
+}
@@ -0,0 +1,96 @@
+use color_eyre::eyre::{Result, eyre};
+
+// Name of the role seeded by the migrations, which may manage other users.
+pub const ADMIN_ROLE: &str = "admin";
+
+// Permissions of the admin role.
+pub const USERS_READ_PERMISSION: &str = "users:read";
+pub const USERS_WRITE_PERMISSION: &str = "users:write";
+pub const AUDIT_READ_PERMISSION: &str = "audit:read";
+
+// A named bundle of permissions, granted to users.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Role(String);
+
+impl Role {
+    pub fn parse(name: &str) -> Result<Self> {
+        if !name.is_empty() && name.chars().all(is_name_char) {
+            Ok(Self(name.to_owned()))
+        } else {
+            Err(eyre!("{} is not a valid role name.", name))
+        }
+    }
+}
+
+impl AsRef<str> for Role {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Something a user may do, named `resource:action`, e.g. `users:write`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Permission(String);
+
+impl Permission {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.split_once(':') {
+            Some((resource, action))
+                if !resource.is_empty()
+                    && !action.is_empty()
+                    && resource.chars().all(is_name_char)
+                    && action.chars().all(is_name_char) =>
+            {
+                Ok(Self(name.to_owned()))
+            }
+            _ => Err(eyre!("{} is not a valid permission.", name)),
+        }
+    }
+}
+
+impl AsRef<str> for Permission {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'
+}
+
+// The roles of a user, and every permission they grant, sorted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Grants {
+    pub roles: Vec<Role>,
+    pub permissions: Vec<Permission>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+
+    #[test]
+    fn valid_names_are_accepted() {
+        assert!(Role::parse("admin").is_ok());
+        assert!(Role::parse("support-team_2").is_ok());
+        assert!(Permission::parse("users:read").is_ok());
+        assert!(Permission::parse("signing-keys:rotate").is_ok());
+    }
+
+    #[test]
+    fn malformed_names_are_rejected() {
+        for name in ["", "Admin", "ad min", "admin:all"] {
+            assert!(Role::parse(name).is_err());
+        }
+        for name in [
+            "",
+            "users",
+            "users:",
+            ":read",
+            "users:read:all",
+            "Users:read",
+        ] {
+            assert!(Permission::parse(name).is_err());
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::net::IpAddr;
+
+use super::{RefreshTokenFamilyId, UserId};
+
+// A logged in device. Every login starts a refresh token family, and the session
+// shares its id, so the JWTs and refresh tokens of one login are revoked together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: RefreshTokenFamilyId,
+    pub user_id: UserId,
+    pub client: ClientInfo,
+    // Seconds since the epoch.
+    pub created_at: i64,
+    pub last_seen_at: i64,
+}
+
+impl Session {
+    pub fn new(user_id: UserId, client: ClientInfo, now: i64) -> Self {
+        Self {
+            id: RefreshTokenFamilyId::default(),
+            user_id,
+            client,
+            created_at: now,
+            last_seen_at: now,
+        }
+    }
+}
+
+// Where a session was started from, as shown to its user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    pub ip: IpAddr,
+    pub user_agent: Option<String>,
+}
@@ -0,0 +1,138 @@
+use color_eyre::eyre::{Context, Result, eyre};
+use secrecy::{ExposeSecret, Secret};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret as RawSecret, TOTP};
+
+use super::{Email, TwoFACode};
+
+// RFC 6238 defaults, understood by every authenticator app.
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECONDS: u64 = 30;
+
+// Base32-encoded shared secret of an authenticator app (TOTP).
+#[derive(Debug, Clone)]
+pub struct TotpSecret(Secret<String>);
+
+impl TotpSecret {
+    pub fn parse(s: Secret<String>) -> Result<Self> {
+        // Building a TOTP instance checks both the encoding and the secret length.
+        let secret = Self(s);
+        secret.totp(0, None, String::new())?;
+        Ok(secret)
+    }
+
+    // Provisioning URI, usually rendered as a QR code for authenticator apps.
+    pub fn otpauth_uri(&self, issuer: &str, account: &Email) -> Result<String> {
+        let totp = self.totp(
+            0,
+            Some(issuer.to_owned()),
+            account.as_ref().expose_secret().to_owned(),
+        )?;
+        Ok(totp.get_url())
+    }
+
+    // Check a code against the current time step, accepting `skew` steps before and after it.
+    // Returns the time step the code belongs to, so that it can only be used once.
+    pub fn verify(&self, code: &TwoFACode, skew: u8) -> Result<Option<u64>> {
+        let totp = self.totp(0, None, String::new())?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .wrap_err("system time is before the unix epoch")?;
+        let current = now.as_secs() / TOTP_STEP_SECONDS;
+        let steps = current.saturating_sub(skew.into())..=current + u64::from(skew);
+
+        Ok(steps
+            .into_iter()
+            .find(|step| totp.check(code.as_ref().expose_secret(), step * TOTP_STEP_SECONDS)))
+    }
+
+    fn totp(&self, skew: u8, issuer: Option<String>, account_name: String) -> Result<TOTP> {
+        let bytes = RawSecret::Encoded(self.0.expose_secret().to_owned())
+            .to_bytes()
+            .map_err(|e| eyre!("Invalid TOTP secret: {}", e))?;
+        TOTP::new(
+            Algorithm::SHA1,
+            TOTP_DIGITS,
+            skew,
+            TOTP_STEP_SECONDS,
+            bytes,
+            issuer,
+            account_name,
+        )
+        .wrap_err("Invalid TOTP secret")
+    }
+}
+
+impl Default for TotpSecret {
+    fn default() -> Self {
+        let secret = RawSecret::generate_secret().to_encoded().to_string();
+        Self(Secret::new(secret))
+    }
+}
+
+impl AsRef<Secret<String>> for TotpSecret {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+impl PartialEq for TotpSecret {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current_code(secret: &TotpSecret) -> TwoFACode {
+        let code = secret
+            .totp(0, None, String::new())
+            .unwrap()
+            .generate_current()
+            .unwrap();
+        TwoFACode::parse(Secret::new(code)).unwrap()
+    }
+
+    #[test]
+    fn generated_secret_is_parsed_successfully() {
+        let secret = TotpSecret::default();
+        assert!(TotpSecret::parse(secret.as_ref().clone()).is_ok());
+    }
+
+    #[test]
+    fn short_or_malformed_secret_is_rejected() {
+        assert!(TotpSecret::parse(Secret::new("".to_owned())).is_err());
+        assert!(TotpSecret::parse(Secret::new("JBSWY3DP".to_owned())).is_err());
+        assert!(TotpSecret::parse(Secret::new("not base32!".to_owned())).is_err());
+    }
+
+    #[test]
+    fn otpauth_uri_contains_issuer_and_secret() {
+        let secret = TotpSecret::default();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let uri = secret.otpauth_uri("Auth Service", &email).unwrap();
+
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains(&format!("secret={}", secret.as_ref().expose_secret())));
+        assert!(uri.contains("issuer=Auth%20Service"));
+    }
+
+    #[test]
+    fn current_code_is_accepted() {
+        let secret = TotpSecret::default();
+        let code = current_code(&secret);
+        let step = secret.verify(&code, 0).unwrap().expect("code rejected");
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        assert_eq!(step, now.as_secs() / TOTP_STEP_SECONDS);
+    }
+
+    #[test]
+    fn code_from_another_secret_is_rejected() {
+        let secret = TotpSecret::default();
+        let code = current_code(&TotpSecret::default());
+        assert_eq!(secret.verify(&code, 1).unwrap(), None);
+    }
+}
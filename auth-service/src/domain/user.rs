@@ -1,19 +1,103 @@
+use color_eyre::eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+
 use super::email::Email;
 use super::password::Password;
+use super::user_id::UserId;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct User {
+    pub id: UserId,
     pub email: Email,
     pub password: Password,
-    pub requires_2fa: bool,
+    pub two_fa_method: TwoFAMethod,
+    // Whether the user proved they own the email address.
+    pub verified: bool,
+    // Locked accounts cannot log in until an operator unlocks them.
+    pub locked: bool,
 }
 
 impl User {
-    pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+    pub fn new(email: Email, password: Password, two_fa_method: TwoFAMethod) -> Self {
         Self {
+            id: UserId::default(),
             email,
             password,
-            requires_2fa,
+            two_fa_method,
+            verified: false,
+            locked: false,
+        }
+    }
+}
+
+// Everything stored about a user, without the password hash or the TOTP secret.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserExport {
+    pub id: UserId,
+    pub email: Email,
+    pub two_fa_method: TwoFAMethod,
+    pub verified: bool,
+    pub has_totp_secret: bool,
+    pub locked: bool,
+}
+
+// Which users to list: those whose email contains `search`, in email order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserQuery {
+    pub search: Option<String>,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+// One page of users, and how many users match the query in all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserPage {
+    pub users: Vec<User>,
+    pub total: u64,
+}
+
+// Second factor a user has to provide after their password.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAMethod {
+    #[default]
+    None,
+    Email,
+    Totp,
+}
+
+impl TwoFAMethod {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "email" => Ok(Self::Email),
+            "totp" => Ok(Self::Totp),
+            _ => Err(eyre!("{} is not a valid 2FA method.", s)),
         }
     }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Email => "email",
+            Self::Totp => "totp",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoFAMethod;
+
+    #[test]
+    fn two_fa_method_round_trips_through_str() {
+        for method in [TwoFAMethod::None, TwoFAMethod::Email, TwoFAMethod::Totp] {
+            assert_eq!(TwoFAMethod::parse(method.as_str()).unwrap(), method);
+        }
+    }
+
+    #[test]
+    fn unknown_two_fa_method_is_rejected() {
+        assert!(TwoFAMethod::parse("sms").is_err());
+    }
 }
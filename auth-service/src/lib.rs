@@ -1,21 +1,27 @@
 use axum::{
-    Json, Router,
-    http::{Method, StatusCode},
+    Extension, Json, Router,
+    http::{HeaderValue, Method, StatusCode, header},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post},
+    extract::connect_info::{ConnectInfo, IntoMakeServiceWithConnectInfo},
+    middleware::{self, AddExtension},
     serve::Serve,
 };
 use redis::{Client, RedisResult};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, postgres::PgPoolOptions};
-use std::error::Error;
+use std::{error::Error, future::IntoFuture, net::SocketAddr, time::Duration};
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
 
 use app_state::AppState;
 use domain::AuthAPIError;
 
-use self::utils::tracing::{make_span_with_request_id, on_request, on_response};
+use self::utils::{
+    metrics::set_route_info,
+    shutdown::ShutdownHandle,
+    tracing::{make_span_with_request_id, on_request, on_response, set_request_id},
+};
 
 pub mod app_state;
 pub mod domain;
@@ -25,50 +31,186 @@ pub mod utils;
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
+    app_state: AppState,
+    shutdown: ShutdownHandle,
+    pre_stop_delay: Duration,
+    drain_timeout: Duration,
 }
 
 impl Application {
-    pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
-        let allowed_origins = [
-            "http://localhost:8000".parse()?,
-            "http://206.189.177.178:8000".parse()?, // TODO: use env. :)
-        ];
+    // Listen on the address of `app_state.settings`.
+    pub async fn build(app_state: AppState) -> Result<Self, Box<dyn Error>> {
+        let settings = app_state.settings.application.clone();
+        let allowed_origins = settings
+            .allowed_origins
+            .iter()
+            .map(|origin| origin.parse::<HeaderValue>())
+            .collect::<Result<Vec<_>, _>>()?;
         let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST])
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             // Allow cookies to be included in requests
             .allow_credentials(true)
             .allow_origin(allowed_origins);
 
+        let shutdown = ShutdownHandle::default();
+
         let router = Router::new()
             .nest_service("/", ServeDir::new("assets"))
+            .route("/health/live", get(routes::health_live))
+            .route("/health/ready", get(routes::health_ready))
+            .route("/metrics", get(routes::metrics))
             .route("/signup", post(routes::signup))
             .route("/login", post(routes::login))
             .route("/logout", post(routes::logout))
+            .route("/refresh", post(routes::refresh))
+            .route(
+                "/password-reset/request",
+                post(routes::request_password_reset),
+            )
+            .route(
+                "/password-reset/confirm",
+                post(routes::confirm_password_reset),
+            )
+            .route("/account", delete(routes::delete_account))
+            .route("/account/export", get(routes::export_account))
+            .route("/account/password", post(routes::change_password))
+            .route("/account/email", post(routes::change_email))
+            .route("/account/email/confirm", post(routes::confirm_email_change))
+            .route("/verify-email", post(routes::verify_email))
+            .route("/resend-verification", post(routes::resend_verification))
+            .route(
+                "/sessions",
+                get(routes::list_sessions).delete(routes::revoke_all_sessions),
+            )
+            .route("/sessions/:id", delete(routes::revoke_session))
             .route("/verify-2fa", post(routes::verify_2fa))
             .route("/verify-token", post(routes::verify_token))
-            .with_state(app_state)
+            .route("/totp/enroll", post(routes::enroll_totp))
+            .route("/totp/confirm", post(routes::confirm_totp))
+            .route("/.well-known/jwks.json", get(routes::jwks))
+            .route(
+                "/admin/signing-keys/rotate",
+                post(routes::rotate_signing_keys),
+            )
+            .route(
+                "/admin/users",
+                get(routes::admin_list_users).post(routes::admin_create_user),
+            )
+            .route(
+                "/admin/users/:id",
+                get(routes::admin_get_user).delete(routes::admin_delete_user),
+            )
+            .route(
+                "/admin/users/:id/password-reset",
+                post(routes::admin_force_password_reset),
+            )
+            .route("/admin/users/:id/2fa", post(routes::admin_set_two_fa_method))
+            .route("/admin/users/:id/lock", post(routes::admin_lock_user))
+            .route("/admin/users/:id/unlock", post(routes::admin_unlock_user))
+            .route(
+                "/admin/users/:id/sessions",
+                delete(routes::admin_revoke_user_sessions),
+            )
+            .route("/admin/audit", get(routes::admin_query_audit_log))
+            .route("/admin/audit/verify", get(routes::admin_verify_audit_log))
+            .with_state(app_state.clone())
+            // Readiness fails as soon as the server shuts down.
+            .layer(Extension(shutdown.clone()))
             .layer(cors)
+            .layer(middleware::from_fn(set_route_info))
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(make_span_with_request_id)
                     .on_request(on_request)
                     .on_response(on_response),
-            );
+            )
+            .layer(middleware::from_fn(set_request_id));
 
-        let listener = tokio::net::TcpListener::bind(address).await?;
+        let listener = tokio::net::TcpListener::bind(&settings.address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Handlers can tell clients apart by address, e.g. to throttle logins.
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
+
+        Ok(Application {
+            server,
+            address,
+            app_state,
+            shutdown,
+            pre_stop_delay: settings.pre_stop_delay(),
+            drain_timeout: settings.drain_timeout(),
+        })
+    }
 
-        Ok(Application { server, address })
+    // Stops the server once `run`, e.g. on SIGTERM or at the end of a test.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
     }
 
+    // Serve until shut down. Readiness fails right away, but requests are accepted for
+    // the pre-stop delay still. Then stop accepting connections, let the requests in
+    // flight and the background tasks finish for at most the drain timeout, and close
+    // the stores.
     pub async fn run(self) -> Result<(), std::io::Error> {
-        tracing::info!("listening on {}", &self.address);
-        self.server.await
+        let Application {
+            server,
+            address,
+            app_state,
+            shutdown,
+            pre_stop_delay,
+            drain_timeout,
+        } = self;
+        tracing::info!("listening on {}", &address);
+
+        let signal = shutdown.clone();
+        let stop_accepting = async move {
+            signal.wait().await;
+            tracing::info!(
+                "shutting down, accepting requests for {:?} more",
+                pre_stop_delay
+            );
+            tokio::time::sleep(pre_stop_delay).await;
+            tracing::info!(
+                "draining requests and background tasks for up to {:?}",
+                drain_timeout
+            );
+        };
+        let server = server.with_graceful_shutdown(stop_accepting).into_future();
+        let background_tasks = app_state.background_tasks.clone();
+        let finish = async {
+            let result = server.await;
+            // Emails of the last requests are sent before the stores close.
+            background_tasks.close();
+            background_tasks.wait().await;
+            result
+        };
+        let drain = async {
+            shutdown.wait().await;
+            tokio::time::sleep(pre_stop_delay + drain_timeout).await;
+        };
+        let result = tokio::select! {
+            result = finish => result,
+            _ = drain => {
+                tracing::warn!(
+                    "requests and background tasks still running after {:?} are dropped",
+                    drain_timeout
+                );
+                Ok(())
+            }
+        };
+
+        app_state.close().await;
+        tracing::info!("shut down");
+        result
     }
 }
 
@@ -81,6 +223,10 @@ impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self);
 
+        let retry_after = match self {
+            AuthAPIError::TooManyRequests { retry_after } => Some(retry_after),
+            _ => None,
+        };
         let (status, error_message) = match self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
@@ -90,13 +236,29 @@ impl IntoResponse for AuthAPIError {
             AuthAPIError::IncorrectCredentials => {
                 (StatusCode::UNAUTHORIZED, "Incorrect credentials")
             }
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email not verified"),
+            AuthAPIError::AccountLocked => (StatusCode::FORBIDDEN, "Account locked"),
+            AuthAPIError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
+            AuthAPIError::InvalidInput(message) => (StatusCode::BAD_REQUEST, message),
+            AuthAPIError::TooManyRequests { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "Too many requests")
+            }
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing token"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token"),
+            AuthAPIError::MissingPermission => (StatusCode::FORBIDDEN, "Missing permission"),
+            AuthAPIError::SessionNotFound => (StatusCode::NOT_FOUND, "Session not found"),
+            AuthAPIError::TotpAlreadyEnabled => (StatusCode::CONFLICT, "TOTP already enabled"),
         };
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
         });
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
 
@@ -113,16 +275,7 @@ pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
     redis::Client::open(redis_url)
 }
 
+// Log the error with its sources, as one event.
 fn log_error_chain(e: &(dyn Error + 'static)) {
-    let separator =
-        "\n-----------------------------------------------------------------------------------\n";
-    let mut report = format!("{}{:?}\n", separator, e);
-    let mut current = e.source();
-    while let Some(cause) = current {
-        let str = format!("Caused by:\n\n{:?}", cause);
-        report = format!("{}\n{}", report, str);
-        current = cause.source();
-    }
-    report = format!("{}\n{}", report, separator);
-    tracing::error!("{}", report);
+    tracing::error!(error = e, "request failed");
 }
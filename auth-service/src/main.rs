@@ -3,18 +3,23 @@ use secrecy::Secret;
 use sqlx::PgPool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio_util::task::TaskTracker;
 
 use auth_service::{
     Application,
-    app_state::AppState,
-    domain::Email,
+    app_state::{AppState, AuditSinkType, EmailClientType},
+    domain::{Email, LocalPartPolicy},
     get_postgres_pool, get_redis_client,
     services::{
-        PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore,
-        postmark_email_client::PostmarkEmailClient,
+        JsonLinesAuditSink, PostgresAuditSink, PostgresUserStore, RedisBannedTokenStore, RedisPool,
+        RedisRateLimitStore, RedisRefreshTokenStore, RedisSessionStore, RedisTwoFACodeStore,
+        postmark_email_client::PostmarkEmailClient, smtp_email_client::SmtpEmailClient,
     },
     utils::{
-        constants::{DATABASE_URL, POSTMARK_AUTH_TOKEN, REDIS_HOST_NAME, prod},
+        audit::AuditWriter,
+        key_ring::{KeyRing, reload_on_sighup},
+        settings::{EmailClientSettings, EmailProvider, Settings},
+        shutdown::shutdown_on_signal,
         tracing::init_tracing,
     },
 };
@@ -22,35 +27,81 @@ use auth_service::{
 #[tokio::main]
 async fn main() {
     color_eyre::install().expect("Failed to install color_eyre");
-    init_tracing().expect("Failed to initialize tracing");
 
-    let pg_pool = configure_postgresql().await;
-    let banned_token_redis_conn = Arc::new(RwLock::new(configure_redis()));
-    let two_fa_redis_conn = Arc::new(RwLock::new(configure_redis()));
+    let settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let tracer_provider = init_tracing(&settings.tracing).expect("Failed to initialize tracing");
 
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-    let banned_tokens_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(
-        banned_token_redis_conn,
-    )));
-    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(two_fa_redis_conn)));
-    let email_client = Arc::new(RwLock::new(configure_postmark_email_client()));
+    let pg_pool = configure_postgresql(&settings).await;
+    let redis_pool = configure_redis(&settings);
+
+    let audit_sink = configure_audit_sink(&settings, pg_pool.clone()).await;
+    let hash_params = settings
+        .password_hashing
+        .params()
+        .expect("Invalid password hashing parameters");
+    let user_store = Arc::new(PostgresUserStore::new(pg_pool, hash_params));
+    // Accounts created before emails were canonicalized catch up with the policy. The
+    // service starts either way, but until they do, those accounts cannot log in.
+    if settings.email.local_part_policy == LocalPartPolicy::Lowercase {
+        match user_store.lowercase_local_parts().await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("lowercased the local part of {} emails", count),
+            Err(e) => tracing::error!("could not lowercase the local part of emails: {:?}", e),
+        }
+    }
+    let banned_tokens_store = Arc::new(RedisBannedTokenStore::new(
+        redis_pool.clone(),
+        settings.jwt.token_ttl_seconds,
+    ));
+    let two_fa_code_store = Arc::new(RedisTwoFACodeStore::new(redis_pool.clone()));
+    let refresh_token_store = Arc::new(RedisRefreshTokenStore::new(redis_pool.clone()));
+    let session_store = Arc::new(RedisSessionStore::new(redis_pool.clone()));
+    let rate_limit_store = Arc::new(RedisRateLimitStore::new(redis_pool));
+    let key_ring = Arc::new(RwLock::new(
+        KeyRing::load(settings.jwt.key_source(), settings.jwt.token_ttl_seconds)
+            .expect("Failed to load JWT signing keys"),
+    ));
+    let email_client = configure_email_client(&settings);
+
+    // Keys can be rotated without a restart by sending SIGHUP.
+    tokio::spawn(reload_on_sighup(key_ring.clone()));
 
     let app_state = AppState {
         user_store,
         banned_tokens_store,
         two_fa_code_store,
+        refresh_token_store,
+        session_store,
+        rate_limit_store,
+        audit_writer: AuditWriter::spawn(audit_sink.clone()),
+        audit_sink,
+        key_ring,
         email_client,
+        settings: Arc::new(settings),
+        background_tasks: TaskTracker::new(),
     };
 
-    let app = Application::build(app_state, prod::APP_ADDRESS)
+    let app = Application::build(app_state)
         .await
         .expect("Failed to build app");
 
+    // Requests in flight are drained on SIGTERM or Ctrl-C before exiting.
+    tokio::spawn(shutdown_on_signal(app.shutdown_handle()));
+
     app.run().await.expect("Failed to run app");
+
+    if let Some(tracer_provider) = tracer_provider {
+        tracer_provider
+            .shutdown()
+            .expect("Failed to export the last spans");
+    }
 }
 
-pub async fn configure_postgresql() -> PgPool {
-    let pg_pool = get_postgres_pool(&DATABASE_URL)
+pub async fn configure_postgresql(settings: &Settings) -> PgPool {
+    let pg_pool = get_postgres_pool(&settings.database.url)
         .await
         .expect("Failed to create Postgres connection pool!");
 
@@ -63,23 +114,49 @@ pub async fn configure_postgresql() -> PgPool {
     pg_pool
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
+async fn configure_audit_sink(settings: &Settings, pg_pool: PgPool) -> AuditSinkType {
+    match settings.audit.log_file.as_ref() {
+        Some(path) => Arc::new(
+            JsonLinesAuditSink::open(path)
+                .await
+                .expect("Failed to open the audit log file"),
+        ),
+        None => Arc::new(PostgresAuditSink::new(pg_pool)),
+    }
+}
+
+fn configure_redis(settings: &Settings) -> RedisPool {
+    let client =
+        get_redis_client(settings.redis.host_name.to_owned()).expect("Failed to get Redis client");
+    RedisPool::new(client)
+}
+
+fn configure_email_client(settings: &Settings) -> EmailClientType {
+    match settings.email_client.provider {
+        EmailProvider::Postmark => {
+            Arc::new(configure_postmark_email_client(&settings.email_client))
+        }
+        EmailProvider::Smtp => Arc::new(
+            SmtpEmailClient::new(
+                &settings.smtp,
+                Email::parse(Secret::new(settings.email_client.sender.to_owned())).unwrap(),
+                settings.email_client.timeout(),
+            )
+            .expect("Failed to configure the SMTP client"),
+        ),
+    }
 }
 
-fn configure_postmark_email_client() -> PostmarkEmailClient {
+fn configure_postmark_email_client(settings: &EmailClientSettings) -> PostmarkEmailClient {
     let http_client = Client::builder()
-        .timeout(prod::email_client::TIMEOUT)
+        .timeout(settings.timeout())
         .build()
         .expect("Failed to build HTTP client");
 
     PostmarkEmailClient::new(
-        prod::email_client::BASE_URL.to_owned(),
-        Email::parse(Secret::new(prod::email_client::SENDER.to_owned())).unwrap(),
-        POSTMARK_AUTH_TOKEN.to_owned(),
+        settings.base_url.to_owned(),
+        Email::parse(Secret::new(settings.sender.to_owned())).unwrap(),
+        settings.auth_token.to_owned(),
         http_client,
     )
 }
@@ -0,0 +1,422 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::{CookieJar, cookie::Cookie};
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{
+        AuditEvent, AuditQuery, AuthAPIError, ClientInfo, Email, Password, RateLimitKey,
+        TwoFACodeStoreError, TwoFAMethod, UserId, UserStoreError,
+    },
+    routes::{SessionResponse, authenticate_session, start_session},
+    utils::{
+        auth::{generate_email_change_token, validate_auth_cookie, validate_email_change_token},
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_PATH},
+        rate_limit::{clear_failures, release, try_acquire},
+    },
+};
+
+// Change the password of the logged in user. Every other session is logged out,
+// and this one gets new tokens.
+#[tracing::instrument(name = "Change password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let result = update_password(&state, &client, &jar, request).await;
+    let user_id = match result {
+        Ok(user_id) => user_id,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let (auth_cookie, refresh_cookie) = match start_session(&state, &user_id, client).await {
+        Ok(cookies) => cookies,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(StatusCode::OK))
+}
+
+async fn update_password(
+    state: &AppState,
+    client: &ClientInfo,
+    jar: &CookieJar,
+    request: ChangePasswordRequest,
+) -> Result<UserId, AuthAPIError> {
+    let user_id = authenticate(state, jar).await?;
+    let current_password =
+        Password::parse(request.current_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let new_password =
+        Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    check_password(state, client, user_id, current_password).await?;
+    state
+        .user_store
+        .update_password(user_id, new_password)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    revoke_all_tokens(state, &user_id).await?;
+    Ok(user_id)
+}
+
+// Email a confirmation link to the new address. The email only changes once it is followed.
+#[tracing::instrument(name = "Change email", skip_all)]
+pub async fn change_email(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&state, &jar).await?;
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let new_email =
+        Email::parse_with_policy(request.new_email, state.settings.email.local_part_policy)
+            .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let email = check_password(&state, &client, user_id, password).await?;
+    if new_email == email {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+    match state.user_store.get_user(new_email.clone()).await {
+        Ok(_) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::UserNotFound) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let token = generate_email_change_token(state.key_ring.clone(), &email, &new_email)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let link = format!(
+        "{}?token={}",
+        state.settings.links.email_change_url,
+        token.expose_secret()
+    );
+    state
+        .email_client
+        .send_email(
+            &new_email,
+            "Confirm your new email address",
+            format!(
+                "Hi there, follow this link to use this email address for your account: {}\n\
+                 If you did not ask for it, you can ignore this email.",
+                link
+            )
+            .as_str(),
+        )
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(ChangeEmailResponse {
+        message: "A confirmation link has been sent to the new email address.".to_owned(),
+    });
+
+    Ok((StatusCode::ACCEPTED, response))
+}
+
+// Switch to the new email from a confirmation link, and let the old address know.
+// Every session is revoked, so the user has to log in again.
+#[tracing::instrument(name = "Confirm email change", skip_all)]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Json(request): Json<ConfirmEmailChangeRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let claims = validate_email_change_token(state.key_ring.clone(), &request.token)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+    let email = Email::parse_with_policy(
+        Secret::new(claims.sub),
+        state.settings.email.local_part_policy,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+    let new_email = Email::parse_with_policy(
+        Secret::new(claims.new_email),
+        state.settings.email.local_part_policy,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = match state.user_store.get_user(email.clone()).await {
+        Ok(user) => user.id,
+        // The email already changed: the link was used.
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    match state
+        .user_store
+        .update_email(user_id, new_email.clone())
+        .await
+    {
+        Ok(()) => {}
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    revoke_all_tokens(&state, &user_id).await?;
+
+    // The change is done: a failure to notify must not report it as failed.
+    if let Err(e) = state
+        .email_client
+        .send_email(
+            &email,
+            "Your email address was changed",
+            format!(
+                "Hi there, your account now uses the email address {}.\n\
+                 If you did not ask for it, please contact us right away.",
+                new_email.as_ref().expose_secret()
+            )
+            .as_str(),
+        )
+        .await
+    {
+        tracing::error!("failed to notify the previous email address: {:?}", e);
+    }
+
+    Ok(StatusCode::OK)
+}
+
+// Delete the account of the logged in user, after they entered their password again.
+#[tracing::instrument(name = "Delete account", skip_all)]
+pub async fn delete_account(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<DeleteAccountRequest>,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    if let Err(e) = remove_user(&state, &client, &jar, request).await {
+        return (jar, Err(e));
+    }
+
+    let jar = jar
+        .remove(Cookie::from(JWT_COOKIE_NAME))
+        .remove(Cookie::build(REFRESH_TOKEN_COOKIE_NAME).path(REFRESH_TOKEN_COOKIE_PATH));
+
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
+
+async fn remove_user(
+    state: &AppState,
+    client: &ClientInfo,
+    jar: &CookieJar,
+    request: DeleteAccountRequest,
+) -> Result<(), AuthAPIError> {
+    let user_id = authenticate(state, jar).await?;
+    let token = jar
+        .get(JWT_COOKIE_NAME)
+        .map(|cookie| Secret::new(cookie.value().to_owned()))
+        .ok_or(AuthAPIError::MissingToken)?;
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let email = check_password(state, client, user_id, password).await?;
+    state
+        .user_store
+        .delete_user(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // A login waiting for its 2FA code must not complete.
+    match state.two_fa_code_store.remove_code(&email).await {
+        Ok(()) | Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    state
+        .banned_tokens_store
+        .add_token(token)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    // Other sessions must not outlive the account either.
+    revoke_all_tokens(state, &user_id).await
+}
+
+// Everything stored about the logged in user. Hashes and secrets are redacted.
+#[tracing::instrument(name = "Export account", skip_all)]
+pub async fn export_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user_id, current_id) = authenticate_session(&state, &jar).await?;
+
+    let user = match state.user_store.export_user(user_id).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    let pending_login = match state.two_fa_code_store.get_code(&user.email).await {
+        Ok(_) => true,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => false,
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    let grants = state
+        .user_store
+        .get_grants(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    let mut sessions = state
+        .session_store
+        .get_user_sessions(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+    // Events of requests that already answered may still be queued.
+    state.audit_writer.flush().await;
+    let query = AuditQuery {
+        user_id: Some(user_id),
+        ..AuditQuery::default()
+    };
+    let audit_entries = state
+        .audit_sink
+        .query(query)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(AccountExport {
+        id: user.id.to_string(),
+        email: user.email.as_ref().expose_secret().to_owned(),
+        password_hash: REDACTED.to_owned(),
+        verified: user.verified,
+        two_fa_method: user.two_fa_method,
+        totp_secret: user.has_totp_secret.then(|| REDACTED.to_owned()),
+        pending_2fa_login: pending_login,
+        locked: user.locked,
+        roles: grants
+            .roles
+            .iter()
+            .map(|role| role.as_ref().to_owned())
+            .collect(),
+        sessions: sessions
+            .into_iter()
+            .map(|session| SessionResponse::new(session, &current_id))
+            .collect(),
+        audit_events: audit_entries.into_iter().map(|entry| entry.event).collect(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Placeholder for values that are stored, but never exported.
+const REDACTED: &str = "[REDACTED]";
+
+// Ban every JWT and refresh token issued to the user so far, ending all of their sessions.
+pub(crate) async fn revoke_all_tokens(
+    state: &AppState,
+    user_id: &UserId,
+) -> Result<(), AuthAPIError> {
+    state
+        .session_store
+        .remove_user_sessions(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    state
+        .banned_tokens_store
+        .ban_tokens_issued_before(user_id, Utc::now().timestamp_millis())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    state
+        .refresh_token_store
+        .revoke_all_families(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+async fn authenticate(state: &AppState, jar: &CookieJar) -> Result<UserId, AuthAPIError> {
+    let claims = validate_auth_cookie(
+        jar,
+        state.banned_tokens_store.clone(),
+        state.session_store.clone(),
+        state.key_ring.clone(),
+    )
+    .await?;
+    UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)
+}
+
+// Check the password of the user, and return their current email. Wrong passwords
+// are throttled as at login, so that a stolen session cannot be used to guess it.
+async fn check_password(
+    state: &AppState,
+    client: &ClientInfo,
+    user_id: UserId,
+    password: Password,
+) -> Result<Email, AuthAPIError> {
+    let user_store = &state.user_store;
+    let user = match user_store.get_user_by_id(user_id).await {
+        Ok(user) => user,
+        // The user behind a valid token is gone, e.g. after the account was deleted.
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let email_key = RateLimitKey::Email(user.email.clone());
+    let keys = [email_key.clone(), RateLimitKey::Ip(client.ip)];
+    let reservations = try_acquire(state.rate_limit_store.clone(), &keys).await?;
+    match user_store.validate_user(user.email.clone(), password).await {
+        Ok(()) => {}
+        Err(UserStoreError::InvalidCredentials) => return Err(AuthAPIError::IncorrectCredentials),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+    release(state.rate_limit_store.clone(), &reservations).await?;
+    clear_failures(state.rate_limit_store.clone(), &email_key).await?;
+
+    Ok(user.email)
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    pub password: Secret<String>,
+    #[serde(rename = "newEmail")]
+    pub new_email: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ChangeEmailResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AccountExport {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "passwordHash")]
+    pub password_hash: String,
+    pub verified: bool,
+    #[serde(rename = "twoFAMethod")]
+    pub two_fa_method: TwoFAMethod,
+    #[serde(rename = "totpSecret")]
+    pub totp_secret: Option<String>,
+    #[serde(rename = "pending2FALogin")]
+    pub pending_2fa_login: bool,
+    pub locked: bool,
+    pub roles: Vec<String>,
+    // Active sessions, with the address and user agent of their clients.
+    pub sessions: Vec<SessionResponse>,
+    // Audit log entries about the user, oldest first.
+    #[serde(rename = "auditEvents")]
+    pub audit_events: Vec<AuditEvent>,
+}
@@ -0,0 +1,458 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use rand::{Rng, distributions::Alphanumeric};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{
+        AuditEventKind, AuthAPIError, Email, Password, TwoFACodeStoreError, TwoFAMethod, User,
+        UserId, UserQuery, UserStoreError,
+    },
+    utils::{
+        audit::Auditor,
+        authorization::{Authorized, UsersRead, UsersWrite},
+    },
+};
+
+use super::{
+    account::revoke_all_tokens, password_reset::send_password_reset_email,
+    verify_email::spawn_verification_email,
+};
+
+// Users listed per page when the request does not say, and at most.
+const DEFAULT_PER_PAGE: u32 = 20;
+const MAX_PER_PAGE: u32 = 100;
+
+// List users in email order, optionally only those whose email contains `search`.
+#[tracing::instrument(name = "Admin list users", skip_all)]
+pub async fn admin_list_users(
+    _admin: Authorized<UsersRead>,
+    State(state): State<AppState>,
+    Query(params): Query<ListUsersParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let page = params.page.unwrap_or(1);
+    let per_page = params.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if page == 0 || per_page == 0 || per_page > MAX_PER_PAGE {
+        return Err(AuthAPIError::InvalidInput("Invalid page or perPage"));
+    }
+
+    let query = UserQuery {
+        search: params.search.filter(|search| !search.is_empty()),
+        offset: (page - 1).saturating_mul(per_page),
+        limit: per_page,
+    };
+    let result = state
+        .user_store
+        .list_users(query)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(UserListResponse {
+        users: result.users.iter().map(AdminUser::from).collect(),
+        page,
+        per_page,
+        total: result.total,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Create an account for someone. Unless it is marked as verified, the usual
+// verification email is sent.
+#[tracing::instrument(name = "Admin create user", skip_all)]
+pub async fn admin_create_user(
+    admin: Authorized<UsersWrite>,
+    State(state): State<AppState>,
+    audit: Auditor,
+    Json(request): Json<CreateUserRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let result = create_user(&state, request).await;
+    let user_id = result.as_ref().ok().map(|(user_id, _)| *user_id);
+    audit
+        .record(
+            AuditEventKind::AdminUserCreation,
+            admin.actor.audit_actor(),
+            user_id,
+            &result,
+        )
+        .await;
+
+    result.map(|(_, user)| (StatusCode::CREATED, Json(user)))
+}
+
+// Also returns the id of the new account, for the audit log.
+async fn create_user(
+    state: &AppState,
+    request: CreateUserRequest,
+) -> Result<(UserId, AdminUser), AuthAPIError> {
+    let email = Email::parse_with_policy(request.email, state.settings.email.local_part_policy)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    // Authenticator apps can only be enrolled by the user themselves.
+    let two_fa_method = request.two_fa_method.unwrap_or_default();
+    if two_fa_method == TwoFAMethod::Totp {
+        return Err(AuthAPIError::InvalidInput(
+            "TOTP can only be enrolled by the user",
+        ));
+    }
+
+    let mut user = User::new(email.clone(), password, two_fa_method);
+    user.verified = request.verified;
+    let (user_id, response) = (user.id, AdminUser::from(&user));
+    match state.user_store.add_user(user).await {
+        Ok(()) => {}
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    if !request.verified {
+        spawn_verification_email(state, email);
+    }
+
+    Ok((user_id, response))
+}
+
+// Show a user, with their roles.
+#[tracing::instrument(name = "Admin get user", skip_all)]
+pub async fn admin_get_user(
+    _admin: Authorized<UsersRead>,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = get_user(&state, &id).await?;
+    let grants = state
+        .user_store
+        .get_grants(user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(AdminUserDetails {
+        user: AdminUser::from(&user),
+        roles: grants
+            .roles
+            .iter()
+            .map(|role| role.as_ref().to_owned())
+            .collect(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Delete a user and end all of their sessions.
+#[tracing::instrument(name = "Admin delete user", skip_all)]
+pub async fn admin_delete_user(
+    admin: Authorized<UsersWrite>,
+    State(state): State<AppState>,
+    audit: Auditor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    let result = delete_user(&state, &id).await;
+    record(
+        &audit,
+        AuditEventKind::AdminUserDeletion,
+        &admin,
+        &id,
+        &result,
+    )
+    .await;
+
+    result
+}
+
+async fn delete_user(state: &AppState, id: &str) -> Result<StatusCode, AuthAPIError> {
+    let user = get_user(state, id).await?;
+    state
+        .user_store
+        .delete_user(user.id)
+        .await
+        .map_err(store_error)?;
+
+    remove_pending_login(state, &user.email).await?;
+    revoke_all_tokens(state, &user.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Replace the password of a user with a random one, log them out everywhere, and
+// email them a link to choose a new one.
+#[tracing::instrument(name = "Admin force password reset", skip_all)]
+pub async fn admin_force_password_reset(
+    admin: Authorized<UsersWrite>,
+    State(state): State<AppState>,
+    audit: Auditor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    let result = force_password_reset(&state, &id).await;
+    record(
+        &audit,
+        AuditEventKind::AdminPasswordReset,
+        &admin,
+        &id,
+        &result,
+    )
+    .await;
+
+    result
+}
+
+async fn force_password_reset(state: &AppState, id: &str) -> Result<StatusCode, AuthAPIError> {
+    let user = get_user(state, id).await?;
+    let password =
+        Password::parse(Secret::new(random_password())).map_err(AuthAPIError::UnexpectedError)?;
+
+    let user_store = &state.user_store;
+    user_store
+        .update_password(user.id, password)
+        .await
+        .map_err(store_error)?;
+    revoke_all_tokens(state, &user.id).await?;
+
+    // The reset link is tied to the password as stored, i.e. to its hash.
+    let user = user_store
+        .get_user_by_id(user.id)
+        .await
+        .map_err(store_error)?;
+    send_password_reset_email(state.clone(), user.email, user.password).await;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+// Switch the second factor of a user. TOTP can only be turned back on for a user
+// who already enrolled an authenticator app.
+#[tracing::instrument(name = "Admin set user 2FA method", skip_all)]
+pub async fn admin_set_two_fa_method(
+    admin: Authorized<UsersWrite>,
+    State(state): State<AppState>,
+    audit: Auditor,
+    Path(id): Path<String>,
+    Json(request): Json<SetTwoFAMethodRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let result = set_two_fa_method(&state, &id, request).await;
+    record(
+        &audit,
+        AuditEventKind::AdminTwoFAChange,
+        &admin,
+        &id,
+        &result,
+    )
+    .await;
+
+    result.map(|user| (StatusCode::OK, Json(user)))
+}
+
+async fn set_two_fa_method(
+    state: &AppState,
+    id: &str,
+    request: SetTwoFAMethodRequest,
+) -> Result<AdminUser, AuthAPIError> {
+    let mut user = get_user(state, id).await?;
+
+    let user_store = &state.user_store;
+    if request.two_fa_method == TwoFAMethod::Totp {
+        match user_store.get_totp_secret(user.id).await {
+            Ok(_) => {}
+            Err(UserStoreError::TotpSecretNotFound) => {
+                return Err(AuthAPIError::InvalidInput("TOTP not enrolled"));
+            }
+            Err(e) => return Err(store_error(e)),
+        }
+    }
+    user_store
+        .set_two_fa_method(user.id, request.two_fa_method)
+        .await
+        .map_err(store_error)?;
+    user.two_fa_method = request.two_fa_method;
+
+    Ok(AdminUser::from(&user))
+}
+
+// Lock a user out: they are logged out everywhere, and cannot log in until unlocked.
+#[tracing::instrument(name = "Admin lock user", skip_all)]
+pub async fn admin_lock_user(
+    admin: Authorized<UsersWrite>,
+    State(state): State<AppState>,
+    audit: Auditor,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let result = set_locked(&state, &id, true).await;
+    record(&audit, AuditEventKind::AdminLock, &admin, &id, &result).await;
+
+    result.map(|user| (StatusCode::OK, Json(user)))
+}
+
+#[tracing::instrument(name = "Admin unlock user", skip_all)]
+pub async fn admin_unlock_user(
+    admin: Authorized<UsersWrite>,
+    State(state): State<AppState>,
+    audit: Auditor,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let result = set_locked(&state, &id, false).await;
+    record(&audit, AuditEventKind::AdminUnlock, &admin, &id, &result).await;
+
+    result.map(|user| (StatusCode::OK, Json(user)))
+}
+
+async fn set_locked(state: &AppState, id: &str, locked: bool) -> Result<AdminUser, AuthAPIError> {
+    let mut user = get_user(state, id).await?;
+    state
+        .user_store
+        .set_locked(user.id, locked)
+        .await
+        .map_err(store_error)?;
+    user.locked = locked;
+
+    if locked {
+        remove_pending_login(state, &user.email).await?;
+        revoke_all_tokens(state, &user.id).await?;
+    }
+
+    Ok(AdminUser::from(&user))
+}
+
+// Log a user out of every session.
+#[tracing::instrument(name = "Admin revoke user sessions", skip_all)]
+pub async fn admin_revoke_user_sessions(
+    admin: Authorized<UsersWrite>,
+    State(state): State<AppState>,
+    audit: Auditor,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    let result = revoke_user_sessions(&state, &id).await;
+    record(
+        &audit,
+        AuditEventKind::AdminSessionRevocation,
+        &admin,
+        &id,
+        &result,
+    )
+    .await;
+
+    result
+}
+
+async fn revoke_user_sessions(state: &AppState, id: &str) -> Result<StatusCode, AuthAPIError> {
+    let user = get_user(state, id).await?;
+    remove_pending_login(state, &user.email).await?;
+    revoke_all_tokens(state, &user.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Record the result of an admin action about the user with the id in the path.
+async fn record<T>(
+    audit: &Auditor,
+    kind: AuditEventKind,
+    admin: &Authorized<UsersWrite>,
+    id: &str,
+    result: &Result<T, AuthAPIError>,
+) {
+    let user_id = UserId::parse(id).ok();
+    audit
+        .record(kind, admin.actor.audit_actor(), user_id, result)
+        .await;
+}
+
+// The user with the id in the path. Ids that do not parse belong to no user.
+async fn get_user(state: &AppState, id: &str) -> Result<User, AuthAPIError> {
+    let id = UserId::parse(id).map_err(|_| AuthAPIError::UserNotFound)?;
+    state
+        .user_store
+        .get_user_by_id(id)
+        .await
+        .map_err(store_error)
+}
+
+// A login waiting for its 2FA code must not complete.
+async fn remove_pending_login(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    match state.two_fa_code_store.remove_code(email).await {
+        Ok(()) | Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => Ok(()),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+fn store_error(e: UserStoreError) -> AuthAPIError {
+    match e {
+        UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+        e => AuthAPIError::UnexpectedError(e.into()),
+    }
+}
+
+// A password nobody knows, so that the old one stops working until the user picks a new one.
+fn random_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub struct ListUsersParams {
+    pub search: Option<String>,
+    pub page: Option<u32>,
+    #[serde(rename = "perPage")]
+    pub per_page: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateUserRequest {
+    pub email: Secret<String>,
+    pub password: Secret<String>,
+    #[serde(rename = "twoFAMethod")]
+    pub two_fa_method: Option<TwoFAMethod>,
+    #[serde(default)]
+    pub verified: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SetTwoFAMethodRequest {
+    #[serde(rename = "twoFAMethod")]
+    pub two_fa_method: TwoFAMethod,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AdminUser {
+    pub id: String,
+    pub email: String,
+    pub verified: bool,
+    #[serde(rename = "twoFAMethod")]
+    pub two_fa_method: TwoFAMethod,
+    pub locked: bool,
+}
+
+impl From<&User> for AdminUser {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.id.to_string(),
+            email: user.email.as_ref().expose_secret().to_owned(),
+            verified: user.verified,
+            two_fa_method: user.two_fa_method,
+            locked: user.locked,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AdminUserDetails {
+    #[serde(flatten)]
+    pub user: AdminUser,
+    pub roles: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct UserListResponse {
+    pub users: Vec<AdminUser>,
+    pub page: u32,
+    #[serde(rename = "perPage")]
+    pub per_page: u32,
+    pub total: u64,
+}
@@ -0,0 +1,100 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{AuditEntry, AuditQuery, AuthAPIError, UserId, verify_chain},
+    utils::authorization::{AuditRead, Authorized},
+};
+
+// Entries returned when the request does not say, and at most.
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+// Read the audit log, oldest entry first, optionally about one user or a time range.
+#[tracing::instrument(name = "Admin query audit log", skip_all)]
+pub async fn admin_query_audit_log(
+    _admin: Authorized<AuditRead>,
+    State(state): State<AppState>,
+    Query(params): Query<AuditLogParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+    let user_id = params
+        .user_id
+        .map(|id| UserId::parse(&id))
+        .transpose()
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let query = AuditQuery {
+        user_id,
+        from: params.from,
+        to: params.to,
+        limit: Some(limit),
+    };
+    // Events of requests that already answered may still be queued.
+    state.audit_writer.flush().await;
+    let entries = state
+        .audit_sink
+        .query(query)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok((StatusCode::OK, Json(AuditLogResponse { entries })))
+}
+
+// Check that no entry of the audit log was altered or removed, short of the latest
+// ones: see `verify_chain`.
+#[tracing::instrument(name = "Admin verify audit log", skip_all)]
+pub async fn admin_verify_audit_log(
+    _admin: Authorized<AuditRead>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    state.audit_writer.flush().await;
+    let entries = state
+        .audit_sink
+        .query(AuditQuery::default())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let broken_at = verify_chain(&entries).err();
+    let response = Json(AuditChainResponse {
+        valid: broken_at.is_none(),
+        entries: entries.len(),
+        broken_at,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Deserialize)]
+pub struct AuditLogParams {
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+    // Bounds on the timestamps, in milliseconds since the epoch, both included.
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AuditLogResponse {
+    pub entries: Vec<AuditEntry>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AuditChainResponse {
+    pub valid: bool,
+    pub entries: usize,
+    // Sequence number of the first entry that does not follow the one before.
+    #[serde(rename = "brokenAt")]
+    pub broken_at: Option<i64>,
+}
@@ -0,0 +1,226 @@
+use axum::{Extension, Json, extract::State, http::StatusCode, response::IntoResponse};
+use color_eyre::eyre::Report;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    time::{Duration, Instant},
+};
+use tracing;
+
+use crate::{AppState, utils::shutdown::ShutdownHandle};
+
+// Whether the process is up. Nothing else is checked, so that a slow dependency
+// does not get the service restarted.
+#[tracing::instrument(name = "Liveness", skip_all)]
+pub async fn health_live() -> impl IntoResponse {
+    let response = Json(LivenessResponse {
+        status: HealthStatus::Up,
+    });
+
+    (StatusCode::OK, response)
+}
+
+// Whether the service can take requests: it is not shutting down, and every store
+// answers a ping in time. Stores kept in memory always do. The email provider is
+// only checked when configured. Neither it nor the rate limit store, which falls back
+// to memory, makes the service unready: it is degraded while they are down.
+#[tracing::instrument(name = "Readiness", skip_all)]
+pub async fn health_ready(
+    State(state): State<AppState>,
+    Extension(shutdown): Extension<ShutdownHandle>,
+) -> impl IntoResponse {
+    let settings = &state.settings.health;
+    let timeout = settings.timeout();
+
+    let email = async {
+        match settings.check_email {
+            true => Some(check("emailClient", false, timeout, state.email_client.ping()).await),
+            false => None,
+        }
+    };
+    let (users, banned, codes, refresh, sessions, limits, audit, email) = tokio::join!(
+        check("userStore", true, timeout, state.user_store.ping()),
+        check(
+            "bannedTokenStore",
+            true,
+            timeout,
+            state.banned_tokens_store.ping()
+        ),
+        check(
+            "twoFACodeStore",
+            true,
+            timeout,
+            state.two_fa_code_store.ping()
+        ),
+        check(
+            "refreshTokenStore",
+            true,
+            timeout,
+            state.refresh_token_store.ping()
+        ),
+        check("sessionStore", true, timeout, state.session_store.ping()),
+        check(
+            "rateLimitStore",
+            false,
+            timeout,
+            state.rate_limit_store.ping()
+        ),
+        check("auditSink", true, timeout, state.audit_sink.ping()),
+        email,
+    );
+    let checks: BTreeMap<String, DependencyHealth> =
+        [users, banned, codes, refresh, sessions, limits, audit]
+            .into_iter()
+            .chain(email)
+            .collect();
+
+    // Unready from the start of the shutdown, so that load balancers stop sending
+    // requests during the pre-stop delay, before the server stops accepting them.
+    let draining = shutdown.is_draining();
+    let ready = !draining
+        && checks
+            .values()
+            .all(|check| check.status == HealthStatus::Up || !check.critical);
+    let degraded = checks
+        .values()
+        .any(|check| check.status == HealthStatus::Down);
+    let (status_code, status) = match (ready, degraded) {
+        (true, false) => (StatusCode::OK, HealthStatus::Up),
+        (true, true) => (StatusCode::OK, HealthStatus::Degraded),
+        (false, _) => (StatusCode::SERVICE_UNAVAILABLE, HealthStatus::Down),
+    };
+
+    let response = Json(ReadinessResponse {
+        status,
+        draining,
+        checks,
+    });
+
+    (status_code, response)
+}
+
+// Time a ping of the dependency, giving up after `timeout`.
+async fn check<E: Into<Report>>(
+    name: &str,
+    critical: bool,
+    timeout: Duration,
+    ping: impl Future<Output = Result<(), E>>,
+) -> (String, DependencyHealth) {
+    let start = Instant::now();
+    let status = match tokio::time::timeout(timeout, ping).await {
+        Ok(Ok(())) => HealthStatus::Up,
+        Ok(Err(e)) => {
+            tracing::warn!("{} is down: {:?}", name, e.into());
+            HealthStatus::Down
+        }
+        Err(_) => {
+            tracing::warn!("{} did not answer within {:?}", name, timeout);
+            HealthStatus::Down
+        }
+    };
+    let health = DependencyHealth {
+        status,
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        critical,
+    };
+
+    (name.to_owned(), health)
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    // Ready, but a dependency that is not critical is down.
+    Degraded,
+    Down,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct LivenessResponse {
+    pub status: HealthStatus,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ReadinessResponse {
+    pub status: HealthStatus,
+    pub draining: bool,
+    pub checks: BTreeMap<String, DependencyHealth>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: f64,
+    // Whether the service is unready while the dependency is down.
+    pub critical: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{
+        HashmapRateLimitStore, HashmapRefreshTokenStore, HashmapSessionStore,
+        HashmapTwoFACodeStore, HashmapUserStore, HashsetBannedTokenStore, VecAuditSink,
+        mock_email_client::MockEmailClient,
+    };
+    use crate::utils::{key_ring::KeyRing, settings::Settings};
+    use axum::body::to_bytes;
+    use secrecy::Secret;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn get_app_state() -> AppState {
+        let mut settings = Settings::default();
+        settings.jwt.secret = Secret::new("test-secret".to_owned());
+        let key_ring = KeyRing::load(settings.jwt.key_source(), settings.jwt.token_ttl_seconds);
+
+        AppState::new(
+            Arc::new(HashmapUserStore::default()),
+            Arc::new(HashsetBannedTokenStore::default()),
+            Arc::new(HashmapTwoFACodeStore::default()),
+            Arc::new(HashmapRefreshTokenStore::default()),
+            Arc::new(HashmapSessionStore::default()),
+            Arc::new(HashmapRateLimitStore::default()),
+            Arc::new(VecAuditSink::default()),
+            Arc::new(RwLock::new(key_ring.unwrap())),
+            Arc::new(MockEmailClient),
+            Arc::new(settings),
+        )
+    }
+
+    async fn get_readiness(shutdown: ShutdownHandle) -> (StatusCode, ReadinessResponse) {
+        let response = health_ready(State(get_app_state()), Extension(shutdown))
+            .await
+            .into_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_ready_while_serving() {
+        let (status, response) = get_readiness(ShutdownHandle::default()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.status, HealthStatus::Up);
+        assert!(!response.draining);
+    }
+
+    #[tokio::test]
+    async fn test_unready_while_draining() {
+        let shutdown = ShutdownHandle::default();
+        shutdown.shutdown();
+
+        let (status, response) = get_readiness(shutdown).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.status, HealthStatus::Down);
+        assert!(response.draining);
+        // Stores are still checked, to tell a drain from an outage.
+        assert_eq!(response.checks["userStore"].status, HealthStatus::Up);
+    }
+}
@@ -0,0 +1,20 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+
+use crate::AppState;
+
+// Public keys relying services can use to verify auth tokens on their own.
+#[tracing::instrument(name = "JWKS", skip_all)]
+pub async fn jwks(State(state): State<AppState>) -> impl IntoResponse {
+    let jwks = state.key_ring.read().await.jwks();
+
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(jwks),
+    )
+}
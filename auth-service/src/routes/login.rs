@@ -6,44 +6,115 @@ use tracing;
 
 use crate::{
     AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, Password, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{
+        AuditActor, AuditEventKind, AuthAPIError, ClientInfo, Email, LoginAttemptId, Password,
+        RateLimitKey, TwoFACode, TwoFAMethod, User, UserId,
+    },
+    routes::start_session,
+    utils::{
+        audit::Auditor,
+        rate_limit::{clear_failures, release, try_acquire},
+    },
 };
 
 #[tracing::instrument(name = "Login", skip_all)]
 pub async fn login(
     State(state): State<AppState>,
+    client: ClientInfo,
+    audit: Auditor,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    let Ok(email) = Email::parse(request.email) else {
+    let mut user_id = None;
+    let (jar, result) = log_in(&state, client, jar, request, &mut user_id).await;
+
+    let kind = match &result {
+        Ok((StatusCode::PARTIAL_CONTENT, _)) => AuditEventKind::TwoFAChallenge,
+        _ => AuditEventKind::Login,
+    };
+    audit
+        .record(kind, AuditActor::Anonymous, user_id, &result)
+        .await;
+
+    (jar, result)
+}
+
+// Check the credentials, and either start a session or ask for a 2FA code.
+// `user_id` is set as soon as the account is known, for the audit log.
+async fn log_in(
+    state: &AppState,
+    client: ClientInfo,
+    jar: CookieJar,
+    request: LoginRequest,
+    user_id: &mut Option<UserId>,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let Ok(email) = Email::parse_with_policy(request.email, state.settings.email.local_part_policy)
+    else {
         return (jar, Err(AuthAPIError::InvalidCredentials));
     };
     let Ok(password) = Password::parse(request.password) else {
         return (jar, Err(AuthAPIError::InvalidCredentials));
     };
 
-    let user_store = &state.user_store.read().await;
-    if user_store
-        .validate_user(email.clone(), password)
-        .await
-        .is_err()
-    {
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
-    }
+    // Failures are counted per account and per client, so that guessing is slowed
+    // down both for one account and across many.
+    let email_key = RateLimitKey::Email(email.clone());
+    let keys = [email_key.clone(), RateLimitKey::Ip(client.ip)];
+    let reservations = match try_acquire(state.rate_limit_store.clone(), &keys).await {
+        Ok(reservations) => reservations,
+        Err(e) => return (jar, Err(e)),
+    };
 
-    let user = match user_store.get_user(email).await {
+    let user = match validate_user(state, email, password).await {
         Ok(user) => user,
-        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+        Err(id) => {
+            *user_id = id;
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
     };
+    *user_id = Some(user.id);
+    // The password was right: the attempt does not count against the client, and the
+    // account's failures are forgotten.
+    if let Err(e) = release(state.rate_limit_store.clone(), &reservations).await {
+        return (jar, Err(e));
+    }
+    if let Err(e) = clear_failures(state.rate_limit_store.clone(), &email_key).await {
+        return (jar, Err(e));
+    }
+    if !user.verified {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+    if user.locked {
+        return (jar, Err(AuthAPIError::AccountLocked));
+    }
 
     // Handle request based on user's 2FA configuration
-    match user.requires_2fa {
-        true => handle_2fa(&user.email, &state, jar).await,
-        false => handle_no_2fa(&user.email, jar).await,
+    match user.two_fa_method {
+        TwoFAMethod::Email => handle_2fa(&user.email, state, jar).await,
+        TwoFAMethod::Totp => handle_totp(&user.email, state, jar).await,
+        TwoFAMethod::None => handle_no_2fa(&user.id, state, jar, client).await,
     }
 }
 
+// The user with these credentials. Otherwise, the id of the account whose password
+// was wrong, if there is one.
+async fn validate_user(
+    state: &AppState,
+    email: Email,
+    password: Password,
+) -> Result<User, Option<UserId>> {
+    let user_store = &state.user_store;
+    let user = user_store.get_user(email.clone()).await.map_err(|_| None)?;
+    user_store
+        .validate_user(email, password)
+        .await
+        .map_err(|_| Some(user.id))?;
+    Ok(user)
+}
+
 #[tracing::instrument(name = "Handle 2FA", skip_all)]
 async fn handle_2fa(
     email: &Email,
@@ -59,16 +130,18 @@ async fn handle_2fa(
 
     if let Err(e) = state
         .two_fa_code_store
-        .write()
-        .await
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+        .add_code(
+            email.clone(),
+            login_attempt_id.clone(),
+            Some(two_fa_code.clone()),
+        )
         .await
     {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
     // Send code by email.
-    let email_client = state.email_client.write().await;
+    let email_client = &state.email_client;
     if let Err(err) = email_client
         .send_email(
             email,
@@ -92,19 +165,51 @@ async fn handle_2fa(
     (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
 }
 
+#[tracing::instrument(name = "Handle TOTP", skip_all)]
+async fn handle_totp(
+    email: &Email,
+    state: &AppState,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    // The code comes from the user's authenticator app: we only track the login attempt,
+    // and `verify_2fa` checks TOTP users against their secret.
+    let login_attempt_id = LoginAttemptId::default();
+
+    if let Err(e) = state
+        .two_fa_code_store
+        .add_code(email.clone(), login_attempt_id.clone(), None)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+        message: "2FA required".to_owned(),
+        login_attempt_id: login_attempt_id.as_ref().expose_secret().to_owned(),
+    }));
+
+    (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
+}
+
 #[tracing::instrument(name = "Handle No 2FA", skip_all)]
 async fn handle_no_2fa(
-    email: &Email,
+    user_id: &UserId,
+    state: &AppState,
     jar: CookieJar,
+    client: ClientInfo,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    let auth_cookie = match generate_auth_cookie(email) {
-        Ok(cookie) => cookie,
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    // Every login starts a new session, with its own refresh token family.
+    let (auth_cookie, refresh_cookie) = match start_session(state, user_id, client).await {
+        Ok(cookies) => cookies,
+        Err(e) => return (jar, Err(e)),
     };
-    let updated_jar = jar.add(auth_cookie);
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
 
     (
         updated_jar,
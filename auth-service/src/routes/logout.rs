@@ -1,7 +1,12 @@
 use crate::{
     AppState,
-    domain::AuthAPIError,
-    utils::{self, constants::JWT_COOKIE_NAME},
+    domain::{AuditActor, AuditEventKind, AuthAPIError, RefreshTokenFamilyId, UserId},
+    routes::end_session,
+    utils::{
+        self,
+        audit::Auditor,
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_PATH},
+    },
 };
 use axum::{extract::State, http::StatusCode};
 use axum_extra::extract::{CookieJar, cookie};
@@ -11,7 +16,25 @@ use tracing;
 #[tracing::instrument(name = "Logout", skip_all)]
 pub async fn logout(
     State(state): State<AppState>,
+    audit: Auditor,
     jar: CookieJar,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let mut user_id = None;
+    let (jar, result) = log_out(&state, jar, &mut user_id).await;
+    // Only a valid token tells who is logging out.
+    let actor = user_id.map_or(AuditActor::Anonymous, AuditActor::User);
+    audit
+        .record(AuditEventKind::Logout, actor, user_id, &result)
+        .await;
+
+    (jar, result)
+}
+
+// `user_id` is set once the token is known to be valid, for the audit log.
+async fn log_out(
+    state: &AppState,
+    jar: CookieJar,
+    user_id: &mut Option<UserId>,
 ) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
     let cookie = match jar.get(JWT_COOKIE_NAME) {
         Some(cookie) => cookie,
@@ -20,24 +43,36 @@ pub async fn logout(
     let token = Secret::new(cookie.value().to_owned());
 
     // Validate token
-    let _ = match utils::auth::validate_token(state.banned_tokens_store.clone(), &token).await {
+    let claims = match utils::auth::validate_token(
+        state.banned_tokens_store.clone(),
+        state.session_store.clone(),
+        state.key_ring.clone(),
+        &token,
+    )
+    .await
+    {
         Ok(claims) => claims,
         Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
     };
+    *user_id = UserId::parse(&claims.sub).ok();
+    let Ok(session_id) = RefreshTokenFamilyId::parse(claims.sid) else {
+        return (jar, Err(AuthAPIError::InvalidToken));
+    };
 
     // Add token to banned list
-    if let Err(e) = state
-        .banned_tokens_store
-        .write()
-        .await
-        .add_token(token)
-        .await
-    {
+    if let Err(e) = state.banned_tokens_store.add_token(token).await {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
-    // Remove jwt cookie
-    let jar = jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME));
+    // End the session, so that no rotated refresh token outlives it.
+    if let Err(e) = end_session(state, &session_id).await {
+        return (jar, Err(e));
+    }
+
+    // Remove jwt and refresh cookies
+    let jar = jar
+        .remove(cookie::Cookie::from(JWT_COOKIE_NAME))
+        .remove(cookie::Cookie::build(REFRESH_TOKEN_COOKIE_NAME).path(REFRESH_TOKEN_COOKIE_PATH));
 
     (jar, Ok(StatusCode::OK))
 }
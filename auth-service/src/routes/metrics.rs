@@ -0,0 +1,31 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+
+use crate::{
+    AppState,
+    domain::AuthAPIError,
+    utils::{
+        auth::validate_admin_api_key,
+        metrics::{self, METRICS_CONTENT_TYPE},
+    },
+};
+
+// Counters and latencies of the service, for Prometheus to scrape with the admin API key.
+#[tracing::instrument(name = "Metrics", skip_all)]
+pub async fn metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    validate_admin_api_key(&headers, state.settings.admin.api_key.as_ref())?;
+
+    let body = metrics::encode().map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)],
+        body,
+    ))
+}
@@ -1,12 +1,36 @@
+mod account;
+mod admin;
+mod audit;
+mod health;
+mod jwks;
 mod login;
 mod logout;
+mod metrics;
+mod password_reset;
+mod refresh;
+mod sessions;
+mod signing_keys;
 mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
 
 // re-export items from sub-modules
+pub use account::*;
+pub use admin::*;
+pub use audit::*;
+pub use health::*;
+pub use jwks::*;
 pub use login::*;
 pub use logout::*;
+pub use metrics::*;
+pub use password_reset::*;
+pub use refresh::*;
+pub use sessions::*;
+pub use signing_keys::*;
 pub use signup::*;
+pub use totp::*;
 pub use verify_2fa::*;
+pub use verify_email::*;
 pub use verify_token::*;
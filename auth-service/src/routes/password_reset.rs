@@ -0,0 +1,133 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{AuthAPIError, Email, Password, UserStoreError},
+    utils::auth::{
+        generate_password_reset_token, password_fingerprint, validate_password_reset_token,
+    },
+};
+
+use super::account::revoke_all_tokens;
+
+// Email a password reset link. The response never tells whether the account exists.
+#[tracing::instrument(name = "Request password reset", skip_all)]
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse_with_policy(request.email, state.settings.email.local_part_policy)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user = state.user_store.get_user(email.clone()).await;
+    match user {
+        // The email is sent in the background, so that the response time does not
+        // give away whether the account exists either.
+        Ok(user) => {
+            let email = send_password_reset_email(state.clone(), email, user.password);
+            state.background_tasks.spawn(email);
+        }
+        Err(UserStoreError::UserNotFound) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(PasswordResetResponse {
+        message: "If an account exists for this email, a password reset link has been sent."
+            .to_owned(),
+    });
+
+    Ok((StatusCode::ACCEPTED, response))
+}
+
+#[tracing::instrument(name = "Send password reset email", skip_all)]
+pub(crate) async fn send_password_reset_email(state: AppState, email: Email, password: Password) {
+    let token = match generate_password_reset_token(state.key_ring.clone(), &email, &password).await
+    {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::error!("failed to create password reset token: {:?}", e);
+            return;
+        }
+    };
+    let link = format!(
+        "{}?token={}",
+        state.settings.links.password_reset_url,
+        token.expose_secret()
+    );
+
+    let email_client = &state.email_client;
+    if let Err(e) = email_client
+        .send_email(
+            &email,
+            "Reset your password",
+            format!(
+                "Hi there, follow this link to choose a new password: {}\n\
+                 If you did not ask for it, you can ignore this email.",
+                link
+            )
+            .as_str(),
+        )
+        .await
+    {
+        tracing::error!("failed to send password reset email: {:?}", e);
+    }
+}
+
+// Set a new password from a reset link, and log the user out everywhere.
+#[tracing::instrument(name = "Confirm password reset", skip_all)]
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<ConfirmPasswordResetRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let password =
+        Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let claims = validate_password_reset_token(state.key_ring.clone(), &request.token)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+    let email = Email::parse_with_policy(
+        Secret::new(claims.sub),
+        state.settings.email.local_part_policy,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_store = &state.user_store;
+    let user = match user_store.get_user(email.clone()).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    // The password changed since the link was sent: it was already used.
+    if password_fingerprint(&user.password) != claims.pwd {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    user_store
+        .update_password(user.id, password)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // Whoever knew the old password may still hold a token.
+    revoke_all_tokens(&state, &user.id).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmPasswordResetRequest {
+    pub token: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
@@ -0,0 +1,105 @@
+use axum::{extract::State, http::StatusCode};
+use axum_extra::extract::{CookieJar, cookie};
+use chrono::Utc;
+use secrecy::Secret;
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{AuthAPIError, RefreshToken, RefreshTokenStoreError, SessionStoreError},
+    utils::{
+        auth::{generate_auth_cookie, generate_refresh_cookie},
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_PATH},
+    },
+};
+
+// Swap a refresh token for a new JWT, rotating the refresh token along the way.
+#[tracing::instrument(name = "Refresh", skip_all)]
+pub async fn refresh(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let Some(cookie) = jar.get(REFRESH_TOKEN_COOKIE_NAME) else {
+        return (jar, Err(AuthAPIError::MissingToken));
+    };
+    let Ok(token) = RefreshToken::parse(Secret::new(cookie.value().to_owned())) else {
+        return (jar, Err(AuthAPIError::InvalidToken));
+    };
+
+    // Presenting the token uses it up, even if it turns out to be refused below.
+    let refresh_token_store = &state.refresh_token_store;
+    let entry = match refresh_token_store.use_token(&token).await {
+        Ok(entry) => entry,
+        Err(RefreshTokenStoreError::TokenNotFound) => {
+            return (jar, Err(AuthAPIError::InvalidToken));
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    match refresh_token_store
+        .is_family_revoked(&entry.family_id)
+        .await
+    {
+        Ok(false) => {}
+        Ok(true) => return (remove_cookies(jar), Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // A rotated token was presented again: either it leaked, or the legitimate
+    // client is replaying it after someone else already did. Log the whole family out.
+    if entry.used {
+        tracing::warn!("refresh token reuse detected, revoking its family");
+        if let Err(e) = refresh_token_store.revoke_family(&entry.family_id).await {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+        return (remove_cookies(jar), Err(AuthAPIError::InvalidToken));
+    }
+
+    // The family is the session of the login, which must not have been revoked since.
+    // Tokens issued before sessions were recorded have none, and have to log in again.
+    match state
+        .session_store
+        .touch_session(&entry.family_id, Utc::now().timestamp())
+        .await
+    {
+        Ok(()) => {}
+        Err(SessionStoreError::SessionNotFound) => {
+            return (remove_cookies(jar), Err(AuthAPIError::InvalidToken));
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // Roles granted or removed since the last token show from now on.
+    let grants = match state.user_store.get_grants(entry.user_id).await {
+        Ok(grants) => grants,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+    let auth_cookie = match generate_auth_cookie(
+        state.key_ring.clone(),
+        &entry.user_id,
+        &grants,
+        &entry.family_id,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    };
+    let refresh_cookie = match generate_refresh_cookie(
+        state.refresh_token_store.clone(),
+        &entry.user_id,
+        entry.family_id,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    };
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(StatusCode::OK))
+}
+
+fn remove_cookies(jar: CookieJar) -> CookieJar {
+    jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME))
+        .remove(cookie::Cookie::build(REFRESH_TOKEN_COOKIE_NAME).path(REFRESH_TOKEN_COOKIE_PATH))
+}
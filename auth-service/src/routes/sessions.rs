@@ -0,0 +1,221 @@
+use axum::{
+    Json, async_trait,
+    extract::{ConnectInfo, FromRequestParts, Path, State},
+    http::{StatusCode, header::USER_AGENT, request::Parts},
+    response::IntoResponse,
+};
+use axum_extra::extract::{
+    CookieJar,
+    cookie::{self, Cookie},
+};
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{AuthAPIError, ClientInfo, RefreshTokenFamilyId, Session, SessionStoreError, UserId},
+    routes::revoke_all_tokens,
+    utils::{
+        auth::{generate_auth_cookie, generate_refresh_cookie, validate_auth_cookie},
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_PATH},
+    },
+};
+
+// List the active sessions of the logged in user, most recently used first.
+#[tracing::instrument(name = "List sessions", skip_all)]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (user_id, current_id) = authenticate_session(&state, &jar).await?;
+
+    let mut sessions = state
+        .session_store
+        .get_user_sessions(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+
+    let response: Vec<SessionResponse> = sessions
+        .into_iter()
+        .map(|session| SessionResponse::new(session, &current_id))
+        .collect();
+
+    Ok((StatusCode::OK, Json(response)))
+}
+
+// Log out one session of the user. Revoking the current session logs this client out.
+#[tracing::instrument(name = "Revoke session", skip_all)]
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let (user_id, current_id) = match authenticate_session(&state, &jar).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => return (jar, Err(e)),
+    };
+    let Ok(id) = RefreshTokenFamilyId::parse(id) else {
+        return (jar, Err(AuthAPIError::SessionNotFound));
+    };
+
+    // Other users' sessions are reported as missing, so their ids cannot be probed.
+    let session = state.session_store.get_session(&id).await;
+    match session {
+        Ok(session) if session.user_id == user_id => {}
+        Ok(_) | Err(SessionStoreError::SessionNotFound) => {
+            return (jar, Err(AuthAPIError::SessionNotFound));
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+    if let Err(e) = end_session(&state, &id).await {
+        return (jar, Err(e));
+    }
+
+    let jar = if id == current_id {
+        remove_cookies(jar)
+    } else {
+        jar
+    };
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
+
+// Log out every session of the user, this one included.
+#[tracing::instrument(name = "Revoke all sessions", skip_all)]
+pub async fn revoke_all_sessions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let user_id = match authenticate_session(&state, &jar).await {
+        Ok((user_id, _)) => user_id,
+        Err(e) => return (jar, Err(e)),
+    };
+    if let Err(e) = revoke_all_tokens(&state, &user_id).await {
+        return (jar, Err(e));
+    }
+
+    (remove_cookies(jar), Ok(StatusCode::NO_CONTENT))
+}
+
+// Record a new session for a login, and create its JWT and refresh cookies.
+pub(crate) async fn start_session(
+    state: &AppState,
+    user_id: &UserId,
+    client: ClientInfo,
+) -> Result<(Cookie<'static>, Cookie<'static>), AuthAPIError> {
+    let session = Session::new(*user_id, client, Utc::now().timestamp());
+    let session_id = session.id.clone();
+    state
+        .session_store
+        .add_session(session)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let grants = state
+        .user_store
+        .get_grants(*user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    let auth_cookie = generate_auth_cookie(state.key_ring.clone(), user_id, &grants, &session_id)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let refresh_cookie =
+        generate_refresh_cookie(state.refresh_token_store.clone(), user_id, session_id)
+            .await
+            .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((auth_cookie, refresh_cookie))
+}
+
+// Forget the session, which invalidates its JWTs, and revoke its refresh tokens.
+pub(crate) async fn end_session(
+    state: &AppState,
+    id: &RefreshTokenFamilyId,
+) -> Result<(), AuthAPIError> {
+    state
+        .session_store
+        .remove_session(id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    state
+        .refresh_token_store
+        .revoke_family(id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+// The user behind the JWT cookie, and the session it belongs to.
+pub(crate) async fn authenticate_session(
+    state: &AppState,
+    jar: &CookieJar,
+) -> Result<(UserId, RefreshTokenFamilyId), AuthAPIError> {
+    let claims = validate_auth_cookie(
+        jar,
+        state.banned_tokens_store.clone(),
+        state.session_store.clone(),
+        state.key_ring.clone(),
+    )
+    .await?;
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let session_id =
+        RefreshTokenFamilyId::parse(claims.sid).map_err(|_| AuthAPIError::InvalidToken)?;
+    Ok((user_id, session_id))
+}
+
+fn remove_cookies(jar: CookieJar) -> CookieJar {
+    jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME))
+        .remove(cookie::Cookie::build(REFRESH_TOKEN_COOKIE_NAME).path(REFRESH_TOKEN_COOKIE_PATH))
+}
+
+// The client starting a session: its address, and the user agent it announces.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(address) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .ok_or_else(|| AuthAPIError::UnexpectedError(eyre!("missing client address")))?;
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        Ok(ClientInfo {
+            ip: address.ip(),
+            user_agent,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SessionResponse {
+    pub id: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: i64,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    pub ip: String,
+    // Whether this is the session making the request.
+    pub current: bool,
+}
+
+impl SessionResponse {
+    pub(crate) fn new(session: Session, current_id: &RefreshTokenFamilyId) -> Self {
+        Self {
+            current: &session.id == current_id,
+            id: session.id.as_ref().to_owned(),
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            user_agent: session.client.user_agent,
+            ip: session.client.ip.to_string(),
+        }
+    }
+}
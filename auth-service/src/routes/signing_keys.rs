@@ -0,0 +1,49 @@
+use axum::{Json, extract::State, http::HeaderMap, response::IntoResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    domain::{AuditActor, AuditEventKind, AuthAPIError},
+    utils::{audit::Auditor, auth::validate_admin_api_key},
+};
+
+// Reload the JWT keys from their files, e.g. after a new signing key was put in place.
+// The previous signing key keeps verifying the tokens it signed until they expire.
+#[tracing::instrument(name = "Rotate signing keys", skip_all)]
+pub async fn rotate_signing_keys(
+    State(state): State<AppState>,
+    audit: Auditor,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    validate_admin_api_key(&headers, state.settings.admin.api_key.as_ref())?;
+
+    let result = rotate(&state).await;
+    audit
+        .record(
+            AuditEventKind::SigningKeyRotation,
+            AuditActor::AdminApiKey,
+            None,
+            &result,
+        )
+        .await;
+
+    result.map(Json)
+}
+
+async fn rotate(state: &AppState) -> Result<SigningKeysResponse, AuthAPIError> {
+    let mut key_ring = state.key_ring.write().await;
+    key_ring.reload().map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok(SigningKeysResponse {
+        signing_key_id: key_ring.signing_key().kid().to_owned(),
+        verification_key_ids: key_ring.verification_key_ids(),
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigningKeysResponse {
+    #[serde(rename = "signingKeyId")]
+    pub signing_key_id: String,
+    #[serde(rename = "verificationKeyIds")]
+    pub verification_key_ids: Vec<String>,
+}
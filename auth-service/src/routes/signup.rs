@@ -1,32 +1,66 @@
 use crate::{
     AppState,
-    domain::{AuthAPIError, User, email::Email, password::Password},
+    domain::{
+        AuditActor, AuditEventKind, AuthAPIError, TwoFAMethod, User, UserId, UserStoreError,
+        email::Email, password::Password,
+    },
+    utils::audit::Auditor,
 };
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use secrecy::Secret;
+
+use super::verify_email::spawn_verification_email;
 use serde::{Deserialize, Serialize};
 use tracing;
 
 #[tracing::instrument(name = "Signup", skip_all)]
 pub async fn signup(
     State(state): State<AppState>,
+    audit: Auditor,
     Json(request): Json<SignupRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
-    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let mut user_id = None;
+    let result = sign_up(&state, request, &mut user_id).await;
+    audit
+        .record(
+            AuditEventKind::Signup,
+            AuditActor::Anonymous,
+            user_id,
+            &result,
+        )
+        .await;
+
+    result
+}
+
+// `user_id` is set to the id of the new account, for the audit log.
+async fn sign_up(
+    state: &AppState,
+    request: SignupRequest,
+    user_id: &mut Option<UserId>,
+) -> Result<(StatusCode, Json<SignupResponse>), AuthAPIError> {
+    let email = Email::parse_with_policy(request.email, state.settings.email.local_part_policy)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
 
     let password =
         Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let user = User::new(email, password, request.requires_2fa);
-    let mut user_store = state.user_store.write().await;
-
-    if user_store.get_user(user.email.clone()).await.is_ok() {
-        return Err(AuthAPIError::UserAlreadyExists);
+    // Authenticator apps are enrolled once logged in, so signup only offers email codes.
+    let two_fa_method = match request.requires_2fa {
+        true => TwoFAMethod::Email,
+        false => TwoFAMethod::None,
+    };
+    let user = User::new(email.clone(), password, two_fa_method);
+    let id = user.id;
+    // The store refuses duplicates: checking first would let concurrent signups through.
+    match state.user_store.add_user(user).await {
+        Ok(()) => *user_id = Some(id),
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
     }
 
-    if let Err(e) = user_store.add_user(user).await {
-        return Err(AuthAPIError::UnexpectedError(e.into()));
-    }
+    // The account exists either way: a lost email can be sent again with /resend-verification.
+    spawn_verification_email(state, email);
 
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
@@ -0,0 +1,120 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{AuthAPIError, TotpSecret, TwoFACode, TwoFAMethod, UserId, UserStoreError},
+    utils::{auth::validate_auth_cookie, constants::TOTP_ISSUER},
+};
+
+// Start enrolling an authenticator app: the new secret stays pending until confirmed.
+#[tracing::instrument(name = "Enroll TOTP", skip_all)]
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let claims = validate_auth_cookie(
+        &jar,
+        state.banned_tokens_store.clone(),
+        state.session_store.clone(),
+        state.key_ring.clone(),
+    )
+    .await?;
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_store = &state.user_store;
+    let user = user_store
+        .get_user_by_id(user_id)
+        .await
+        .map_err(map_user_store_error)?;
+    if user.two_fa_method == TwoFAMethod::Totp {
+        return Err(AuthAPIError::TotpAlreadyEnabled);
+    }
+
+    let secret = TotpSecret::default();
+    let otpauth_uri = secret
+        .otpauth_uri(TOTP_ISSUER, &user.email)
+        .map_err(AuthAPIError::UnexpectedError)?;
+    user_store
+        .set_totp_secret(user_id, secret.clone())
+        .await
+        .map_err(map_user_store_error)?;
+
+    let response = Json(EnrollTotpResponse {
+        secret: secret.as_ref().expose_secret().to_owned(),
+        otpauth_uri,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Finish enrolling: a valid code proves the authenticator app holds the pending secret.
+#[tracing::instrument(name = "Confirm TOTP", skip_all)]
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConfirmTotpRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let claims = validate_auth_cookie(
+        &jar,
+        state.banned_tokens_store.clone(),
+        state.session_store.clone(),
+        state.key_ring.clone(),
+    )
+    .await?;
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user_store = &state.user_store;
+    let secret = user_store
+        .get_totp_secret(user_id)
+        .await
+        .map_err(map_user_store_error)?;
+
+    // The time step of the code is used up, as on login, so that the code cannot log
+    // in again while it is still displayed.
+    let valid = match secret
+        .verify(&code, state.settings.totp.skew)
+        .map_err(AuthAPIError::UnexpectedError)?
+    {
+        Some(step) => user_store
+            .use_totp_step(user_id, step)
+            .await
+            .map_err(map_user_store_error)?,
+        None => false,
+    };
+    if !valid {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    user_store
+        .set_two_fa_method(user_id, TwoFAMethod::Totp)
+        .await
+        .map_err(map_user_store_error)?;
+
+    Ok(StatusCode::OK)
+}
+
+fn map_user_store_error(e: UserStoreError) -> AuthAPIError {
+    match e {
+        // The user behind a valid token is gone, e.g. after the account was deleted.
+        UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+        UserStoreError::TotpSecretNotFound => AuthAPIError::InvalidCredentials,
+        e => AuthAPIError::UnexpectedError(e.into()),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnrollTotpResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    pub code: Secret<String>,
+}
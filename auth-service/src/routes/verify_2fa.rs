@@ -6,18 +6,50 @@ use tracing;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode},
-    utils,
+    domain::{
+        AuditActor, AuditEventKind, AuthAPIError, ClientInfo, Email, LoginAttemptId,
+        MAX_2FA_GUESSES, RateLimitKey, TwoFACode, TwoFAMethod, UserId,
+    },
+    routes::start_session,
+    utils::{
+        audit::Auditor,
+        rate_limit::{clear_failures, release, try_acquire},
+    },
 };
 
 #[tracing::instrument(name = "Verify 2FA", skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>,
+    client: ClientInfo,
+    audit: Auditor,
     jar: CookieJar,
     Json(request): Json<Verify2FARequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let mut user_id = None;
+    let (jar, result) = verify(&state, client, jar, request, &mut user_id).await;
+    audit
+        .record(
+            AuditEventKind::TwoFAVerification,
+            AuditActor::Anonymous,
+            user_id,
+            &result,
+        )
+        .await;
+
+    (jar, result)
+}
+
+// `user_id` is set as soon as the account is known, for the audit log.
+async fn verify(
+    state: &AppState,
+    client: ClientInfo,
+    jar: CookieJar,
+    request: Verify2FARequest,
+    user_id: &mut Option<UserId>,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
     // Parse request
-    let Ok(email) = Email::parse(request.email) else {
+    let Ok(email) = Email::parse_with_policy(request.email, state.settings.email.local_part_policy)
+    else {
         return (jar, Err(AuthAPIError::InvalidCredentials));
     };
     let Ok(login_attempt_id) = LoginAttemptId::parse(Secret::new(request.login_attempt_id)) else {
@@ -27,28 +59,137 @@ pub async fn verify_2fa(
         return (jar, Err(AuthAPIError::InvalidCredentials));
     };
 
-    // Validate code
-    let mut two_fa_code_store = state.two_fa_code_store.write().await;
-    let Ok(code_tuple) = two_fa_code_store.get_code(&email).await else {
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    let email_key = RateLimitKey::Email(email.clone());
+    let keys = [email_key.clone(), RateLimitKey::Ip(client.ip)];
+    let reservations = match try_acquire(state.rate_limit_store.clone(), &keys).await {
+        Ok(reservations) => reservations,
+        Err(e) => return (jar, Err(e)),
     };
-    if (login_attempt_id, two_fa_code) != code_tuple {
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
+
+    let id = match validate_code(
+        state,
+        &email,
+        login_attempt_id.clone(),
+        two_fa_code,
+        user_id,
+    )
+    .await
+    {
+        Ok(id) => id,
+        Err(e @ AuthAPIError::IncorrectCredentials) => return (jar, Err(e)),
+        // Only wrong codes count as failures.
+        Err(e) => {
+            let result = release(state.rate_limit_store.clone(), &reservations).await;
+            return (jar, result.and(Err(e)));
+        }
+    };
+    if let Err(e) = release(state.rate_limit_store.clone(), &reservations).await {
+        return (jar, Err(e));
     }
-    // Delete valid code after first use.
-    if let Err(err) = two_fa_code_store.remove_code(&email).await {
-        return (jar, Err(AuthAPIError::UnexpectedError(err.into())));
+    for key in [email_key, RateLimitKey::LoginAttempt(login_attempt_id)] {
+        if let Err(e) = clear_failures(state.rate_limit_store.clone(), &key).await {
+            return (jar, Err(e));
+        }
     }
 
     // Update cookie jar
-    let updated_jar = match utils::auth::generate_auth_cookie(&email) {
-        Ok(auth_cookie) => jar.add(auth_cookie),
-        Err(err) => {
-            return (jar, Err(AuthAPIError::UnexpectedError(err.into())));
-        }
+    let (auth_cookie, refresh_cookie) = match start_session(state, &id, client).await {
+        Ok(cookies) => cookies,
+        Err(e) => return (jar, Err(e)),
     };
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
+
+    (updated_jar, Ok(StatusCode::OK))
+}
+
+// Check the code against the pending login attempt, and use it up if it is valid.
+// Returns the id of the user logging in, which is also set in `user_id` once known.
+async fn validate_code(
+    state: &AppState,
+    email: &Email,
+    login_attempt_id: LoginAttemptId,
+    two_fa_code: TwoFACode,
+    user_id: &mut Option<UserId>,
+) -> Result<UserId, AuthAPIError> {
+    // Look up the user's second factor before checking the code.
+    let (id, totp_secret) = {
+        let user_store = &state.user_store;
+        let user = user_store
+            .get_user(email.clone())
+            .await
+            .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+        *user_id = Some(user.id);
+        let totp_secret = match user.two_fa_method {
+            TwoFAMethod::Totp => Some(
+                user_store
+                    .get_totp_secret(user.id)
+                    .await
+                    .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?,
+            ),
+            _ => None,
+        };
+        (user.id, totp_secret)
+    };
+
+    let two_fa_code_store = &state.two_fa_code_store;
+    let (expected_login_attempt_id, expected_code) = two_fa_code_store
+        .get_code(email)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    if login_attempt_id != expected_login_attempt_id {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+    // A login attempt only gets a few guesses, after which the user has to log in again.
+    // Each guess is counted before the code is checked, so that concurrent guesses
+    // cannot get past the limit.
+    let guesses = try_acquire(
+        state.rate_limit_store.clone(),
+        &[RateLimitKey::LoginAttempt(login_attempt_id)],
+    )
+    .await?
+    .iter()
+    .map(|reservation| reservation.failures.count)
+    .max()
+    .unwrap_or_default();
+    if guesses > MAX_2FA_GUESSES {
+        two_fa_code_store
+            .remove_code(email)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+    let is_valid_code = match totp_secret {
+        // A code seen by someone else stays valid for the whole skew window, so each
+        // time step is only accepted once (RFC 6238, section 5.2).
+        Some(secret) => match secret
+            .verify(&two_fa_code, state.settings.totp.skew)
+            .map_err(AuthAPIError::UnexpectedError)?
+        {
+            Some(step) => state
+                .user_store
+                .use_totp_step(id, step)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?,
+            None => false,
+        },
+        None => expected_code.is_some_and(|expected_code| two_fa_code == expected_code),
+    };
+    if !is_valid_code {
+        if guesses >= MAX_2FA_GUESSES {
+            two_fa_code_store
+                .remove_code(email)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        }
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+    // Delete valid code after first use.
+    two_fa_code_store
+        .remove_code(email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
 
-    (updated_jar, Ok(StatusCode::OK.into_response()))
+    Ok(id)
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,113 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use crate::{
+    AppState,
+    domain::{AuthAPIError, Email, UserStoreError},
+    utils::auth::{generate_email_verification_token, validate_email_verification_token},
+};
+
+// Mark the email of the user named by a verification link as verified.
+#[tracing::instrument(name = "Verify email", skip_all)]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyEmailRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let claims = validate_email_verification_token(state.key_ring.clone(), &request.token)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+    let email = Email::parse_with_policy(
+        Secret::new(claims.sub),
+        state.settings.email.local_part_policy,
+    )
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // The link names the address it was sent to: it no longer verifies a changed email.
+    let user_store = &state.user_store;
+    let result = match user_store.get_user(email).await {
+        Ok(user) => user_store.mark_email_verified(user.id).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Send a new verification link. The response never tells whether the account exists.
+#[tracing::instrument(name = "Resend verification email", skip_all)]
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    Json(request): Json<ResendVerificationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse_with_policy(request.email, state.settings.email.local_part_policy)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user = state.user_store.get_user(email.clone()).await;
+    match user {
+        Ok(user) if !user.verified => spawn_verification_email(&state, email),
+        Ok(_) | Err(UserStoreError::UserNotFound) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(ResendVerificationResponse {
+        message:
+            "If an unverified account exists for this email, a verification link has been sent."
+                .to_owned(),
+    });
+
+    Ok((StatusCode::ACCEPTED, response))
+}
+
+// Send the verification email in the background, so the response does not wait on it.
+pub(crate) fn spawn_verification_email(state: &AppState, email: Email) {
+    let state = state.clone();
+    state.background_tasks.clone().spawn(async move {
+        if let Err(e) = send_verification_email(&state, &email).await {
+            tracing::error!("failed to send verification email: {:?}", e);
+        }
+    });
+}
+
+#[tracing::instrument(name = "Send verification email", skip_all)]
+async fn send_verification_email(state: &AppState, email: &Email) -> Result<()> {
+    let token = generate_email_verification_token(state.key_ring.clone(), email).await?;
+    let link = format!(
+        "{}?token={}",
+        state.settings.links.email_verification_url,
+        token.expose_secret()
+    );
+
+    state
+        .email_client
+        .send_email(
+            email,
+            "Verify your email address",
+            format!(
+                "Hi there, follow this link to verify your email address: {}\n\
+                 If you did not create an account, you can ignore this email.",
+                link
+            )
+            .as_str(),
+        )
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ResendVerificationResponse {
+    pub message: String,
+}
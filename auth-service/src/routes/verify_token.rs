@@ -2,21 +2,64 @@ use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use secrecy::Secret;
 use serde::Deserialize;
 
-use crate::{AppState, domain::AuthAPIError, utils};
+use crate::{
+    AppState,
+    domain::{AuditActor, AuditEventKind, AuthAPIError, UserId},
+    utils::{self, audit::Auditor},
+};
 
+// Check a token, and optionally that it grants a permission, for other services.
 #[tracing::instrument(name = "Verify token", skip_all)]
 pub async fn verify_token(
     State(state): State<AppState>,
+    audit: Auditor,
     Json(request): Json<VerifyTokenRequest>,
 ) -> impl IntoResponse {
+    let (user_id, result) = verify(state, request).await;
+    // The caller is another service, on behalf of the user named by the token.
+    audit
+        .record(
+            AuditEventKind::TokenVerification,
+            AuditActor::Anonymous,
+            user_id,
+            &result,
+        )
+        .await;
+
+    result
+}
+
+// Also returns the subject of a valid token, for the audit log.
+async fn verify(
+    state: AppState,
+    request: VerifyTokenRequest,
+) -> (Option<UserId>, Result<StatusCode, AuthAPIError>) {
     let token = Secret::new(request.token);
-    match utils::auth::validate_token(state.banned_tokens_store, &token).await {
-        Ok(_) => Ok(StatusCode::OK),
-        Err(_) => Err(AuthAPIError::InvalidToken),
-    }
+    let claims = match utils::auth::validate_token(
+        state.banned_tokens_store,
+        state.session_store,
+        state.key_ring,
+        &token,
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(_) => return (None, Err(AuthAPIError::InvalidToken)),
+    };
+    let user_id = UserId::parse(&claims.sub).ok();
+
+    let result = match request.required_permission {
+        Some(permission) if !claims.has_permission(&permission) => {
+            Err(AuthAPIError::MissingPermission)
+        }
+        _ => Ok(StatusCode::OK),
+    };
+    (user_id, result)
 }
 
 #[derive(Deserialize)]
 pub struct VerifyTokenRequest {
     pub token: String,
+    #[serde(rename = "requiredPermission")]
+    pub required_permission: Option<String>,
 }
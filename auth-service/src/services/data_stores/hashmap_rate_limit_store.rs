@@ -0,0 +1,162 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+use crate::domain::{
+    Failures, RateLimitKey,
+    data_stores::{RateLimitStore, RateLimitStoreError},
+};
+
+// Count failures in memory. Also used by `RedisRateLimitStore` while Redis is unavailable.
+#[derive(Default)]
+pub struct HashmapRateLimitStore {
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl HashmapRateLimitStore {
+    // Failures of the key that have not expired at `now`, without counting an attempt.
+    pub fn failures(&self, key: &RateLimitKey, now: i64) -> Option<Failures> {
+        self.failures
+            .lock()
+            .get(&key.name())
+            .filter(|failures| !failures.is_expired(now))
+            .copied()
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for HashmapRateLimitStore {
+    async fn reserve_attempt(
+        &self,
+        key: &RateLimitKey,
+        now: i64,
+    ) -> Result<Option<Failures>, RateLimitStoreError> {
+        let mut all_failures = self.failures.lock();
+        // Forget expired failures, so that the map does not grow forever.
+        all_failures.retain(|_, failures| !failures.is_expired(now));
+
+        let failures = all_failures.entry(key.name()).or_insert(Failures {
+            count: 0,
+            last_failure_at: now,
+        });
+        let previous = (failures.count > 0).then_some(*failures);
+        failures.count += 1;
+        failures.last_failure_at = now;
+        Ok(previous)
+    }
+
+    async fn release_attempt(
+        &self,
+        key: &RateLimitKey,
+        previous: Option<Failures>,
+    ) -> Result<(), RateLimitStoreError> {
+        let name = key.name();
+        let mut all_failures = self.failures.lock();
+        let Some(failures) = all_failures.get_mut(&name) else {
+            return Ok(());
+        };
+        failures.count = failures.count.saturating_sub(1);
+        if let Some(previous) = previous.filter(|previous| previous.count == failures.count) {
+            *failures = previous;
+        }
+        if failures.count == 0 {
+            all_failures.remove(&name);
+        }
+        Ok(())
+    }
+
+    async fn clear_failures(&self, key: &RateLimitKey) -> Result<(), RateLimitStoreError> {
+        self.failures.lock().remove(&key.name());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::FAILURE_TTL_SECONDS;
+    use chrono::Utc;
+
+    fn key() -> RateLimitKey {
+        RateLimitKey::Ip("127.0.0.1".parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_reserve_and_clear_attempts() {
+        let store = HashmapRateLimitStore::default();
+        let now = Utc::now().timestamp();
+        assert_eq!(store.reserve_attempt(&key(), now - 1).await.unwrap(), None);
+
+        let previous = store.reserve_attempt(&key(), now).await.unwrap();
+        assert_eq!(
+            previous,
+            Some(Failures {
+                count: 1,
+                last_failure_at: now - 1
+            })
+        );
+        assert_eq!(
+            store.failures(&key(), now),
+            Some(Failures {
+                count: 2,
+                last_failure_at: now
+            })
+        );
+
+        store.clear_failures(&key()).await.unwrap();
+        assert_eq!(store.failures(&key(), now), None);
+    }
+
+    #[tokio::test]
+    async fn test_released_attempts_are_not_counted() {
+        let store = HashmapRateLimitStore::default();
+        let now = Utc::now().timestamp();
+        store.reserve_attempt(&key(), now).await.unwrap();
+        let previous = store.reserve_attempt(&key(), now).await.unwrap();
+        store.release_attempt(&key(), previous).await.unwrap();
+        assert_eq!(store.failures(&key(), now).unwrap().count, 1);
+
+        // Another attempt was counted in the meantime, and still is.
+        let previous = store.reserve_attempt(&key(), now).await.unwrap();
+        store.reserve_attempt(&key(), now + 1).await.unwrap();
+        store.release_attempt(&key(), previous).await.unwrap();
+        assert_eq!(
+            store.failures(&key(), now),
+            Some(Failures {
+                count: 2,
+                last_failure_at: now + 1
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_released_attempt_after_a_failure_keeps_the_time_of_the_failure() {
+        let store = HashmapRateLimitStore::default();
+        let now = Utc::now().timestamp();
+        store.reserve_attempt(&key(), now - 10).await.unwrap();
+
+        let previous = store.reserve_attempt(&key(), now).await.unwrap();
+        store.release_attempt(&key(), previous).await.unwrap();
+        assert_eq!(
+            store.failures(&key(), now),
+            Some(Failures {
+                count: 1,
+                last_failure_at: now - 10
+            })
+        );
+
+        store.release_attempt(&key(), None).await.unwrap();
+        assert_eq!(store.failures(&key(), now), None);
+    }
+
+    #[tokio::test]
+    async fn test_expired_failures_are_forgotten() {
+        let store = HashmapRateLimitStore::default();
+        let now = Utc::now().timestamp();
+        let long_ago = now - FAILURE_TTL_SECONDS;
+        store.reserve_attempt(&key(), long_ago).await.unwrap();
+        assert_eq!(store.failures(&key(), now), None);
+
+        assert_eq!(store.reserve_attempt(&key(), now).await.unwrap(), None);
+        assert_eq!(store.failures(&key(), now).unwrap().count, 1);
+    }
+}
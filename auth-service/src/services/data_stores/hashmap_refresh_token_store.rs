@@ -0,0 +1,160 @@
+use std::collections::{HashMap, HashSet};
+
+use parking_lot::RwLock;
+use secrecy::ExposeSecret;
+
+use crate::domain::{
+    UserId,
+    data_stores::{
+        RefreshToken, RefreshTokenEntry, RefreshTokenFamilyId, RefreshTokenStore,
+        RefreshTokenStoreError,
+    },
+};
+
+#[derive(Default)]
+pub struct HashmapRefreshTokenStore {
+    tokens: RwLock<HashMap<String, RefreshTokenEntry>>,
+    revoked_families: RwLock<HashSet<RefreshTokenFamilyId>>,
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for HashmapRefreshTokenStore {
+    async fn add_token(
+        &self,
+        token: RefreshToken,
+        entry: RefreshTokenEntry,
+    ) -> Result<(), RefreshTokenStoreError> {
+        self.tokens
+            .write()
+            .insert(token.as_ref().expose_secret().to_owned(), entry);
+        Ok(())
+    }
+
+    async fn get_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenEntry, RefreshTokenStoreError> {
+        self.tokens
+            .read()
+            .get(token.as_ref().expose_secret())
+            .cloned()
+            .ok_or(RefreshTokenStoreError::TokenNotFound)
+    }
+
+    async fn use_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenEntry, RefreshTokenStoreError> {
+        let mut tokens = self.tokens.write();
+        let entry = tokens
+            .get_mut(token.as_ref().expose_secret())
+            .ok_or(RefreshTokenStoreError::TokenNotFound)?;
+        let previous = entry.clone();
+        entry.used = true;
+        Ok(previous)
+    }
+
+    async fn revoke_family(
+        &self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        self.revoked_families.write().insert(family_id.clone());
+        Ok(())
+    }
+
+    async fn is_family_revoked(
+        &self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<bool, RefreshTokenStoreError> {
+        Ok(self.revoked_families.read().contains(family_id))
+    }
+
+    async fn revoke_all_families(&self, user_id: &UserId) -> Result<(), RefreshTokenStoreError> {
+        let tokens = self.tokens.read();
+        let families = tokens
+            .values()
+            .filter(|entry| &entry.user_id == user_id)
+            .map(|entry| entry.family_id.clone());
+        self.revoked_families.write().extend(families);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> RefreshTokenEntry {
+        RefreshTokenEntry::new(UserId::default(), RefreshTokenFamilyId::default())
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_token() {
+        let store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        let entry = entry();
+
+        let result = store.get_token(&token).await;
+        assert_eq!(result.unwrap_err(), RefreshTokenStoreError::TokenNotFound);
+
+        store
+            .add_token(token.clone(), entry.clone())
+            .await
+            .expect("add token failed");
+        assert_eq!(store.get_token(&token).await.unwrap(), entry);
+    }
+
+    #[tokio::test]
+    async fn test_use_token() {
+        let store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+
+        let result = store.use_token(&token).await;
+        assert_eq!(result.unwrap_err(), RefreshTokenStoreError::TokenNotFound);
+
+        store.add_token(token.clone(), entry()).await.unwrap();
+        assert!(!store.use_token(&token).await.unwrap().used);
+        assert!(store.use_token(&token).await.unwrap().used);
+        assert!(store.get_token(&token).await.unwrap().used);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let store = HashmapRefreshTokenStore::default();
+        let family_id = RefreshTokenFamilyId::default();
+
+        assert!(!store.is_family_revoked(&family_id).await.unwrap());
+        store.revoke_family(&family_id).await.unwrap();
+        assert!(store.is_family_revoked(&family_id).await.unwrap());
+        assert!(
+            !store
+                .is_family_revoked(&RefreshTokenFamilyId::default())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all_families() {
+        let store = HashmapRefreshTokenStore::default();
+        let first = entry();
+        let second = RefreshTokenEntry::new(first.user_id, RefreshTokenFamilyId::default());
+        let other_user = RefreshTokenEntry::new(UserId::default(), RefreshTokenFamilyId::default());
+        for entry in [&first, &second, &other_user] {
+            store
+                .add_token(RefreshToken::default(), entry.clone())
+                .await
+                .unwrap();
+        }
+
+        store.revoke_all_families(&first.user_id).await.unwrap();
+        assert!(store.is_family_revoked(&first.family_id).await.unwrap());
+        assert!(store.is_family_revoked(&second.family_id).await.unwrap());
+        assert!(
+            !store
+                .is_family_revoked(&other_user.family_id)
+                .await
+                .unwrap()
+        );
+    }
+}
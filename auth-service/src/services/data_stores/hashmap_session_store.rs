@@ -0,0 +1,134 @@
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+use crate::domain::{
+    Session, UserId,
+    data_stores::{RefreshTokenFamilyId, SessionStore, SessionStoreError},
+};
+
+#[derive(Default)]
+pub struct HashmapSessionStore {
+    sessions: RwLock<HashMap<RefreshTokenFamilyId, Session>>,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for HashmapSessionStore {
+    async fn add_session(&self, session: Session) -> Result<(), SessionStoreError> {
+        self.sessions.write().insert(session.id.clone(), session);
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &RefreshTokenFamilyId) -> Result<Session, SessionStoreError> {
+        self.sessions
+            .read()
+            .get(id)
+            .cloned()
+            .ok_or(SessionStoreError::SessionNotFound)
+    }
+
+    async fn get_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError> {
+        Ok(self
+            .sessions
+            .read()
+            .values()
+            .filter(|session| &session.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn touch_session(
+        &self,
+        id: &RefreshTokenFamilyId,
+        now: i64,
+    ) -> Result<(), SessionStoreError> {
+        let mut sessions = self.sessions.write();
+        let session = sessions
+            .get_mut(id)
+            .ok_or(SessionStoreError::SessionNotFound)?;
+        session.last_seen_at = now;
+        Ok(())
+    }
+
+    async fn remove_session(&self, id: &RefreshTokenFamilyId) -> Result<(), SessionStoreError> {
+        self.sessions.write().remove(id);
+        Ok(())
+    }
+
+    async fn remove_user_sessions(&self, user_id: &UserId) -> Result<(), SessionStoreError> {
+        self.sessions
+            .write()
+            .retain(|_, session| &session.user_id != user_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ClientInfo;
+
+    fn session(user_id: UserId) -> Session {
+        let client = ClientInfo {
+            ip: "127.0.0.1".parse().unwrap(),
+            user_agent: Some("test".to_owned()),
+        };
+        Session::new(user_id, client, 1_000)
+    }
+
+    #[tokio::test]
+    async fn test_add_get_and_remove_session() {
+        let store = HashmapSessionStore::default();
+        let session = session(UserId::default());
+
+        let result = store.get_session(&session.id).await;
+        assert_eq!(result.unwrap_err(), SessionStoreError::SessionNotFound);
+
+        store.add_session(session.clone()).await.unwrap();
+        assert_eq!(store.get_session(&session.id).await.unwrap(), session);
+
+        store.remove_session(&session.id).await.unwrap();
+        let result = store.get_session(&session.id).await;
+        assert_eq!(result.unwrap_err(), SessionStoreError::SessionNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_touch_session() {
+        let store = HashmapSessionStore::default();
+        let session = session(UserId::default());
+
+        let result = store.touch_session(&session.id, 2_000).await;
+        assert_eq!(result.unwrap_err(), SessionStoreError::SessionNotFound);
+
+        store.add_session(session.clone()).await.unwrap();
+        store.touch_session(&session.id, 2_000).await.unwrap();
+        let touched = store.get_session(&session.id).await.unwrap();
+        assert_eq!(touched.created_at, 1_000);
+        assert_eq!(touched.last_seen_at, 2_000);
+    }
+
+    #[tokio::test]
+    async fn test_user_sessions() {
+        let store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+        let first = session(user_id);
+        let second = session(user_id);
+        let other_user = session(UserId::default());
+        for session in [&first, &second, &other_user] {
+            store.add_session(session.clone()).await.unwrap();
+        }
+
+        let sessions = store.get_user_sessions(&user_id).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.contains(&first) && sessions.contains(&second));
+
+        store.remove_user_sessions(&user_id).await.unwrap();
+        assert!(
+            store
+                .get_user_sessions(&user_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(store.get_session(&other_user.id).await.unwrap(), other_user);
+    }
+}
@@ -1,3 +1,4 @@
+use parking_lot::RwLock;
 use std::collections::HashMap;
 
 use crate::domain::{
@@ -7,26 +8,27 @@ use crate::domain::{
 
 #[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: RwLock<HashMap<Email, (LoginAttemptId, Option<TwoFACode>)>>,
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
-        &mut self,
+        &self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code: Option<TwoFACode>,
     ) -> Result<(), TwoFACodeStoreError> {
         // match self.codes.insert(email, (login_attempt_id, code)) {
         //     None => Ok(()),
         //     _ => Err(TwoFACodeStoreError::UnexpectedError),
         // }
-        self.codes.insert(email, (login_attempt_id, code));
+        self.codes.write().insert(email, (login_attempt_id, code));
         Ok(())
     }
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+    async fn remove_code(&self, email: &Email) -> Result<(), TwoFACodeStoreError> {
         self.codes
+            .write()
             .remove(email)
             .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
         Ok(())
@@ -34,12 +36,12 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let code = self
-            .codes
+    ) -> Result<(LoginAttemptId, Option<TwoFACode>), TwoFACodeStoreError> {
+        self.codes
+            .read()
             .get(email)
-            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
-        Ok(code.clone())
+            .cloned()
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
     }
 }
 
@@ -51,49 +53,49 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_code() {
-        let mut store = HashmapTwoFACodeStore::default();
+        let store = HashmapTwoFACodeStore::default();
         let email =
             Email::parse(Secret::new("test@example.com".to_owned())).expect("invalid email");
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         store
-            .add_code(email.clone(), login_attempt_id, code)
+            .add_code(email.clone(), login_attempt_id, Some(code))
             .await
             .expect("add code failed");
-        assert!(store.codes.contains_key(&email));
+        assert!(store.codes.read().contains_key(&email));
     }
 
     #[tokio::test]
     async fn test_remove_code() {
-        let mut store = HashmapTwoFACodeStore::default();
+        let store = HashmapTwoFACodeStore::default();
         let email =
             Email::parse(Secret::new("test@example.com".to_owned())).expect("invalid email");
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         store
-            .add_code(email.clone(), login_attempt_id, code)
+            .add_code(email.clone(), login_attempt_id, Some(code))
             .await
             .unwrap();
         store.remove_code(&email).await.unwrap();
-        assert!(!store.codes.contains_key(&email));
+        assert!(!store.codes.read().contains_key(&email));
     }
 
     #[tokio::test]
     async fn test_get_code() {
-        let mut store = HashmapTwoFACodeStore::default();
+        let store = HashmapTwoFACodeStore::default();
         let email =
             Email::parse(Secret::new("test@example.com".to_owned())).expect("invalid email");
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         store
-            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .add_code(email.clone(), login_attempt_id.clone(), Some(code.clone()))
             .await
             .unwrap();
         let (id, c) = store.get_code(&email).await.unwrap();
         assert_eq!(id, login_attempt_id);
-        assert_eq!(c, code);
+        assert_eq!(c, Some(code));
     }
 }
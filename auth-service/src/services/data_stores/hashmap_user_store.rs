@@ -1,41 +1,262 @@
-use std::collections::HashMap;
+use parking_lot::RwLock;
+use std::collections::{BTreeSet, HashMap};
 
-use crate::domain::User;
 use crate::domain::data_stores::{UserStore, UserStoreError};
 use crate::domain::email::Email;
 use crate::domain::password::Password;
+use crate::domain::{
+    ADMIN_ROLE, AUDIT_READ_PERMISSION, Grants, Permission, Role, TotpSecret, TwoFAMethod,
+    USERS_READ_PERMISSION, USERS_WRITE_PERMISSION, User, UserExport, UserId, UserPage, UserQuery,
+};
+use secrecy::ExposeSecret;
 
 // Store users in a HashMap (in memory) for now.
+// The maps sit behind one lock, so that they always agree with each other.
 #[derive(Default)]
 pub struct HashmapUserStore {
-    users: HashMap<Email, User>,
+    inner: RwLock<Users>,
+}
+
+struct Users {
+    users: HashMap<UserId, User>,
+    ids: HashMap<Email, UserId>,
+    totp_secrets: HashMap<UserId, TotpSecret>,
+    totp_last_steps: HashMap<UserId, u64>,
+    user_roles: HashMap<UserId, BTreeSet<Role>>,
+    role_permissions: HashMap<Role, BTreeSet<Permission>>,
+}
+
+impl Default for Users {
+    // Knows the same roles as a migrated database.
+    fn default() -> Self {
+        let admin_permissions = [
+            USERS_READ_PERMISSION,
+            USERS_WRITE_PERMISSION,
+            AUDIT_READ_PERMISSION,
+        ]
+        .into_iter()
+        .map(|permission| Permission::parse(permission).expect("valid permission"))
+        .collect();
+        let role_permissions = HashMap::from([(
+            Role::parse(ADMIN_ROLE).expect("valid role"),
+            admin_permissions,
+        )]);
+
+        Self {
+            users: HashMap::new(),
+            ids: HashMap::new(),
+            totp_secrets: HashMap::new(),
+            totp_last_steps: HashMap::new(),
+            user_roles: HashMap::new(),
+            role_permissions,
+        }
+    }
+}
+
+impl Users {
+    fn get_mut(&mut self, id: UserId) -> Result<&mut User, UserStoreError> {
+        self.users.get_mut(&id).ok_or(UserStoreError::UserNotFound)
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for HashmapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        if self.users.contains_key(&user.email) {
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let mut inner = self.inner.write();
+        if inner.ids.contains_key(&user.email) || inner.users.contains_key(&user.id) {
             Err(UserStoreError::UserAlreadyExists)
         } else {
-            self.users.insert(user.email.clone(), user);
+            inner.ids.insert(user.email.clone(), user.id);
+            inner.users.insert(user.id, user);
             Ok(())
         }
     }
 
     async fn get_user(&self, email: Email) -> Result<User, UserStoreError> {
-        match self.users.get(&email) {
+        let inner = self.inner.read();
+        match inner.ids.get(&email).and_then(|id| inner.users.get(id)) {
             Some(user) => Ok(user.clone()),
             None => Err(UserStoreError::UserNotFound),
         }
     }
 
-    async fn validate_user(&self, email: Email, password: Password) -> Result<(), UserStoreError> {
-        match self.users.get(&email) {
-            Some(user) if user.password == password => Ok(()),
-            Some(_user) => Err(UserStoreError::InvalidCredentials),
+    async fn get_user_by_id(&self, id: UserId) -> Result<User, UserStoreError> {
+        match self.inner.read().users.get(&id) {
+            Some(user) => Ok(user.clone()),
             None => Err(UserStoreError::UserNotFound),
         }
     }
+
+    async fn validate_user(&self, email: Email, password: Password) -> Result<(), UserStoreError> {
+        match self.get_user(email).await {
+            Ok(user) if user.password == password => Ok(()),
+            Ok(_user) => Err(UserStoreError::InvalidCredentials),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete_user(&self, id: UserId) -> Result<(), UserStoreError> {
+        let mut inner = self.inner.write();
+        let user = inner
+            .users
+            .remove(&id)
+            .ok_or(UserStoreError::UserNotFound)?;
+        inner.ids.remove(&user.email);
+        inner.totp_secrets.remove(&id);
+        inner.totp_last_steps.remove(&id);
+        inner.user_roles.remove(&id);
+        Ok(())
+    }
+
+    async fn export_user(&self, id: UserId) -> Result<UserExport, UserStoreError> {
+        let inner = self.inner.read();
+        let user = inner.users.get(&id).ok_or(UserStoreError::UserNotFound)?;
+        Ok(UserExport {
+            id,
+            email: user.email.clone(),
+            two_fa_method: user.two_fa_method,
+            verified: user.verified,
+            has_totp_secret: inner.totp_secrets.contains_key(&id),
+            locked: user.locked,
+        })
+    }
+
+    async fn update_password(&self, id: UserId, password: Password) -> Result<(), UserStoreError> {
+        self.inner.write().get_mut(id)?.password = password;
+        Ok(())
+    }
+
+    async fn update_email(&self, id: UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let mut inner = self.inner.write();
+        if inner.ids.contains_key(&new_email) {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+        let user = inner.get_mut(id)?;
+        let email = std::mem::replace(&mut user.email, new_email.clone());
+        inner.ids.remove(&email);
+        inner.ids.insert(new_email, id);
+        Ok(())
+    }
+
+    async fn set_two_fa_method(
+        &self,
+        id: UserId,
+        method: TwoFAMethod,
+    ) -> Result<(), UserStoreError> {
+        self.inner.write().get_mut(id)?.two_fa_method = method;
+        Ok(())
+    }
+
+    async fn mark_email_verified(&self, id: UserId) -> Result<(), UserStoreError> {
+        self.inner.write().get_mut(id)?.verified = true;
+        Ok(())
+    }
+
+    async fn set_totp_secret(&self, id: UserId, secret: TotpSecret) -> Result<(), UserStoreError> {
+        let mut inner = self.inner.write();
+        inner.get_mut(id)?;
+        inner.totp_secrets.insert(id, secret);
+        Ok(())
+    }
+
+    async fn get_totp_secret(&self, id: UserId) -> Result<TotpSecret, UserStoreError> {
+        let inner = self.inner.read();
+        if !inner.users.contains_key(&id) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        inner
+            .totp_secrets
+            .get(&id)
+            .cloned()
+            .ok_or(UserStoreError::TotpSecretNotFound)
+    }
+
+    async fn use_totp_step(&self, id: UserId, step: u64) -> Result<bool, UserStoreError> {
+        let mut inner = self.inner.write();
+        inner.get_mut(id)?;
+        if inner
+            .totp_last_steps
+            .get(&id)
+            .is_some_and(|last| *last >= step)
+        {
+            return Ok(false);
+        }
+        inner.totp_last_steps.insert(id, step);
+        Ok(true)
+    }
+
+    async fn get_grants(&self, id: UserId) -> Result<Grants, UserStoreError> {
+        let inner = self.inner.read();
+        let Some(roles) = inner.user_roles.get(&id) else {
+            return Ok(Grants::default());
+        };
+        let permissions: BTreeSet<&Permission> = roles
+            .iter()
+            .filter_map(|role| inner.role_permissions.get(role))
+            .flatten()
+            .collect();
+        Ok(Grants {
+            roles: roles.iter().cloned().collect(),
+            permissions: permissions.into_iter().cloned().collect(),
+        })
+    }
+
+    async fn add_role(&self, id: UserId, role: Role) -> Result<(), UserStoreError> {
+        let mut inner = self.inner.write();
+        inner.get_mut(id)?;
+        if !inner.role_permissions.contains_key(&role) {
+            return Err(UserStoreError::RoleNotFound);
+        }
+        inner.user_roles.entry(id).or_default().insert(role);
+        Ok(())
+    }
+
+    async fn remove_role(&self, id: UserId, role: Role) -> Result<(), UserStoreError> {
+        let mut inner = self.inner.write();
+        inner.get_mut(id)?;
+        if let Some(roles) = inner.user_roles.get_mut(&id) {
+            roles.remove(&role);
+        }
+        Ok(())
+    }
+
+    async fn list_users(&self, query: UserQuery) -> Result<UserPage, UserStoreError> {
+        let search = query.search.map(|search| search.to_lowercase());
+        let inner = self.inner.read();
+        let mut users: Vec<&User> = inner
+            .users
+            .values()
+            .filter(|user| match &search {
+                Some(search) => user
+                    .email
+                    .as_ref()
+                    .expose_secret()
+                    .contains(search.as_str()),
+                None => true,
+            })
+            .collect();
+        users.sort_by(|a, b| {
+            a.email
+                .as_ref()
+                .expose_secret()
+                .cmp(b.email.as_ref().expose_secret())
+        });
+
+        Ok(UserPage {
+            total: users.len() as u64,
+            users: users
+                .into_iter()
+                .skip(query.offset as usize)
+                .take(query.limit as usize)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    async fn set_locked(&self, id: UserId, locked: bool) -> Result<(), UserStoreError> {
+        self.inner.write().get_mut(id)?.locked = locked;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -45,17 +266,17 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_user() {
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
 
         let user = User::new(
             Email::parse(Secret::from("a@example.com".to_owned())).unwrap(),
             Password::parse(Secret::new("password123".to_owned())).unwrap(),
-            false,
+            TwoFAMethod::None,
         );
         let duplicate = User::new(
             Email::parse(Secret::from("a@example.com".to_owned())).unwrap(),
             Password::parse(Secret::new("password123".to_owned())).unwrap(),
-            false,
+            TwoFAMethod::None,
         );
 
         assert_eq!(store.add_user(user).await, Ok(()));
@@ -72,13 +293,13 @@ mod tests {
         let password =
             Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
 
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
 
         let result = store.get_user(email.clone()).await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
 
-        let sample = User::new(email.clone(), password, false);
+        let sample = User::new(email.clone(), password, TwoFAMethod::None);
         assert_eq!(store.add_user(sample).await, Ok(()));
 
         let result = store.get_user(email.clone()).await;
@@ -87,15 +308,31 @@ mod tests {
         assert_eq!(result_email, email);
     }
 
+    #[tokio::test]
+    async fn test_get_user_by_id() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+
+        let result = store.get_user_by_id(UserId::default()).await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email, password, TwoFAMethod::None);
+        assert!(store.add_user(user.clone()).await.is_ok());
+        assert_eq!(store.get_user_by_id(user.id).await.unwrap(), user);
+    }
+
     #[tokio::test]
     async fn test_validate_user() {
         // Store a valid user.
-        let mut store = HashmapUserStore::default();
+        let store = HashmapUserStore::default();
         let email =
             Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
         let password = Password::parse(Secret::new("good-password".to_owned()))
             .expect("Invalid test password");
-        let valid_user = User::new(email.clone(), password.clone(), false);
+        let valid_user = User::new(email.clone(), password.clone(), TwoFAMethod::None);
         assert!(store.add_user(valid_user).await.is_ok());
 
         // ok
@@ -117,4 +354,346 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
     }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+
+        let result = store.delete_user(UserId::default()).await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), password, TwoFAMethod::None);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        assert!(
+            store
+                .set_totp_secret(id, TotpSecret::default())
+                .await
+                .is_ok()
+        );
+        assert!(store.delete_user(id).await.is_ok());
+        assert_eq!(
+            store.get_user(email.clone()).await.unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+        assert_eq!(
+            store.get_totp_secret(id).await.unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_user() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+
+        let result = store.export_user(UserId::default()).await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), password, TwoFAMethod::Email);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        let export = store.export_user(id).await.unwrap();
+        assert_eq!(
+            export,
+            UserExport {
+                id,
+                email: email.clone(),
+                two_fa_method: TwoFAMethod::Email,
+                verified: false,
+                has_totp_secret: false,
+                locked: false,
+            }
+        );
+
+        assert!(
+            store
+                .set_totp_secret(id, TotpSecret::default())
+                .await
+                .is_ok()
+        );
+        assert!(store.export_user(id).await.unwrap().has_totp_secret);
+
+        assert!(store.set_locked(id, true).await.is_ok());
+        assert!(store.export_user(id).await.unwrap().locked);
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let old_password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+        let new_password =
+            Password::parse(Secret::new("new-password".to_owned())).expect("Invalid test password");
+
+        let result = store
+            .update_password(UserId::default(), new_password.clone())
+            .await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), old_password.clone(), TwoFAMethod::None);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        assert!(
+            store
+                .update_password(id, new_password.clone())
+                .await
+                .is_ok()
+        );
+        assert!(
+            store
+                .validate_user(email.clone(), new_password)
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            store.validate_user(email, old_password).await.unwrap_err(),
+            UserStoreError::InvalidCredentials
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_email() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let new_email =
+            Email::parse(Secret::from("b@example.com".to_owned())).expect("Invalid test email");
+        let taken_email =
+            Email::parse(Secret::from("c@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+        let secret = TotpSecret::default();
+
+        let result = store
+            .update_email(UserId::default(), new_email.clone())
+            .await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), password.clone(), TwoFAMethod::Totp);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        assert!(store.set_totp_secret(id, secret.clone()).await.is_ok());
+        let other = User::new(taken_email.clone(), password.clone(), TwoFAMethod::None);
+        assert!(store.add_user(other).await.is_ok());
+
+        let result = store.update_email(id, taken_email).await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserAlreadyExists);
+
+        assert!(store.update_email(id, new_email.clone()).await.is_ok());
+        assert_eq!(
+            store.get_user(email).await.unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+        let user = store.get_user(new_email.clone()).await.unwrap();
+        assert_eq!(user.email, new_email);
+        assert_eq!(user.two_fa_method, TwoFAMethod::Totp);
+        assert!(
+            store
+                .validate_user(new_email.clone(), password)
+                .await
+                .is_ok()
+        );
+        assert_eq!(store.get_totp_secret(id).await.unwrap(), secret);
+    }
+
+    #[tokio::test]
+    async fn test_set_two_fa_method() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+
+        let result = store
+            .set_two_fa_method(UserId::default(), TwoFAMethod::Totp)
+            .await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), password, TwoFAMethod::Email);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        assert!(store.set_two_fa_method(id, TwoFAMethod::Totp).await.is_ok());
+        let user = store.get_user(email).await.unwrap();
+        assert_eq!(user.two_fa_method, TwoFAMethod::Totp);
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+
+        let result = store.mark_email_verified(UserId::default()).await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), password, TwoFAMethod::None);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        assert!(!store.get_user(email.clone()).await.unwrap().verified);
+
+        assert!(store.mark_email_verified(id).await.is_ok());
+        assert!(store.get_user(email).await.unwrap().verified);
+    }
+
+    #[tokio::test]
+    async fn test_totp_secret() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+        let secret = TotpSecret::default();
+
+        let result = store
+            .set_totp_secret(UserId::default(), secret.clone())
+            .await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), password, TwoFAMethod::None);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        let result = store.get_totp_secret(id).await;
+        assert_eq!(result.unwrap_err(), UserStoreError::TotpSecretNotFound);
+
+        assert!(store.set_totp_secret(id, secret.clone()).await.is_ok());
+        assert_eq!(store.get_totp_secret(id).await.unwrap(), secret);
+    }
+
+    #[tokio::test]
+    async fn test_use_totp_step() {
+        let store = HashmapUserStore::default();
+        let user = User::new(
+            Email::parse(Secret::from("a@example.com".to_owned())).unwrap(),
+            Password::parse(Secret::new("password123".to_owned())).unwrap(),
+            TwoFAMethod::Totp,
+        );
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+
+        assert!(store.use_totp_step(id, 10).await.unwrap());
+        assert!(!store.use_totp_step(id, 10).await.unwrap());
+        assert!(!store.use_totp_step(id, 9).await.unwrap());
+        assert!(store.use_totp_step(id, 11).await.unwrap());
+        assert_eq!(
+            store.use_totp_step(UserId::default(), 1).await.unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_roles() {
+        let store = HashmapUserStore::default();
+        let user = User::new(
+            Email::parse(Secret::from("a@example.com".to_owned())).unwrap(),
+            Password::parse(Secret::new("password123".to_owned())).unwrap(),
+            TwoFAMethod::None,
+        );
+        let id = user.id;
+        let admin = Role::parse(ADMIN_ROLE).unwrap();
+
+        let result = store.add_role(id, admin.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        store.add_user(user).await.unwrap();
+        assert_eq!(store.get_grants(id).await.unwrap(), Grants::default());
+
+        let unknown = Role::parse("unknown").unwrap();
+        let result = store.add_role(id, unknown).await;
+        assert_eq!(result, Err(UserStoreError::RoleNotFound));
+
+        store.add_role(id, admin.clone()).await.unwrap();
+        store.add_role(id, admin.clone()).await.unwrap();
+        let grants = store.get_grants(id).await.unwrap();
+        assert_eq!(grants.roles, vec![admin.clone()]);
+        assert_eq!(
+            grants.permissions,
+            vec![
+                Permission::parse(AUDIT_READ_PERMISSION).unwrap(),
+                Permission::parse(USERS_READ_PERMISSION).unwrap(),
+                Permission::parse(USERS_WRITE_PERMISSION).unwrap(),
+            ]
+        );
+
+        store.remove_role(id, admin).await.unwrap();
+        assert_eq!(store.get_grants(id).await.unwrap().roles, vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        let store = HashmapUserStore::default();
+        for email in ["carol@example.com", "alice@example.com", "bob@test.com"] {
+            let user = User::new(
+                Email::parse(Secret::from(email.to_owned())).unwrap(),
+                Password::parse(Secret::new("password123".to_owned())).unwrap(),
+                TwoFAMethod::None,
+            );
+            store.add_user(user).await.unwrap();
+        }
+        let emails = |page: &UserPage| -> Vec<String> {
+            page.users
+                .iter()
+                .map(|user| user.email.as_ref().expose_secret().to_owned())
+                .collect()
+        };
+
+        let query = UserQuery {
+            search: None,
+            offset: 1,
+            limit: 1,
+        };
+        let page = store.list_users(query).await.unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(emails(&page), vec!["bob@test.com"]);
+
+        let query = UserQuery {
+            search: Some("EXAMPLE".to_owned()),
+            offset: 0,
+            limit: 10,
+        };
+        let page = store.list_users(query).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(
+            emails(&page),
+            vec!["alice@example.com", "carol@example.com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_locked() {
+        let store = HashmapUserStore::default();
+        let email =
+            Email::parse(Secret::from("a@example.com".to_owned())).expect("Invalid test email");
+        let password =
+            Password::parse(Secret::new("password123".to_owned())).expect("Invalid test password");
+
+        let result = store.set_locked(UserId::default(), true).await;
+        assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
+
+        let user = User::new(email.clone(), password, TwoFAMethod::None);
+        let id = user.id;
+        assert!(store.add_user(user).await.is_ok());
+        assert!(!store.get_user(email.clone()).await.unwrap().locked);
+
+        assert!(store.set_locked(id, true).await.is_ok());
+        assert!(store.get_user(email.clone()).await.unwrap().locked);
+        assert!(store.set_locked(id, false).await.is_ok());
+        assert!(!store.get_user(email).await.unwrap().locked);
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let store = HashmapUserStore::default();
+        assert!(store.ping().await.is_ok());
+    }
 }
@@ -1,24 +1,45 @@
+use parking_lot::RwLock;
 use secrecy::{ExposeSecret, Secret};
 
-use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
-use std::collections::HashSet;
+use crate::domain::{
+    UserId,
+    data_stores::{BannedTokenStore, BannedTokenStoreError},
+};
+use std::collections::{HashMap, HashSet};
 
 // Hashset store for banned user tokens.
 #[derive(Default)]
 pub struct HashsetBannedTokenStore {
-    pub tokens: HashSet<String>,
+    pub tokens: RwLock<HashSet<String>>,
+    pub banned_before: RwLock<HashMap<UserId, i64>>,
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
+    async fn add_token(&self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
         let secret = token.expose_secret();
-        self.tokens.insert(secret.clone());
+        self.tokens.write().insert(secret.clone());
         Ok(())
     }
 
     async fn has_token(&self, token: Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        Ok(self.tokens.contains(token.expose_secret()))
+        Ok(self.tokens.read().contains(token.expose_secret()))
+    }
+
+    async fn ban_tokens_issued_before(
+        &self,
+        user_id: &UserId,
+        timestamp: i64,
+    ) -> Result<(), BannedTokenStoreError> {
+        self.banned_before.write().insert(*user_id, timestamp);
+        Ok(())
+    }
+
+    async fn get_tokens_banned_before(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<i64>, BannedTokenStoreError> {
+        Ok(self.banned_before.read().get(user_id).copied())
     }
 }
 
@@ -28,24 +49,38 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_token() {
-        let mut store = HashsetBannedTokenStore::default();
+        let store = HashsetBannedTokenStore::default();
         assert!(
             store
                 .add_token(Secret::new("test".to_owned()))
                 .await
                 .is_ok()
         );
-        assert!(store.tokens.contains("test"));
+        assert!(store.tokens.read().contains("test"));
     }
 
     #[tokio::test]
+    #[allow(clippy::bool_assert_comparison)]
     async fn test_has_token() {
-        let mut store = HashsetBannedTokenStore::default();
+        let store = HashsetBannedTokenStore::default();
         let found = store.has_token(Secret::new("test".to_owned())).await;
         assert_eq!(found.unwrap(), false);
 
-        store.tokens.insert("test".to_owned());
+        store.tokens.write().insert("test".to_owned());
         let found = store.has_token(Secret::new("test".to_owned())).await;
         assert_eq!(found.unwrap(), true);
     }
+
+    #[tokio::test]
+    async fn test_ban_tokens_issued_before() {
+        let store = HashsetBannedTokenStore::default();
+        let user_id = UserId::default();
+
+        assert_eq!(store.get_tokens_banned_before(&user_id).await.unwrap(), None);
+        store.ban_tokens_issued_before(&user_id, 42).await.unwrap();
+        assert_eq!(
+            store.get_tokens_banned_before(&user_id).await.unwrap(),
+            Some(42)
+        );
+    }
 }
@@ -0,0 +1,198 @@
+use color_eyre::eyre::{Context, Result};
+use std::path::PathBuf;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+
+use crate::domain::{
+    AuditEntry, AuditEvent, AuditQuery, GENESIS_HASH,
+    data_stores::{AuditSink, AuditSinkError},
+};
+
+// Append the audit log to a file, one JSON entry per line.
+pub struct JsonLinesAuditSink {
+    path: PathBuf,
+    // Appends and reads take turns, so that every entry follows the one written
+    // before it, and reads never see half a line.
+    tail: Mutex<Tail>,
+}
+
+// The file, and the last entry written to it.
+struct Tail {
+    file: File,
+    sequence: i64,
+    hash: String,
+}
+
+impl JsonLinesAuditSink {
+    // Open the log at `path`, creating it if needed, to append after its last entry.
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .wrap_err_with(|| format!("failed to open audit log {}", path.display()))?;
+
+        let entries = read_entries(&path).await?;
+        let (sequence, hash) = match entries.last() {
+            Some(last) => (last.sequence, last.hash.clone()),
+            None => (0, GENESIS_HASH.to_owned()),
+        };
+
+        Ok(Self {
+            path,
+            tail: Mutex::new(Tail {
+                file,
+                sequence,
+                hash,
+            }),
+        })
+    }
+}
+
+async fn read_entries(path: &PathBuf) -> Result<Vec<AuditEntry>> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .wrap_err_with(|| format!("failed to read audit log {}", path.display()))?;
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_str(line).wrap_err("invalid audit log entry"))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl AuditSink for JsonLinesAuditSink {
+    #[tracing::instrument(name = "Appending audit entry to file", skip_all)]
+    async fn record(&self, event: AuditEvent) -> Result<AuditEntry, AuditSinkError> {
+        let mut tail = self.tail.lock().await;
+        let entry = AuditEntry::new(tail.sequence + 1, tail.hash.clone(), event);
+
+        let mut line =
+            serde_json::to_vec(&entry).map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+        line.push(b'\n');
+        tail.file
+            .write_all(&line)
+            .await
+            .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+        tail.file
+            .flush()
+            .await
+            .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+
+        tail.sequence = entry.sequence;
+        tail.hash = entry.hash.clone();
+        Ok(entry)
+    }
+
+    #[tracing::instrument(name = "Reading audit entries from file", skip_all)]
+    async fn query(&self, query: AuditQuery) -> Result<Vec<AuditEntry>, AuditSinkError> {
+        let _tail = self.tail.lock().await;
+        let entries = read_entries(&self.path)
+            .await
+            .map_err(AuditSinkError::UnexpectedError)?;
+
+        let limit = query.limit.map_or(usize::MAX, |limit| limit as usize);
+        Ok(entries
+            .into_iter()
+            .filter(|entry| query.matches(&entry.event))
+            .take(limit)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{AuditActor, AuditEventKind, AuditOutcome, UserId, verify_chain};
+
+    fn get_event(user_id: UserId, timestamp: i64) -> AuditEvent {
+        AuditEvent {
+            kind: AuditEventKind::Signup,
+            outcome: AuditOutcome::Success,
+            reason: None,
+            actor: AuditActor::Anonymous,
+            user_id: Some(user_id),
+            ip: Some("127.0.0.1".parse().unwrap()),
+            user_agent: Some("Test Browser/1.0".to_owned()),
+            request_id: Some(uuid::Uuid::new_v4().to_string()),
+            timestamp,
+        }
+    }
+
+    fn get_path() -> PathBuf {
+        std::env::temp_dir().join(format!("audit-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_record_and_query() {
+        let path = get_path();
+        let sink = JsonLinesAuditSink::open(&path).await.unwrap();
+        let user_id = UserId::default();
+        for timestamp in 1..=3 {
+            sink.record(get_event(user_id, timestamp)).await.unwrap();
+            sink.record(get_event(UserId::default(), timestamp))
+                .await
+                .unwrap();
+        }
+
+        let entries = sink.query(AuditQuery::default()).await.unwrap();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(verify_chain(&entries), Ok(()));
+
+        let query = AuditQuery {
+            user_id: Some(user_id),
+            from: Some(2),
+            to: None,
+            limit: Some(1),
+        };
+        let entries = sink.query(query).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event.user_id, Some(user_id));
+        assert_eq!(entries[0].event.timestamp, 2);
+
+        tokio::fs::remove_file(path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reopen_continues_the_chain() {
+        let path = get_path();
+        let sink = JsonLinesAuditSink::open(&path).await.unwrap();
+        sink.record(get_event(UserId::default(), 1)).await.unwrap();
+        drop(sink);
+
+        let sink = JsonLinesAuditSink::open(&path).await.unwrap();
+        let entry = sink.record(get_event(UserId::default(), 2)).await.unwrap();
+        assert_eq!(entry.sequence, 2);
+        let entries = sink.query(AuditQuery::default()).await.unwrap();
+        assert_eq!(verify_chain(&entries), Ok(()));
+
+        tokio::fs::remove_file(path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_removed_line_is_detected() {
+        let path = get_path();
+        let sink = JsonLinesAuditSink::open(&path).await.unwrap();
+        for timestamp in 1..=3 {
+            sink.record(get_event(UserId::default(), timestamp))
+                .await
+                .unwrap();
+        }
+
+        let content = tokio::fs::read_to_string(&path).await.unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        tokio::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2]))
+            .await
+            .unwrap();
+
+        let entries = sink.query(AuditQuery::default()).await.unwrap();
+        assert_eq!(verify_chain(&entries), Err(3));
+
+        tokio::fs::remove_file(path).await.unwrap();
+    }
+}
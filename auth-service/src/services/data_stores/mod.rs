@@ -1,13 +1,33 @@
+pub mod hashmap_rate_limit_store;
+pub mod hashmap_refresh_token_store;
+pub mod hashmap_session_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
 pub mod hashset_banned_token_store;
+pub mod json_lines_audit_sink;
+pub mod postgres_audit_sink;
 pub mod postgres_user_store;
 pub mod redis_banned_token_store;
+pub mod redis_pool;
+pub mod redis_rate_limit_store;
+pub mod redis_refresh_token_store;
+pub mod redis_session_store;
 pub mod redis_two_fa_code_store;
+pub mod vec_audit_sink;
 
+pub use hashmap_rate_limit_store::*;
+pub use hashmap_refresh_token_store::*;
+pub use hashmap_session_store::*;
 pub use hashmap_two_fa_code_store::*;
 pub use hashmap_user_store::*;
 pub use hashset_banned_token_store::*;
+pub use json_lines_audit_sink::*;
+pub use postgres_audit_sink::*;
 pub use postgres_user_store::*;
 pub use redis_banned_token_store::*;
+pub use redis_pool::*;
+pub use redis_rate_limit_store::*;
+pub use redis_refresh_token_store::*;
+pub use redis_session_store::*;
 pub use redis_two_fa_code_store::*;
+pub use vec_audit_sink::*;
@@ -0,0 +1,172 @@
+use color_eyre::eyre::Result;
+use sqlx::{Connection, PgPool};
+use uuid::Uuid;
+
+use crate::domain::{
+    AuditActor, AuditEntry, AuditEvent, AuditEventKind, AuditOutcome, AuditQuery, GENESIS_HASH,
+    UserId,
+    data_stores::{AuditSink, AuditSinkError},
+};
+use crate::utils::metrics;
+
+// Key of the advisory lock that lets one entry be appended at a time: `audit` in ASCII.
+const AUDIT_LOG_LOCK: i64 = 0x0061_7564_6974;
+
+pub struct PostgresAuditSink {
+    pool: PgPool,
+}
+
+impl PostgresAuditSink {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PgAuditRow {
+    sequence: i64,
+    kind: String,
+    outcome: String,
+    reason: Option<String>,
+    actor: String,
+    user_id: Option<Uuid>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+    occurred_at: i64,
+    previous_hash: String,
+    hash: String,
+}
+
+impl TryFrom<PgAuditRow> for AuditEntry {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(row: PgAuditRow) -> Result<Self> {
+        Ok(Self {
+            sequence: row.sequence,
+            event: AuditEvent {
+                kind: AuditEventKind::parse(&row.kind)?,
+                outcome: AuditOutcome::parse(&row.outcome)?,
+                reason: row.reason,
+                actor: AuditActor::parse(&row.actor)?,
+                user_id: row.user_id.map(UserId::from),
+                ip: row.ip.map(|ip| ip.parse()).transpose()?,
+                user_agent: row.user_agent,
+                request_id: row.request_id,
+                timestamp: row.occurred_at,
+            },
+            previous_hash: row.previous_hash,
+            hash: row.hash,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for PostgresAuditSink {
+    #[tracing::instrument(name = "Appending audit entry to PostgreSQL", skip_all)]
+    async fn record(&self, event: AuditEvent) -> Result<AuditEntry, AuditSinkError> {
+        let _timer = metrics::postgres_timer("record_audit_entry");
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+
+        // Concurrent appends would both chain after the same entry.
+        sqlx::query!("SELECT pg_advisory_xact_lock($1)", AUDIT_LOG_LOCK)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+        let last = sqlx::query!(
+            r#"
+            SELECT sequence, hash
+            FROM audit_log
+            ORDER BY sequence DESC
+            LIMIT 1
+            "#
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+        let (sequence, previous_hash) = match last {
+            Some(last) => (last.sequence + 1, last.hash),
+            None => (1, GENESIS_HASH.to_owned()),
+        };
+
+        let entry = AuditEntry::new(sequence, previous_hash, event);
+        let event = &entry.event;
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_log (sequence, kind, outcome, reason, actor, user_id, ip,
+                user_agent, request_id, occurred_at, previous_hash, hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#,
+            entry.sequence,
+            event.kind.as_str(),
+            event.outcome.as_str(),
+            event.reason.as_deref(),
+            String::from(event.actor),
+            event.user_id.as_ref().map(|id| *id.as_ref()),
+            event.ip.map(|ip| ip.to_string()),
+            event.user_agent.as_deref(),
+            event.request_id.as_deref(),
+            event.timestamp,
+            entry.previous_hash,
+            entry.hash,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+        Ok(entry)
+    }
+
+    #[tracing::instrument(name = "Querying audit entries from PostgreSQL", skip_all)]
+    async fn query(&self, query: AuditQuery) -> Result<Vec<AuditEntry>, AuditSinkError> {
+        let _timer = metrics::postgres_timer("query_audit_log");
+        sqlx::query_as!(
+            PgAuditRow,
+            r#"
+            SELECT sequence, kind, outcome, reason, actor, user_id, ip, user_agent,
+                request_id, occurred_at, previous_hash, hash
+            FROM audit_log
+            WHERE ($1::UUID IS NULL OR user_id = $1)
+                AND ($2::BIGINT IS NULL OR occurred_at >= $2)
+                AND ($3::BIGINT IS NULL OR occurred_at <= $3)
+            ORDER BY sequence
+            LIMIT $4
+            "#,
+            query.user_id.as_ref().map(|id| *id.as_ref()),
+            query.from,
+            query.to,
+            query.limit.map(i64::from),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(AuditEntry::try_from)
+        .collect::<Result<_>>()
+        .map_err(AuditSinkError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Ping PostgreSQL", skip_all)]
+    async fn ping(&self) -> Result<(), AuditSinkError> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| AuditSinkError::UnexpectedError(e.into()))?;
+        conn.ping()
+            .await
+            .map_err(|e| AuditSinkError::UnexpectedError(e.into()))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+}
@@ -2,63 +2,126 @@ use argon2::{
     Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
     password_hash::SaltString,
 };
-use color_eyre::eyre::{Context, Result};
+use color_eyre::eyre::{Context, Result, eyre};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{Connection, PgPool};
 use tracing;
+use uuid::Uuid;
 
 use crate::domain::{
-    Email, Password, User,
+    Email, Grants, LocalPartPolicy, Password, Permission, Role, TotpSecret, TwoFAMethod, User,
+    UserExport, UserId, UserPage, UserQuery,
     data_stores::{UserStore, UserStoreError},
 };
+use crate::utils::metrics::{self, HASH_OPERATION, VERIFY_OPERATION};
 
 pub struct PostgresUserStore {
     pool: PgPool,
+    // Argon2id cost of new password hashes.
+    hash_params: Params,
 }
 
 impl PostgresUserStore {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, hash_params: Params) -> Self {
+        Self { pool, hash_params }
+    }
+
+    // Lowercase the local part of emails stored before they were canonicalized, for
+    // the `lowercase` policy. Returns how many emails changed. Nothing changes when two
+    // emails differ only by case: those accounts must be merged or deleted first.
+    // Emails are not included in the error, as it ends up in logs.
+    #[tracing::instrument(name = "Lowercasing emails in PostgreSQL", skip_all)]
+    pub async fn lowercase_local_parts(&self) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET email = lower(email)
+            WHERE email <> lower(email)
+            "#
+        )
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(eyre!(
+                "some emails belong to several accounts once lowercased, merge or delete them \
+                 first. Find them with: SELECT array_agg(email) FROM users GROUP BY lower(email) \
+                 HAVING count(*) > 1"
+            )),
+            Err(e) => Err(e).wrap_err("failed to lowercase emails"),
+        }
     }
 }
 
 #[derive(sqlx::FromRow)]
 struct PgUserRow {
+    id: Uuid,
     email: String,
     password_hash: String,
-    requires_2fa: bool,
+    two_fa_method: String,
+    verified: bool,
+    locked: bool,
+}
+
+impl TryFrom<PgUserRow> for User {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(row: PgUserRow) -> Result<Self> {
+        Ok(Self {
+            id: UserId::from(row.id),
+            // Stored emails are canonical already, under whichever policy was in use.
+            email: Email::parse_with_policy(Secret::new(row.email), LocalPartPolicy::Preserve)?,
+            password: Password::parse(Secret::new(row.password_hash))?,
+            two_fa_method: TwoFAMethod::parse(&row.two_fa_method)?,
+            verified: row.verified,
+            locked: row.locked,
+        })
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let password_hash = compute_password_hash(user.password.as_ref().to_owned())
-            .await
-            .map_err(UserStoreError::UnexpectedError)?;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let password_hash =
+            compute_password_hash(user.password.as_ref().to_owned(), self.hash_params.clone())
+                .await
+                .map_err(UserStoreError::UnexpectedError)?;
 
+        let _timer = metrics::postgres_timer("add_user");
         sqlx::query!(
             r#"
-            INSERT INTO users (email, password_hash, requires_2fa)
-            VALUES($1, $2, $3)
+            INSERT INTO users (id, email, password_hash, two_fa_method, verified, locked)
+            VALUES($1, $2, $3, $4, $5, $6)
             "#,
+            user.id.as_ref(),
             user.email.as_ref().expose_secret(),
             &password_hash.expose_secret(),
-            user.requires_2fa,
+            user.two_fa_method.as_str(),
+            user.verified,
+            user.locked,
         )
         .execute(&self.pool)
         .await
-        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        .map_err(|e| match e {
+            // The unique email settles concurrent signups for the same address.
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
 
         Ok(())
     }
 
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: Email) -> Result<User, UserStoreError> {
+        let _timer = metrics::postgres_timer("get_user");
         sqlx::query_as!(
             PgUserRow,
             r#"
-            SELECT email, password_hash, requires_2fa
+            SELECT id, email, password_hash, two_fa_method, verified, locked
             FROM users
             WHERE email = $1
             "#,
@@ -67,16 +130,29 @@ impl UserStore for PostgresUserStore {
         .fetch_optional(&self.pool)
         .await
         .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
-        .map(|row| {
-            Ok(User {
-                email: Email::parse(Secret::new(row.email))
-                    .map_err(UserStoreError::UnexpectedError)?,
-                password: Password::parse(Secret::new(row.password_hash))
-                    .map_err(UserStoreError::UnexpectedError)?,
-                requires_2fa: row.requires_2fa,
-            })
-        })
         .ok_or(UserStoreError::UserNotFound)?
+        .try_into()
+        .map_err(UserStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Retrieving user by id from PostgreSQL", skip_all)]
+    async fn get_user_by_id(&self, id: UserId) -> Result<User, UserStoreError> {
+        let _timer = metrics::postgres_timer("get_user_by_id");
+        sqlx::query_as!(
+            PgUserRow,
+            r#"
+            SELECT id, email, password_hash, two_fa_method, verified, locked
+            FROM users
+            WHERE id = $1
+            "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?
+        .try_into()
+        .map_err(UserStoreError::UnexpectedError)
     }
 
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)]
@@ -91,6 +167,394 @@ impl UserStore for PostgresUserStore {
 
         Ok(())
     }
+
+    #[tracing::instrument(name = "Deleting user from PostgreSQL", skip_all)]
+    async fn delete_user(&self, id: UserId) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("delete_user");
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Exporting user from PostgreSQL", skip_all)]
+    async fn export_user(&self, id: UserId) -> Result<UserExport, UserStoreError> {
+        let _timer = metrics::postgres_timer("export_user");
+        let row = sqlx::query!(
+            r#"
+            SELECT id, email, two_fa_method, verified, totp_secret IS NOT NULL AS "has_totp_secret!", locked
+            FROM users
+            WHERE id = $1
+            "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        Ok(UserExport {
+            id: UserId::from(row.id),
+            email: Email::parse_with_policy(Secret::new(row.email), LocalPartPolicy::Preserve)
+                .map_err(UserStoreError::UnexpectedError)?,
+            two_fa_method: TwoFAMethod::parse(&row.two_fa_method)
+                .map_err(UserStoreError::UnexpectedError)?,
+            verified: row.verified,
+            has_totp_secret: row.has_totp_secret,
+            locked: row.locked,
+        })
+    }
+
+    #[tracing::instrument(name = "Updating user password in PostgreSQL", skip_all)]
+    async fn update_password(&self, id: UserId, password: Password) -> Result<(), UserStoreError> {
+        let password_hash =
+            compute_password_hash(password.as_ref().to_owned(), self.hash_params.clone())
+                .await
+                .map_err(UserStoreError::UnexpectedError)?;
+
+        let _timer = metrics::postgres_timer("update_password");
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $2
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+            password_hash.expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating user email in PostgreSQL", skip_all)]
+    async fn update_email(&self, id: UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("update_email");
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET email = $2
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+            new_email.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating user 2FA method in PostgreSQL", skip_all)]
+    async fn set_two_fa_method(
+        &self,
+        id: UserId,
+        method: TwoFAMethod,
+    ) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("set_two_fa_method");
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET two_fa_method = $2
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+            method.as_str(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Marking user email as verified in PostgreSQL", skip_all)]
+    async fn mark_email_verified(&self, id: UserId) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("mark_email_verified");
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET verified = TRUE
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Storing user TOTP secret in PostgreSQL", skip_all)]
+    async fn set_totp_secret(&self, id: UserId, secret: TotpSecret) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("set_totp_secret");
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_secret = $2
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+            secret.as_ref().expose_secret(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving user TOTP secret from PostgreSQL", skip_all)]
+    async fn get_totp_secret(&self, id: UserId) -> Result<TotpSecret, UserStoreError> {
+        let _timer = metrics::postgres_timer("get_totp_secret");
+        let row = sqlx::query!(
+            r#"
+            SELECT totp_secret
+            FROM users
+            WHERE id = $1
+            "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        let secret = row.totp_secret.ok_or(UserStoreError::TotpSecretNotFound)?;
+        TotpSecret::parse(Secret::new(secret)).map_err(UserStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Recording user TOTP step in PostgreSQL", skip_all)]
+    async fn use_totp_step(&self, id: UserId, step: u64) -> Result<bool, UserStoreError> {
+        let _timer = metrics::postgres_timer("use_totp_step");
+        let step = i64::try_from(step).map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        // One statement, so that two logins racing with the same code cannot both win.
+        let row = sqlx::query!(
+            r#"
+            WITH updated AS (
+                UPDATE users
+                SET totp_last_step = $2
+                WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+                RETURNING id
+            )
+            SELECT
+                EXISTS (SELECT 1 FROM updated) AS "recorded!",
+                EXISTS (SELECT 1 FROM users WHERE id = $1) AS "exists!"
+            "#,
+            id.as_ref(),
+            step,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match row.exists {
+            true => Ok(row.recorded),
+            false => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving user roles from PostgreSQL", skip_all)]
+    async fn get_grants(&self, id: UserId) -> Result<Grants, UserStoreError> {
+        let _timer = metrics::postgres_timer("get_grants");
+        let roles = sqlx::query_scalar!(
+            r#"
+            SELECT role
+            FROM user_roles
+            WHERE user_id = $1
+            ORDER BY role
+            "#,
+            id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        let permissions = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT permission
+            FROM role_permissions
+            JOIN user_roles USING (role)
+            WHERE user_id = $1
+            ORDER BY permission
+            "#,
+            id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        Ok(Grants {
+            roles: roles
+                .iter()
+                .map(|role| Role::parse(role))
+                .collect::<Result<_>>()
+                .map_err(UserStoreError::UnexpectedError)?,
+            permissions: permissions
+                .iter()
+                .map(|permission| Permission::parse(permission))
+                .collect::<Result<_>>()
+                .map_err(UserStoreError::UnexpectedError)?,
+        })
+    }
+
+    #[tracing::instrument(name = "Adding user role in PostgreSQL", skip_all)]
+    async fn add_role(&self, id: UserId, role: Role) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("add_role");
+        sqlx::query!(
+            r#"
+            INSERT INTO user_roles (user_id, role)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            id.as_ref(),
+            role.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_foreign_key_violation() => match e.constraint() {
+                Some("user_roles_role_fkey") => UserStoreError::RoleNotFound,
+                _ => UserStoreError::UserNotFound,
+            },
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Removing user role in PostgreSQL", skip_all)]
+    async fn remove_role(&self, id: UserId, role: Role) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("remove_role");
+        sqlx::query!(
+            r#"
+            DELETE FROM user_roles
+            WHERE user_id = $1 AND role = $2
+            "#,
+            id.as_ref(),
+            role.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        // Tell a missing user apart from a role they did not have.
+        self.get_user_by_id(id).await.map(|_| ())
+    }
+
+    #[tracing::instrument(name = "Listing users from PostgreSQL", skip_all)]
+    async fn list_users(&self, query: UserQuery) -> Result<UserPage, UserStoreError> {
+        let _timer = metrics::postgres_timer("list_users");
+        let rows = sqlx::query_as!(
+            PgUserRow,
+            r#"
+            SELECT id, email, password_hash, two_fa_method, verified, locked
+            FROM users
+            WHERE $1::TEXT IS NULL OR strpos(email, lower($1)) > 0
+            ORDER BY email
+            LIMIT $2 OFFSET $3
+            "#,
+            query.search.as_deref(),
+            i64::from(query.limit),
+            i64::from(query.offset),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM users
+            WHERE $1::TEXT IS NULL OR strpos(email, lower($1)) > 0
+            "#,
+            query.search.as_deref(),
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        Ok(UserPage {
+            users: rows
+                .into_iter()
+                .map(User::try_from)
+                .collect::<Result<_>>()
+                .map_err(UserStoreError::UnexpectedError)?,
+            total: total as u64,
+        })
+    }
+
+    #[tracing::instrument(name = "Locking or unlocking user in PostgreSQL", skip_all)]
+    async fn set_locked(&self, id: UserId, locked: bool) -> Result<(), UserStoreError> {
+        let _timer = metrics::postgres_timer("set_locked");
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET locked = $2
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+            locked,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Ping PostgreSQL", skip_all)]
+    async fn ping(&self) -> Result<(), UserStoreError> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        conn.ping()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))
+    }
+
+    // Waits for the connections in use to be released.
+    async fn close(&self) {
+        self.pool.close().await
+    }
 }
 
 #[tracing::instrument(name = "Verify password hash", skip_all)]
@@ -101,6 +565,7 @@ async fn verify_password_hash(
     let current_span: tracing::Span = tracing::Span::current();
     let result = tokio::task::spawn_blocking(move || {
         current_span.in_scope(|| {
+            let _timer = metrics::password_hashing_timer(VERIFY_OPERATION);
             let expected_password_hash: PasswordHash<'_> =
                 PasswordHash::new(expected_password_hash.expose_secret())?;
             Argon2::default()
@@ -117,18 +582,15 @@ async fn verify_password_hash(
 }
 
 #[tracing::instrument(name = "Computing password hash", skip_all)]
-async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
+async fn compute_password_hash(password: Secret<String>, params: Params) -> Result<Secret<String>> {
     let current_span: tracing::Span = tracing::Span::current();
     let result = tokio::task::spawn_blocking(move || {
         current_span.in_scope(|| {
+            let _timer = metrics::password_hashing_timer(HASH_OPERATION);
             let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(password.expose_secret().as_bytes(), &salt)?
-            .to_string();
+            let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password(password.expose_secret().as_bytes(), &salt)?
+                .to_string();
 
             Ok(Secret::new(password_hash))
         })
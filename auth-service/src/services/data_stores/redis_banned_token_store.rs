@@ -1,41 +1,49 @@
 use color_eyre::eyre::{Context, Result};
-use redis::{Commands, Connection};
+use redis::AsyncCommands;
 use secrecy::{ExposeSecret, Secret};
-use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing;
 
-use crate::{
-    domain::data_stores::{BannedTokenStore, BannedTokenStoreError},
-    utils::auth::TOKEN_TTL_SECONDS,
+use super::{RedisConnection, RedisPool};
+use crate::domain::{
+    UserId,
+    data_stores::{BannedTokenStore, BannedTokenStoreError},
 };
 
 pub struct RedisBannedTokenStore {
-    conn: Arc<RwLock<Connection>>,
+    pool: RedisPool,
+    // Bans last as long as the tokens they apply to.
+    token_ttl_seconds: i64,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(pool: RedisPool, token_ttl_seconds: i64) -> Self {
+        Self {
+            pool,
+            token_ttl_seconds,
+        }
+    }
+
+    async fn conn(&self) -> Result<RedisConnection, BannedTokenStoreError> {
+        self.pool
+            .get()
+            .await
+            .wrap_err("failed to connect to Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)
     }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
     #[tracing::instrument(name = "Add token to Redis", skip_all)]
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
+    async fn add_token(&self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
         let token_key = get_key(token.expose_secret());
         let value = true;
-        let ttl: u64 = TOKEN_TTL_SECONDS
-            .try_into()
-            .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64")
-            .map_err(BannedTokenStoreError::UnexpectedError)?;
 
         let _: () = self
-            .conn
-            .write()
+            .conn()
+            .await?
+            .set_ex(&token_key, value, get_ttl(self.token_ttl_seconds)?)
             .await
-            .set_ex(&token_key, value, ttl)
             .wrap_err("failed to set banned token in Redis")
             .map_err(BannedTokenStoreError::UnexpectedError)?;
 
@@ -46,20 +54,83 @@ impl BannedTokenStore for RedisBannedTokenStore {
     async fn has_token(&self, token: Secret<String>) -> Result<bool, BannedTokenStoreError> {
         let key = get_key(token.expose_secret());
         let is_banned: bool = self
-            .conn
-            .write()
-            .await
+            .conn()
+            .await?
             .exists(key)
+            .await
             .wrap_err("failed to check if token exists in Redis")
             .map_err(BannedTokenStoreError::UnexpectedError)?;
 
         Ok(is_banned)
     }
+
+    #[tracing::instrument(name = "Ban user tokens in Redis", skip_all)]
+    async fn ban_tokens_issued_before(
+        &self,
+        user_id: &UserId,
+        timestamp: i64,
+    ) -> Result<(), BannedTokenStoreError> {
+        // Every token issued before then has expired once the TTL is over.
+        let _: () = self
+            .conn()
+            .await?
+            .set_ex(
+                get_user_key(user_id),
+                timestamp,
+                get_ttl(self.token_ttl_seconds)?,
+            )
+            .await
+            .wrap_err("failed to ban user tokens in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get user tokens ban from Redis", skip_all)]
+    async fn get_tokens_banned_before(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Option<i64>, BannedTokenStoreError> {
+        let timestamp: Option<i64> = self
+            .conn()
+            .await?
+            .get(get_user_key(user_id))
+            .await
+            .wrap_err("failed to get user tokens ban from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(timestamp)
+    }
+
+    #[tracing::instrument(name = "Ping Redis", skip_all)]
+    async fn ping(&self) -> Result<(), BannedTokenStoreError> {
+        self.pool
+            .ping()
+            .await
+            .map_err(|e| BannedTokenStoreError::UnexpectedError(e.into()))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+}
+
+fn get_ttl(token_ttl_seconds: i64) -> Result<u64, BannedTokenStoreError> {
+    token_ttl_seconds
+        .try_into()
+        .wrap_err("failed to cast the token TTL to u64")
+        .map_err(BannedTokenStoreError::UnexpectedError)
 }
 
 // We are using a key prefix to prevent collisions and organize data!
 const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
 
+const BANNED_USER_KEY_PREFIX: &str = "banned_tokens_issued_before:";
+
 fn get_key(token: &str) -> String {
     format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
 }
+
+fn get_user_key(user_id: &UserId) -> String {
+    format!("{}{}", BANNED_USER_KEY_PREFIX, user_id)
+}
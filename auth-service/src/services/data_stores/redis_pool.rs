@@ -0,0 +1,173 @@
+use redis::{
+    Arg, Client, Cmd, Pipeline, RedisFuture, RedisResult, Value,
+    aio::{ConnectionLike, MultiplexedConnection},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+use tracing;
+
+use crate::utils::metrics;
+
+// Longest wait for a connection to Redis to be established.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Longest wait for the reply to a command, after which the connection is replaced.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Async connection to Redis, shared by every Redis store.
+// Commands of concurrent requests are multiplexed over one connection instead of
+// waiting for each other. When the connection breaks, e.g. because Redis restarted,
+// the next command opens a new one.
+#[derive(Clone)]
+pub struct RedisPool {
+    client: Client,
+    current: Arc<Mutex<Current>>,
+}
+
+#[derive(Default)]
+struct Current {
+    conn: Option<MultiplexedConnection>,
+    // Counts the connections opened, so that a broken one is only dropped once.
+    generation: u64,
+}
+
+impl RedisPool {
+    // No connection is opened until the first command, so the service can start
+    // before Redis does.
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            current: Arc::new(Mutex::new(Current::default())),
+        }
+    }
+
+    // Get a handle to the shared connection, connecting first if there is none.
+    pub async fn get(&self) -> RedisResult<RedisConnection> {
+        let mut current = self.current.lock().await;
+        let conn = match &current.conn {
+            Some(conn) => conn.clone(),
+            None => {
+                let conn = self
+                    .client
+                    .get_multiplexed_tokio_connection_with_response_timeouts(
+                        RESPONSE_TIMEOUT,
+                        CONNECTION_TIMEOUT,
+                    )
+                    .await?;
+                current.generation += 1;
+                current.conn.insert(conn).clone()
+            }
+        };
+
+        Ok(RedisConnection {
+            conn,
+            generation: current.generation,
+            pool: self.clone(),
+        })
+    }
+
+    // Check that Redis answers, connecting first if needed.
+    pub async fn ping(&self) -> RedisResult<()> {
+        let mut conn = self.get().await?;
+        redis::cmd("PING").query_async(&mut conn).await
+    }
+
+    // Close the shared connection. Commands already sent are still answered. The
+    // next command, if any, opens a new one.
+    pub async fn close(&self) {
+        self.current.lock().await.conn = None;
+    }
+
+    // Forget a broken connection, unless it was already replaced.
+    async fn discard(&self, generation: u64) {
+        let mut current = self.current.lock().await;
+        if current.generation == generation && current.conn.is_some() {
+            tracing::warn!("Redis connection broke, reconnecting on the next command");
+            current.conn = None;
+        }
+    }
+}
+
+// Handle to the shared connection, used like any async Redis connection.
+pub struct RedisConnection {
+    conn: MultiplexedConnection,
+    generation: u64,
+    pool: RedisPool,
+}
+
+impl RedisConnection {
+    async fn check<T>(&self, result: RedisResult<T>) -> RedisResult<T> {
+        if let Err(e) = &result
+            && (e.is_unrecoverable_error() || e.is_timeout())
+        {
+            self.pool.discard(self.generation).await;
+        }
+        result
+    }
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            let timer = metrics::redis_timer(&get_command_name(cmd));
+            let result = self.conn.req_packed_command(cmd).await;
+            timer.observe_duration();
+            self.check(result).await
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            let timer = metrics::redis_timer("pipeline");
+            let result = self.conn.req_packed_commands(cmd, offset, count).await;
+            timer.observe_duration();
+            self.check(result).await
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.conn.get_db()
+    }
+}
+
+// Name of the command, e.g. `GET`, to label its latency with.
+fn get_command_name(cmd: &Cmd) -> String {
+    match cmd.args_iter().next() {
+        Some(Arg::Simple(name)) => String::from_utf8_lossy(name).to_uppercase(),
+        _ => "UNKNOWN".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_get_fails_fast_while_redis_is_unreachable() {
+        // Nothing listens on the port once the listener is dropped.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let pool = RedisPool::new(Client::open(format!("redis://{}/", address)).unwrap());
+
+        let start = Instant::now();
+        assert!(pool.get().await.is_err());
+        assert!(start.elapsed() <= CONNECTION_TIMEOUT);
+
+        // The failure is not remembered: every command tries to connect again.
+        assert!(pool.current.lock().await.conn.is_none());
+        assert!(pool.get().await.is_err());
+    }
+
+    #[test]
+    fn test_commands_are_named_in_upper_case() {
+        assert_eq!(get_command_name(redis::cmd("hget").arg("key")), "HGET");
+        assert_eq!(get_command_name(&Cmd::new()), "UNKNOWN");
+    }
+}
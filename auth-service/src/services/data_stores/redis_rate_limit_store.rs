@@ -0,0 +1,138 @@
+use redis::{AsyncCommands, RedisResult};
+use tracing;
+
+use super::{HashmapRateLimitStore, RedisPool};
+use crate::domain::{
+    FAILURE_TTL_SECONDS, Failures, RateLimitKey,
+    data_stores::{RateLimitStore, RateLimitStoreError},
+};
+
+// Count failures in Redis, so that every instance of the service shares them.
+// While Redis is unavailable, failures are counted in memory instead: attempts
+// stay throttled rather than failing, or going unchecked.
+pub struct RedisRateLimitStore {
+    pool: RedisPool,
+    fallback: HashmapRateLimitStore,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(pool: RedisPool) -> Self {
+        Self {
+            pool,
+            fallback: HashmapRateLimitStore::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    #[tracing::instrument(name = "Reserve attempt in Redis", skip_all)]
+    async fn reserve_attempt(
+        &self,
+        key: &RateLimitKey,
+        now: i64,
+    ) -> Result<Option<Failures>, RateLimitStoreError> {
+        let redis_key = get_key(key);
+        let result: RedisResult<((Option<u32>, Option<i64>),)> = async {
+            redis::pipe()
+                .atomic()
+                .hget(&redis_key, &[COUNT_FIELD, LAST_FAILURE_AT_FIELD])
+                .hincr(&redis_key, COUNT_FIELD, 1)
+                .ignore()
+                .hset(&redis_key, LAST_FAILURE_AT_FIELD, now)
+                .ignore()
+                .expire(&redis_key, FAILURE_TTL_SECONDS)
+                .ignore()
+                .query_async(&mut self.pool.get().await?)
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(((Some(count), Some(last_failure_at)),)) => Ok(Some(Failures {
+                count,
+                last_failure_at,
+            })),
+            // Failures counted in memory while Redis was unavailable still count.
+            Ok(_) => Ok(self.fallback.failures(key, now)),
+            Err(e) => {
+                tracing::warn!("failed to reserve attempt in Redis, using memory: {:?}", e);
+                self.fallback.reserve_attempt(key, now).await
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Release attempt in Redis", skip_all)]
+    async fn release_attempt(
+        &self,
+        key: &RateLimitKey,
+        previous: Option<Failures>,
+    ) -> Result<(), RateLimitStoreError> {
+        self.fallback.release_attempt(key, previous).await?;
+        let (previous_count, previous_failure_at) = previous.map_or((0, 0), |previous| {
+            (previous.count, previous.last_failure_at)
+        });
+        let result: RedisResult<()> = async {
+            redis::Script::new(RELEASE_ATTEMPT_SCRIPT)
+                .key(get_key(key))
+                .arg(COUNT_FIELD)
+                .arg(LAST_FAILURE_AT_FIELD)
+                .arg(previous_count)
+                .arg(previous_failure_at)
+                .arg(FAILURE_TTL_SECONDS)
+                .invoke_async(&mut self.pool.get().await?)
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("failed to release attempt in Redis: {:?}", e);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Clear failures in Redis", skip_all)]
+    async fn clear_failures(&self, key: &RateLimitKey) -> Result<(), RateLimitStoreError> {
+        self.fallback.clear_failures(key).await?;
+        let result: RedisResult<()> =
+            async { self.pool.get().await?.del(get_key(key)).await }.await;
+        if let Err(e) = result {
+            tracing::warn!("failed to clear failures in Redis: {:?}", e);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Ping Redis", skip_all)]
+    async fn ping(&self) -> Result<(), RateLimitStoreError> {
+        self.pool
+            .ping()
+            .await
+            .map_err(|e| RateLimitStoreError::UnexpectedError(e.into()))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+}
+
+const RATE_LIMIT_PREFIX: &str = "rate_limit:";
+const COUNT_FIELD: &str = "count";
+const LAST_FAILURE_AT_FIELD: &str = "last_failure_at";
+
+// Failures that expired, or were cleared, in the meantime are left alone. Unless
+// another attempt was counted since, the failures before the attempt are restored,
+// with their expiry.
+const RELEASE_ATTEMPT_SCRIPT: &str = r#"
+local count = tonumber(redis.call('HGET', KEYS[1], ARGV[1]) or '0')
+if count <= 1 then
+    redis.call('DEL', KEYS[1])
+elseif count - 1 == tonumber(ARGV[3]) then
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[3], ARGV[2], ARGV[4])
+    redis.call('EXPIREAT', KEYS[1], tonumber(ARGV[4]) + tonumber(ARGV[5]))
+else
+    redis.call('HINCRBY', KEYS[1], ARGV[1], -1)
+end
+"#;
+
+fn get_key(key: &RateLimitKey) -> String {
+    format!("{}{}", RATE_LIMIT_PREFIX, key.name())
+}
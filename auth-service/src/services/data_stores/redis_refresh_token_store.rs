@@ -0,0 +1,261 @@
+use color_eyre::eyre::{Context, Result};
+use redis::AsyncCommands;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use super::{RedisConnection, RedisPool};
+use crate::{
+    domain::{
+        UserId,
+        data_stores::{
+            RefreshToken, RefreshTokenEntry, RefreshTokenFamilyId, RefreshTokenStore,
+            RefreshTokenStoreError,
+        },
+    },
+    utils::auth::REFRESH_TOKEN_TTL_SECONDS,
+};
+
+pub struct RedisRefreshTokenStore {
+    pool: RedisPool,
+}
+
+impl RedisRefreshTokenStore {
+    pub fn new(pool: RedisPool) -> Self {
+        Self { pool }
+    }
+
+    async fn conn(&self) -> Result<RedisConnection, RefreshTokenStoreError> {
+        self.pool
+            .get()
+            .await
+            .wrap_err("failed to connect to Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    #[tracing::instrument(name = "Add refresh token to Redis", skip_all)]
+    async fn add_token(
+        &self,
+        token: RefreshToken,
+        entry: RefreshTokenEntry,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let key = get_token_key(&token);
+        let user_key = get_user_families_key(&entry.user_id);
+        let family_id = entry.family_id.as_ref().to_owned();
+        let serialized_data = serde_json::to_string(&StoredEntry::from(entry))
+            .wrap_err("failed to serialize refresh token entry")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+        let ttl = get_ttl()?;
+
+        // Index the family by user, so that all of a user's sessions can be revoked.
+        let _: () = redis::pipe()
+            .atomic()
+            .set_ex(&key, serialized_data, ttl)
+            .ignore()
+            .sadd(&user_key, family_id)
+            .ignore()
+            .expire(&user_key, ttl as i64)
+            .ignore()
+            .query_async(&mut self.conn().await?)
+            .await
+            .wrap_err("failed to set refresh token in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get refresh token from Redis", skip_all)]
+    async fn get_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenEntry, RefreshTokenStoreError> {
+        let key = get_token_key(token);
+        let value: Option<String> = self
+            .conn()
+            .await?
+            .get(&key)
+            .await
+            .wrap_err("failed to get refresh token from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+        parse_entry(value.ok_or(RefreshTokenStoreError::TokenNotFound)?)
+    }
+
+    #[tracing::instrument(name = "Use refresh token in Redis", skip_all)]
+    async fn use_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenEntry, RefreshTokenStoreError> {
+        let value: Option<String> = redis::Script::new(USE_TOKEN_SCRIPT)
+            .key(get_token_key(token))
+            .invoke_async(&mut self.conn().await?)
+            .await
+            .wrap_err("failed to use refresh token in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+        parse_entry(value.ok_or(RefreshTokenStoreError::TokenNotFound)?)
+    }
+
+    #[tracing::instrument(name = "Revoke refresh token family in Redis", skip_all)]
+    async fn revoke_family(
+        &self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        // No token of the family outlives its TTL, so neither does the revocation.
+        let _: () = self
+            .conn()
+            .await?
+            .set_ex(get_family_key(family_id), true, get_ttl()?)
+            .await
+            .wrap_err("failed to revoke refresh token family in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Check refresh token family revocation in Redis", skip_all)]
+    async fn is_family_revoked(
+        &self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<bool, RefreshTokenStoreError> {
+        let is_revoked: bool = self
+            .conn()
+            .await?
+            .exists(get_family_key(family_id))
+            .await
+            .wrap_err("failed to check refresh token family in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(is_revoked)
+    }
+
+    #[tracing::instrument(
+        name = "Revoke all refresh token families of a user in Redis",
+        skip_all
+    )]
+    async fn revoke_all_families(&self, user_id: &UserId) -> Result<(), RefreshTokenStoreError> {
+        let user_key = get_user_families_key(user_id);
+        let mut conn = self.conn().await?;
+        let family_ids: Vec<String> = conn
+            .smembers(&user_key)
+            .await
+            .wrap_err("failed to get refresh token families from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let ttl = get_ttl()?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for family_id in family_ids {
+            pipe.set_ex(
+                format!("{}{}", REVOKED_FAMILY_KEY_PREFIX, family_id),
+                true,
+                ttl,
+            )
+            .ignore();
+        }
+        pipe.del(&user_key).ignore();
+        let _: () = pipe
+            .query_async(&mut conn)
+            .await
+            .wrap_err("failed to revoke refresh token families in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Ping Redis", skip_all)]
+    async fn ping(&self) -> Result<(), RefreshTokenStoreError> {
+        self.pool
+            .ping()
+            .await
+            .map_err(|e| RefreshTokenStoreError::UnexpectedError(e.into()))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEntry {
+    #[serde(default)]
+    user_id: Option<String>,
+    family_id: String,
+    used: bool,
+}
+
+impl From<RefreshTokenEntry> for StoredEntry {
+    fn from(entry: RefreshTokenEntry) -> Self {
+        Self {
+            user_id: Some(entry.user_id.to_string()),
+            family_id: entry.family_id.as_ref().to_owned(),
+            used: entry.used,
+        }
+    }
+}
+
+impl TryFrom<StoredEntry> for RefreshTokenEntry {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(data: StoredEntry) -> Result<Self> {
+        Ok(Self {
+            user_id: UserId::parse(&data.user_id.unwrap_or_default())?,
+            family_id: RefreshTokenFamilyId::parse(data.family_id)?,
+            used: data.used,
+        })
+    }
+}
+
+fn parse_entry(value: String) -> Result<RefreshTokenEntry, RefreshTokenStoreError> {
+    let data: StoredEntry = serde_json::from_str(&value)
+        .wrap_err("failed to deserialize refresh token entry")
+        .map_err(RefreshTokenStoreError::UnexpectedError)?;
+    // Tokens stored before users had ids are dropped: their user logs in again.
+    if data.user_id.is_none() {
+        return Err(RefreshTokenStoreError::TokenNotFound);
+    }
+    data.try_into()
+        .map_err(RefreshTokenStoreError::UnexpectedError)
+}
+
+fn get_ttl() -> Result<u64, RefreshTokenStoreError> {
+    REFRESH_TOKEN_TTL_SECONDS
+        .try_into()
+        .wrap_err("failed to cast REFRESH_TOKEN_TTL_SECONDS to u64")
+        .map_err(RefreshTokenStoreError::UnexpectedError)
+}
+
+// Mark the entry as used, and return it as it was. Keep the original expiry: using a
+// token must not extend its lifetime.
+const USE_TOKEN_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if value then
+    local entry = cjson.decode(value)
+    if not entry.used then
+        entry.used = true
+        redis.call('SET', KEYS[1], cjson.encode(entry), 'KEEPTTL')
+    end
+end
+return value
+"#;
+
+const REFRESH_TOKEN_KEY_PREFIX: &str = "refresh_token:";
+const REVOKED_FAMILY_KEY_PREFIX: &str = "refresh_token_family_revoked:";
+const USER_FAMILIES_KEY_PREFIX: &str = "refresh_token_families:";
+
+fn get_token_key(token: &RefreshToken) -> String {
+    format!(
+        "{}{}",
+        REFRESH_TOKEN_KEY_PREFIX,
+        token.as_ref().expose_secret()
+    )
+}
+
+fn get_family_key(family_id: &RefreshTokenFamilyId) -> String {
+    format!("{}{}", REVOKED_FAMILY_KEY_PREFIX, family_id.as_ref())
+}
+
+fn get_user_families_key(user_id: &UserId) -> String {
+    format!("{}{}", USER_FAMILIES_KEY_PREFIX, user_id)
+}
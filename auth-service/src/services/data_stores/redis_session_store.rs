@@ -0,0 +1,277 @@
+use color_eyre::eyre::{Context, Result};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tracing;
+
+use super::{RedisConnection, RedisPool};
+use crate::{
+    domain::{
+        ClientInfo, Session, UserId,
+        data_stores::{RefreshTokenFamilyId, SessionStore, SessionStoreError},
+    },
+    utils::auth::REFRESH_TOKEN_TTL_SECONDS,
+};
+
+pub struct RedisSessionStore {
+    pool: RedisPool,
+}
+
+impl RedisSessionStore {
+    pub fn new(pool: RedisPool) -> Self {
+        Self { pool }
+    }
+
+    async fn conn(&self) -> Result<RedisConnection, SessionStoreError> {
+        self.pool
+            .get()
+            .await
+            .wrap_err("failed to connect to Redis")
+            .map_err(SessionStoreError::UnexpectedError)
+    }
+
+    // Store the session, and keep it for as long as a refresh token issued now.
+    async fn set_session(
+        conn: &mut RedisConnection,
+        session: Session,
+    ) -> Result<(), SessionStoreError> {
+        let key = get_session_key(&session.id);
+        let user_key = get_user_sessions_key(&session.user_id);
+        let session_id = session.id.as_ref().to_owned();
+        let serialized_data = serde_json::to_string(&StoredSession::from(session))
+            .wrap_err("failed to serialize session")
+            .map_err(SessionStoreError::UnexpectedError)?;
+        let ttl = get_ttl()?;
+
+        let _: () = redis::pipe()
+            .atomic()
+            .set_ex(&key, serialized_data, ttl)
+            .ignore()
+            .sadd(&user_key, session_id)
+            .ignore()
+            .expire(&user_key, ttl as i64)
+            .ignore()
+            .query_async(conn)
+            .await
+            .wrap_err("failed to set session in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn get_session(
+        conn: &mut RedisConnection,
+        id: &RefreshTokenFamilyId,
+    ) -> Result<Session, SessionStoreError> {
+        let value: Option<String> = conn
+            .get(get_session_key(id))
+            .await
+            .wrap_err("failed to get session from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+        let value = value.ok_or(SessionStoreError::SessionNotFound)?;
+
+        let data: StoredSession = serde_json::from_str(&value)
+            .wrap_err("failed to deserialize session")
+            .map_err(SessionStoreError::UnexpectedError)?;
+        // Sessions stored before users had ids are ended: their user logs in again.
+        if data.user_id.is_none() {
+            return Err(SessionStoreError::SessionNotFound);
+        }
+        data.try_into().map_err(SessionStoreError::UnexpectedError)
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    #[tracing::instrument(name = "Add session to Redis", skip_all)]
+    async fn add_session(&self, session: Session) -> Result<(), SessionStoreError> {
+        Self::set_session(&mut self.conn().await?, session).await
+    }
+
+    #[tracing::instrument(name = "Get session from Redis", skip_all)]
+    async fn get_session(&self, id: &RefreshTokenFamilyId) -> Result<Session, SessionStoreError> {
+        Self::get_session(&mut self.conn().await?, id).await
+    }
+
+    #[tracing::instrument(name = "Get user sessions from Redis", skip_all)]
+    async fn get_user_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError> {
+        let mut conn = self.conn().await?;
+        let session_ids: Vec<String> = conn
+            .smembers(get_user_sessions_key(user_id))
+            .await
+            .wrap_err("failed to get user sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        // Expired sessions stay in the index until the index itself expires.
+        let mut sessions = Vec::with_capacity(session_ids.len());
+        for session_id in session_ids {
+            let id = RefreshTokenFamilyId::parse(session_id)
+                .map_err(SessionStoreError::UnexpectedError)?;
+            match Self::get_session(&mut conn, &id).await {
+                Ok(session) => sessions.push(session),
+                Err(SessionStoreError::SessionNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sessions)
+    }
+
+    #[tracing::instrument(name = "Touch session in Redis", skip_all)]
+    async fn touch_session(
+        &self,
+        id: &RefreshTokenFamilyId,
+        now: i64,
+    ) -> Result<(), SessionStoreError> {
+        // A revoked session is not brought back: it is only updated while it exists.
+        let touched: bool = redis::Script::new(TOUCH_SESSION_SCRIPT)
+            .key(get_session_key(id))
+            .arg(now)
+            .arg(get_ttl()?)
+            .arg(USER_SESSIONS_KEY_PREFIX)
+            .invoke_async(&mut self.conn().await?)
+            .await
+            .wrap_err("failed to touch session in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        match touched {
+            true => Ok(()),
+            false => Err(SessionStoreError::SessionNotFound),
+        }
+    }
+
+    #[tracing::instrument(name = "Remove session from Redis", skip_all)]
+    async fn remove_session(&self, id: &RefreshTokenFamilyId) -> Result<(), SessionStoreError> {
+        let mut conn = self.conn().await?;
+        let session = match Self::get_session(&mut conn, id).await {
+            Ok(session) => session,
+            Err(SessionStoreError::SessionNotFound) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let _: () = redis::pipe()
+            .atomic()
+            .del(get_session_key(id))
+            .ignore()
+            .srem(get_user_sessions_key(&session.user_id), id.as_ref())
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .wrap_err("failed to remove session from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Remove user sessions from Redis", skip_all)]
+    async fn remove_user_sessions(&self, user_id: &UserId) -> Result<(), SessionStoreError> {
+        let user_key = get_user_sessions_key(user_id);
+        let mut conn = self.conn().await?;
+        let session_ids: Vec<String> = conn
+            .smembers(&user_key)
+            .await
+            .wrap_err("failed to get user sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for session_id in session_ids {
+            pipe.del(format!("{}{}", SESSION_KEY_PREFIX, session_id))
+                .ignore();
+        }
+        pipe.del(&user_key).ignore();
+        let _: () = pipe
+            .query_async(&mut conn)
+            .await
+            .wrap_err("failed to remove user sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Ping Redis", skip_all)]
+    async fn ping(&self) -> Result<(), SessionStoreError> {
+        self.pool
+            .ping()
+            .await
+            .map_err(|e| SessionStoreError::UnexpectedError(e.into()))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    id: String,
+    #[serde(default)]
+    user_id: Option<String>,
+    ip: String,
+    user_agent: Option<String>,
+    created_at: i64,
+    last_seen_at: i64,
+}
+
+impl From<Session> for StoredSession {
+    fn from(session: Session) -> Self {
+        Self {
+            id: session.id.as_ref().to_owned(),
+            user_id: Some(session.user_id.to_string()),
+            ip: session.client.ip.to_string(),
+            user_agent: session.client.user_agent,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        }
+    }
+}
+
+impl TryFrom<StoredSession> for Session {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(data: StoredSession) -> Result<Self> {
+        Ok(Self {
+            id: RefreshTokenFamilyId::parse(data.id)?,
+            user_id: UserId::parse(&data.user_id.unwrap_or_default())?,
+            client: ClientInfo {
+                ip: data.ip.parse().wrap_err("invalid session IP address")?,
+                user_agent: data.user_agent,
+            },
+            created_at: data.created_at,
+            last_seen_at: data.last_seen_at,
+        })
+    }
+}
+
+fn get_ttl() -> Result<u64, SessionStoreError> {
+    REFRESH_TOKEN_TTL_SECONDS
+        .try_into()
+        .wrap_err("failed to cast REFRESH_TOKEN_TTL_SECONDS to u64")
+        .map_err(SessionStoreError::UnexpectedError)
+}
+
+// Update the last-seen time, and keep the session for as long as a refresh token
+// issued now. Sessions without a user are left to expire, as in `get_session`.
+const TOUCH_SESSION_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if not value then
+    return 0
+end
+local session = cjson.decode(value)
+if type(session.user_id) ~= 'string' then
+    return 0
+end
+session.last_seen_at = tonumber(ARGV[1])
+redis.call('SET', KEYS[1], cjson.encode(session), 'EX', ARGV[2])
+redis.call('EXPIRE', ARGV[3] .. session.user_id, ARGV[2])
+return 1
+"#;
+
+const SESSION_KEY_PREFIX: &str = "session:";
+const USER_SESSIONS_KEY_PREFIX: &str = "user_sessions:";
+
+fn get_session_key(id: &RefreshTokenFamilyId) -> String {
+    format!("{}{}", SESSION_KEY_PREFIX, id.as_ref())
+}
+
+fn get_user_sessions_key(user_id: &UserId) -> String {
+    format!("{}{}", USER_SESSIONS_KEY_PREFIX, user_id)
+}
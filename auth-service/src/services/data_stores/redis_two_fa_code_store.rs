@@ -1,22 +1,29 @@
+use super::{RedisConnection, RedisPool};
 use crate::domain::{
     Email,
     data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
 };
 use color_eyre::eyre::Context;
-use redis::{Commands, Connection};
+use redis::AsyncCommands;
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing;
 
 pub struct RedisTwoFACodeStore {
-    conn: Arc<RwLock<Connection>>,
+    pool: RedisPool,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(pool: RedisPool) -> Self {
+        Self { pool }
+    }
+
+    async fn conn(&self) -> Result<RedisConnection, TwoFACodeStoreError> {
+        self.pool
+            .get()
+            .await
+            .wrap_err("failed to connect to Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)
     }
 }
 
@@ -24,37 +31,37 @@ impl RedisTwoFACodeStore {
 impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "Add 2FA code to Redis", skip_all)]
     async fn add_code(
-        &mut self,
+        &self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code: Option<TwoFACode>,
     ) -> Result<(), TwoFACodeStoreError> {
         let twofa = TwoFATuple(
             login_attempt_id.as_ref().expose_secret().to_owned(),
-            code.as_ref().expose_secret().to_owned(),
+            code.map(|code| code.as_ref().expose_secret().to_owned()),
         );
         let key = get_key(&email);
         let serialized_data = serde_json::to_string(&twofa)
             .wrap_err("failed to serialize 2FA tuple")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
         let _: () = self
-            .conn
-            .write()
-            .await
+            .conn()
+            .await?
             .set_ex(&key, serialized_data, TEN_MINUTES_IN_SECONDS)
+            .await
             .wrap_err("failed to set 2FA code in Redis") // New!
             .map_err(TwoFACodeStoreError::UnexpectedError)?; // Upd
         Ok(())
     }
 
     #[tracing::instrument(name = "Delete 2FA code from Redis", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+    async fn remove_code(&self, email: &Email) -> Result<(), TwoFACodeStoreError> {
         let key = get_key(email);
         let _: () = self
-            .conn
-            .write()
-            .await
+            .conn()
+            .await?
             .del(&key)
+            .await
             .wrap_err("failed to delete 2FA code from Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
@@ -65,10 +72,10 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+    ) -> Result<(LoginAttemptId, Option<TwoFACode>), TwoFACodeStoreError> {
         let key = get_key(email);
 
-        match self.conn.write().await.get::<_, String>(&key) {
+        match self.conn().await?.get::<_, String>(&key).await {
             Ok(value) => {
                 let data: TwoFATuple = serde_json::from_str(&value)
                     .wrap_err("failed to deserialize 2FA tuple") // New!
@@ -77,7 +84,10 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
                 let login_attempt_id = LoginAttemptId::parse(Secret::new(data.0))
                     .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-                let email_code = TwoFACode::parse(Secret::new(data.1))
+                let email_code = data
+                    .1
+                    .map(|code| TwoFACode::parse(Secret::new(code)))
+                    .transpose()
                     .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
                 Ok((login_attempt_id, email_code))
@@ -85,10 +95,22 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
             Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
+
+    #[tracing::instrument(name = "Ping Redis", skip_all)]
+    async fn ping(&self) -> Result<(), TwoFACodeStoreError> {
+        self.pool
+            .ping()
+            .await
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+struct TwoFATuple(pub String, pub Option<String>);
 
 const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
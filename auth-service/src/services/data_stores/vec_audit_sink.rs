@@ -0,0 +1,103 @@
+use parking_lot::RwLock;
+
+use crate::domain::{
+    AuditEntry, AuditEvent, AuditQuery, GENESIS_HASH,
+    data_stores::{AuditSink, AuditSinkError},
+};
+
+// Keep the audit log in memory, e.g. for tests.
+#[derive(Default)]
+pub struct VecAuditSink {
+    entries: RwLock<Vec<AuditEntry>>,
+}
+
+#[async_trait::async_trait]
+impl AuditSink for VecAuditSink {
+    async fn record(&self, event: AuditEvent) -> Result<AuditEntry, AuditSinkError> {
+        let mut entries = self.entries.write();
+        let (sequence, previous_hash) = match entries.last() {
+            Some(last) => (last.sequence + 1, last.hash.clone()),
+            None => (1, GENESIS_HASH.to_owned()),
+        };
+        let entry = AuditEntry::new(sequence, previous_hash, event);
+        entries.push(entry.clone());
+        Ok(entry)
+    }
+
+    async fn query(&self, query: AuditQuery) -> Result<Vec<AuditEntry>, AuditSinkError> {
+        let limit = query.limit.map_or(usize::MAX, |limit| limit as usize);
+        Ok(self
+            .entries
+            .read()
+            .iter()
+            .filter(|entry| query.matches(&entry.event))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{AuditActor, AuditEventKind, AuditOutcome, UserId, verify_chain};
+
+    fn get_event(user_id: UserId, timestamp: i64) -> AuditEvent {
+        AuditEvent {
+            kind: AuditEventKind::Login,
+            outcome: AuditOutcome::Success,
+            reason: None,
+            actor: AuditActor::User(user_id),
+            user_id: Some(user_id),
+            ip: None,
+            user_agent: None,
+            request_id: None,
+            timestamp,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_chains_entries() {
+        let sink = VecAuditSink::default();
+        let user_id = UserId::default();
+
+        let first = sink.record(get_event(user_id, 1)).await.unwrap();
+        let second = sink.record(get_event(user_id, 2)).await.unwrap();
+        assert_eq!(first.sequence, 1);
+        assert_eq!(first.previous_hash, GENESIS_HASH);
+        assert_eq!(second.sequence, 2);
+        assert_eq!(second.previous_hash, first.hash);
+
+        let entries = sink.query(AuditQuery::default()).await.unwrap();
+        assert_eq!(entries, vec![first, second]);
+        assert_eq!(verify_chain(&entries), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let sink = VecAuditSink::default();
+        let user_id = UserId::default();
+        let other_id = UserId::default();
+        for timestamp in 1..=4 {
+            sink.record(get_event(user_id, timestamp)).await.unwrap();
+            sink.record(get_event(other_id, timestamp)).await.unwrap();
+        }
+
+        let query = AuditQuery {
+            user_id: Some(user_id),
+            from: Some(2),
+            to: Some(3),
+            limit: None,
+        };
+        let entries = sink.query(query).await.unwrap();
+        let timestamps: Vec<i64> = entries.iter().map(|entry| entry.event.timestamp).collect();
+        assert_eq!(timestamps, vec![2, 3]);
+        assert!(entries.iter().all(|entry| entry.event.user_id == Some(user_id)));
+
+        let query = AuditQuery {
+            limit: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(sink.query(query).await.unwrap().len(), 3);
+    }
+}
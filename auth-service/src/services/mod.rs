@@ -1,5 +1,6 @@
 pub mod data_stores;
 pub mod mock_email_client;
 pub mod postmark_email_client;
+pub mod smtp_email_client;
 
 pub use data_stores::*;
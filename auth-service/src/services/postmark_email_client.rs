@@ -3,6 +3,7 @@ use reqwest::{Client, Url};
 use secrecy::{ExposeSecret, Secret};
 
 use crate::domain::{Email, EmailClient};
+use crate::utils::metrics;
 
 pub struct PostmarkEmailClient {
     http_client: Client,
@@ -52,7 +53,33 @@ impl EmailClient for PostmarkEmailClient {
             )
             .json(&request_body);
 
-        request.send().await?.error_for_status()?;
+        let result = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if result.is_err() {
+            metrics::record_email_send_failure();
+        }
+        result?;
+
+        Ok(())
+    }
+
+    // Postmark describes the server the token belongs to, and refuses unknown tokens.
+    #[tracing::instrument(name = "Pinging email provider", skip_all)]
+    async fn ping(&self) -> Result<()> {
+        let base = Url::parse(&self.base_url)?;
+        let url = base.join("/server")?;
+
+        self.http_client
+            .get(url)
+            .header(
+                POSTMARK_AUTH_HEADER,
+                self.authorization_token.expose_secret(),
+            )
+            .send()
+            .await?
+            .error_for_status()?;
 
         Ok(())
     }
@@ -74,17 +101,18 @@ struct SendEmailRequest<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::constants::test;
-
     use super::*;
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
+    use std::time::Duration;
     use wiremock::matchers::{any, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     use super::PostmarkEmailClient;
 
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
     fn subject() -> String {
         Sentence(1..2).fake()
     }
@@ -98,10 +126,7 @@ mod tests {
     }
 
     fn email_client(base_url: String) -> PostmarkEmailClient {
-        let http_client = Client::builder()
-            .timeout(test::email_client::TIMEOUT)
-            .build()
-            .unwrap();
+        let http_client = Client::builder().timeout(TIMEOUT).build().unwrap();
         PostmarkEmailClient::new(base_url, email(), Secret::new(Faker.fake()), http_client)
     }
 
@@ -190,4 +215,26 @@ mod tests {
 
         assert!(outcome.is_err());
     }
+
+    // Test to ensure a ping checks the token with Postmark
+    #[tokio::test]
+    async fn ping_checks_the_server_token() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists(POSTMARK_AUTH_HEADER))
+            .and(path("/server"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+        assert!(email_client.ping().await.is_ok());
+
+        mock_server.reset().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+        assert!(email_client.ping().await.is_err());
+    }
 }
@@ -0,0 +1,363 @@
+use color_eyre::eyre::{Report, Result, eyre};
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::header::ContentType,
+    transport::smtp::{
+        PoolConfig,
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+    },
+};
+use secrecy::ExposeSecret;
+use std::time::Duration;
+
+use crate::domain::{Email, EmailClient};
+use crate::utils::{
+    metrics,
+    settings::{SmtpSettings, SmtpTls},
+};
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Email,
+    timeout: Duration,
+}
+
+impl SmtpEmailClient {
+    // `timeout` bounds sending an email, from connecting to the server's last answer.
+    pub fn new(settings: &SmtpSettings, sender: Email, timeout: Duration) -> Result<Self> {
+        let tls = match settings.tls {
+            SmtpTls::StartTls => Tls::Required(TlsParameters::new(settings.host.clone())?),
+            SmtpTls::Tls => Tls::Wrapper(TlsParameters::new(settings.host.clone())?),
+            SmtpTls::None => Tls::None,
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            .port(settings.port.unwrap_or(settings.tls.default_port()))
+            .tls(tls)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(settings.max_connections));
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(
+                username.to_owned(),
+                password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+            timeout,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Sending email", skip_all)]
+    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+        let message = Message::builder()
+            .from(self.sender.as_ref().expose_secret().parse()?)
+            .to(recipient.as_ref().expose_secret().parse()?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(content.to_owned())?;
+
+        // The transport only bounds connecting: a server that stops answering would
+        // hold the request forever.
+        let result = match tokio::time::timeout(self.timeout, self.transport.send(message)).await {
+            Ok(result) => result.map(|_| ()).map_err(Report::from),
+            Err(_) => Err(eyre!(
+                "SMTP server did not answer within {:?}",
+                self.timeout
+            )),
+        };
+        if result.is_err() {
+            metrics::record_email_send_failure();
+        }
+        result
+    }
+
+    // Connecting logs in, so the server refuses wrong credentials.
+    #[tracing::instrument(name = "Pinging email provider", skip_all)]
+    async fn ping(&self) -> Result<()> {
+        let connected = tokio::time::timeout(self.timeout, self.transport.test_connection())
+            .await
+            .map_err(|_| eyre!("SMTP server did not answer within {:?}", self.timeout))??;
+        match connected {
+            true => Ok(()),
+            false => Err(eyre!("SMTP server did not answer NOOP")),
+        }
+    }
+
+    // Pooled connections are closed with QUIT.
+    async fn close(&self) {
+        self.transport.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use fake::Fake;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use secrecy::Secret;
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+    const USERNAME: &str = "auth-service";
+    const PASSWORD: &str = "smtp-password";
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> Email {
+        Email::parse(Secret::new(SafeEmail().fake())).unwrap()
+    }
+
+    // What the fake server received.
+    #[derive(Default)]
+    struct Received {
+        connections: usize,
+        quits: usize,
+        recipients: Vec<String>,
+        messages: Vec<String>,
+    }
+
+    // An SMTP server speaking just enough of the protocol for the client, in plain
+    // text. It offers AUTH PLAIN, and STARTTLS if asked to, without supporting it.
+    struct FakeSmtpServer {
+        port: u16,
+        received: Arc<Mutex<Received>>,
+    }
+
+    impl FakeSmtpServer {
+        async fn start(offer_starttls: bool) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let received = Arc::new(Mutex::new(Received::default()));
+            let state = received.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    state.lock().unwrap().connections += 1;
+                    tokio::spawn(serve(stream, offer_starttls, state.clone()));
+                }
+            });
+
+            Self { port, received }
+        }
+
+        fn settings(&self, tls: SmtpTls) -> SmtpSettings {
+            SmtpSettings {
+                host: "127.0.0.1".to_owned(),
+                port: Some(self.port),
+                tls,
+                username: Some(USERNAME.to_owned()),
+                password: Some(Secret::new(PASSWORD.to_owned())),
+                max_connections: 2,
+            }
+        }
+    }
+
+    async fn serve(
+        stream: tokio::net::TcpStream,
+        offer_starttls: bool,
+        received: Arc<Mutex<Received>>,
+    ) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let expected_auth = STANDARD.encode(format!("\0{}\0{}", USERNAME, PASSWORD));
+
+        writer.write_all(b"220 fake ESMTP\r\n").await?;
+        while let Some(line) = lines.next_line().await? {
+            let command = line.to_uppercase();
+            let reply = if command.starts_with("EHLO") {
+                match offer_starttls {
+                    true => "250-fake\r\n250-STARTTLS\r\n250 AUTH PLAIN\r\n".to_owned(),
+                    false => "250-fake\r\n250 AUTH PLAIN\r\n".to_owned(),
+                }
+            } else if command.starts_with("AUTH PLAIN") {
+                match line.split_whitespace().nth(2) == Some(expected_auth.as_str()) {
+                    true => "235 2.7.0 Authentication successful\r\n".to_owned(),
+                    false => "535 5.7.8 Authentication failed\r\n".to_owned(),
+                }
+            } else if command.starts_with("RCPT TO") {
+                received.lock().unwrap().recipients.push(line.clone());
+                "250 OK\r\n".to_owned()
+            } else if command == "DATA" {
+                writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await?;
+                let mut message = String::new();
+                while let Some(line) = lines.next_line().await? {
+                    if line == "." {
+                        break;
+                    }
+                    message.push_str(&line);
+                    message.push('\n');
+                }
+                received.lock().unwrap().messages.push(message);
+                "250 OK\r\n".to_owned()
+            } else if command == "QUIT" {
+                received.lock().unwrap().quits += 1;
+                writer.write_all(b"221 Bye\r\n").await?;
+                return Ok(());
+            } else {
+                // MAIL FROM, NOOP and RSET.
+                "250 OK\r\n".to_owned()
+            };
+            writer.write_all(reply.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    fn email_client(settings: &SmtpSettings) -> SmtpEmailClient {
+        SmtpEmailClient::new(settings, email(), TIMEOUT).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message() {
+        let server = FakeSmtpServer::start(false).await;
+        let email_client = email_client(&server.settings(SmtpTls::None));
+        let recipient = email();
+        let subject = subject();
+
+        let outcome = email_client
+            .send_email(&recipient, &subject, &content())
+            .await;
+        assert!(outcome.is_ok(), "{:?}", outcome);
+
+        let received = server.received.lock().unwrap();
+        assert_eq!(
+            received.recipients,
+            vec![format!("RCPT TO:<{}>", recipient.as_ref().expose_secret())]
+        );
+        assert!(received.messages[0].contains(&format!("Subject: {}", subject)));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_with_wrong_credentials() {
+        let server = FakeSmtpServer::start(false).await;
+        let mut settings = server.settings(SmtpTls::None);
+        settings.password = Some(Secret::new("wrong-password".to_owned()));
+        let email_client = email_client(&settings);
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content())
+            .await;
+
+        assert!(outcome.is_err());
+        assert!(server.received.lock().unwrap().messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_email_reuses_connections() {
+        let server = FakeSmtpServer::start(false).await;
+        let email_client = email_client(&server.settings(SmtpTls::None));
+
+        for _ in 0..3 {
+            let outcome = email_client
+                .send_email(&email(), &subject(), &content())
+                .await;
+            assert!(outcome.is_ok(), "{:?}", outcome);
+            // Connections go back to the pool in the background.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let received = server.received.lock().unwrap();
+        assert_eq!(received.messages.len(), 3);
+        assert_eq!(received.connections, 1);
+    }
+
+    #[tokio::test]
+    async fn close_quits_pooled_connections() {
+        let server = FakeSmtpServer::start(false).await;
+        let email_client = email_client(&server.settings(SmtpTls::None));
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content())
+            .await;
+        assert!(outcome.is_ok(), "{:?}", outcome);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        email_client.close().await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(server.received.lock().unwrap().quits, 1);
+    }
+
+    // The password must never be sent in plain text to a server that cannot encrypt.
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_does_not_offer_starttls() {
+        let server = FakeSmtpServer::start(false).await;
+        let email_client = email_client(&server.settings(SmtpTls::StartTls));
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content())
+            .await;
+
+        assert!(outcome.is_err());
+        assert!(server.received.lock().unwrap().messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_does_not_speak_tls() {
+        let server = FakeSmtpServer::start(true).await;
+        let email_client = email_client(&server.settings(SmtpTls::Tls));
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content())
+            .await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_does_not_answer() {
+        // Accept connections, but never greet the client.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        let settings = SmtpSettings {
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            tls: SmtpTls::None,
+            ..SmtpSettings::default()
+        };
+        let email_client = email_client(&settings);
+
+        let outcome = tokio::time::timeout(
+            TIMEOUT * 4,
+            email_client.send_email(&email(), &subject(), &content()),
+        )
+        .await
+        .expect("The client did not time out");
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn ping_checks_the_credentials() {
+        let server = FakeSmtpServer::start(false).await;
+        let settings = server.settings(SmtpTls::None);
+        assert!(email_client(&settings).ping().await.is_ok());
+
+        let mut settings = server.settings(SmtpTls::None);
+        settings.password = Some(Secret::new("wrong-password".to_owned()));
+        assert!(email_client(&settings).ping().await.is_err());
+    }
+}
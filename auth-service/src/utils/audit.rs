@@ -0,0 +1,126 @@
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use chrono::Utc;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    AppState,
+    app_state::AuditSinkType,
+    domain::{
+        AuditActor, AuditEvent, AuditEventKind, AuditOutcome, AuthAPIError, ClientInfo, UserId,
+    },
+    utils::{metrics, tracing::RequestId},
+};
+
+// Events waiting to be written before requests recording more have to wait.
+const AUDIT_QUEUE_CAPACITY: usize = 1024;
+
+enum AuditCommand {
+    Record(AuditEvent),
+    // Answered once every event queued before it is written.
+    Flush(oneshot::Sender<()>),
+}
+
+// Appends events to the audit log from a single task, so that requests do not wait
+// for the sink, nor for each other to chain their entries.
+#[derive(Clone)]
+pub struct AuditWriter {
+    sender: mpsc::Sender<AuditCommand>,
+}
+
+impl AuditWriter {
+    // Start the task writing to `sink`. It stops once every writer is dropped.
+    pub fn spawn(sink: AuditSinkType) -> Self {
+        let (sender, mut receiver) = mpsc::channel(AUDIT_QUEUE_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                match command {
+                    AuditCommand::Record(event) => {
+                        if let Err(e) = sink.record(event).await {
+                            tracing::error!("failed to record audit event: {:?}", e);
+                        }
+                    }
+                    AuditCommand::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    // Queue the event. Only waits while the queue is full.
+    pub async fn record(&self, event: AuditEvent) {
+        if self.sender.send(AuditCommand::Record(event)).await.is_err() {
+            tracing::error!("failed to record audit event: the audit writer stopped");
+        }
+    }
+
+    // Wait until the events queued so far are written.
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.sender.send(AuditCommand::Flush(done)).await.is_ok() {
+            let _ = written.await;
+        }
+    }
+}
+
+// Records the results of a request in the audit log, along with where it came from,
+// and counts them in the metrics.
+pub struct Auditor {
+    writer: AuditWriter,
+    client: ClientInfo,
+    request_id: Option<String>,
+}
+
+impl Auditor {
+    // Record the result of a request by `actor` about the account `user_id`, if any.
+    // The event is written in the background: a failure to record is logged, but does
+    // not fail the request.
+    pub async fn record<T>(
+        &self,
+        kind: AuditEventKind,
+        actor: AuditActor,
+        user_id: Option<UserId>,
+        result: &Result<T, AuthAPIError>,
+    ) {
+        let (outcome, reason) = match result {
+            Ok(_) => (AuditOutcome::Success, None),
+            Err(e) => (AuditOutcome::Failure, Some(e.to_string())),
+        };
+        metrics::record_auth_event(kind, outcome);
+        let event = AuditEvent {
+            kind,
+            outcome,
+            reason,
+            actor,
+            user_id,
+            ip: Some(self.client.ip),
+            user_agent: self.client.user_agent.clone(),
+            request_id: self.request_id.clone(),
+            timestamp: Utc::now().timestamp_millis(),
+        };
+        self.writer.record(event).await;
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Auditor {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let client = ClientInfo::from_request_parts(parts, state).await?;
+        let request_id = parts
+            .extensions
+            .get::<RequestId>()
+            .map(|RequestId(id)| id.clone());
+
+        Ok(Self {
+            writer: state.audit_writer.clone(),
+            client,
+            request_id,
+        })
+    }
+}
@@ -1,70 +1,252 @@
-use crate::app_state::BannedTokenStoreType;
-use crate::domain::email::Email;
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use crate::app_state::{
+    BannedTokenStoreType, KeyRingType, RefreshTokenStoreType, SessionStoreType,
+};
+use crate::domain::{
+    AuthAPIError, Grants, Password, RefreshToken, RefreshTokenEntry, RefreshTokenFamilyId, UserId,
+    email::Email,
+};
+use axum::http::HeaderMap;
+use axum_extra::extract::{
+    CookieJar,
+    cookie::{Cookie, SameSite},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use chrono::Utc;
 use color_eyre::eyre::{Context, OptionExt, Result, eyre};
-use jsonwebtoken::{DecodingKey, EncodingKey, Validation, decode, encode};
+use jsonwebtoken::{Validation, decode, decode_header, encode};
 use secrecy::{ExposeSecret, Secret};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tracing;
+use uuid::Uuid;
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use super::constants::{
+    ADMIN_API_KEY_HEADER, JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, REFRESH_TOKEN_COOKIE_PATH,
+};
 
-// Create cookie with a new JWT auth token
+// Create cookie with a new JWT auth token for the given session, carrying the
+// user's grants
 #[tracing::instrument(name = "Generate auth cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
+pub async fn generate_auth_cookie(
+    key_ring: KeyRingType,
+    user_id: &UserId,
+    grants: &Grants,
+    session_id: &RefreshTokenFamilyId,
+) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(key_ring, user_id, grants, session_id).await?;
     Ok(create_auth_cookie(token))
 }
 
 // Create cookie and set the value to the passed-in token string
 #[tracing::instrument(name = "Create auth cookie", skip_all)]
 fn create_auth_cookie(token: String) -> Cookie<'static> {
-    let cookie = Cookie::build((JWT_COOKIE_NAME, token))
+    Cookie::build((JWT_COOKIE_NAME, token))
         .path("/")
         .http_only(true)
         .same_site(SameSite::Lax)
-        .build();
+        .build()
+}
+
+// This value determines how long a refresh token can be exchanged for a new JWT
+pub const REFRESH_TOKEN_TTL_SECONDS: i64 = 2_592_000; // 30 days
+
+// Store a new refresh token in the given family, and create its cookie
+#[tracing::instrument(name = "Generate refresh cookie", skip_all)]
+pub async fn generate_refresh_cookie(
+    refresh_token_store: RefreshTokenStoreType,
+    user_id: &UserId,
+    family_id: RefreshTokenFamilyId,
+) -> Result<Cookie<'static>> {
+    let token = RefreshToken::default();
+    refresh_token_store
+        .add_token(token.clone(), RefreshTokenEntry::new(*user_id, family_id))
+        .await?;
 
-    cookie
+    Ok(create_refresh_cookie(token))
 }
 
-// This value determines how long the JWT auth token is valid for
-pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
+// Create cookie and set the value to the passed-in refresh token.
+// Unlike the JWT cookie, it must survive browser restarts.
+#[tracing::instrument(name = "Create refresh cookie", skip_all)]
+fn create_refresh_cookie(token: RefreshToken) -> Cookie<'static> {
+    Cookie::build((
+        REFRESH_TOKEN_COOKIE_NAME,
+        token.as_ref().expose_secret().to_owned(),
+    ))
+    .path(REFRESH_TOKEN_COOKIE_PATH)
+    .http_only(true)
+    .same_site(SameSite::Lax)
+    .max_age(time::Duration::seconds(REFRESH_TOKEN_TTL_SECONDS))
+    .build()
+}
 
 // Create JWT auth token
 #[tracing::instrument(name = "Generate auth token", skip_all)]
-fn generate_auth_token(email: &Email) -> Result<String> {
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .ok_or_eyre("failed to create 10 minute time delta")?;
+async fn generate_auth_token(
+    key_ring: KeyRingType,
+    user_id: &UserId,
+    grants: &Grants,
+    session_id: &RefreshTokenFamilyId,
+) -> Result<String> {
+    let ttl_seconds = key_ring.read().await.token_ttl_seconds();
+    let (iat, iat_ms) = issued_now()?;
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp: expires_in(ttl_seconds)?,
+        iat,
+        iat_ms,
+        jti: Uuid::new_v4().to_string(),
+        sid: session_id.as_ref().to_owned(),
+        roles: grants
+            .roles
+            .iter()
+            .map(|role| role.as_ref().to_owned())
+            .collect(),
+        scope: grants
+            .permissions
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    create_token(key_ring, &claims).await
+}
+
+// This value determines how long a password reset link is valid for
+pub const PASSWORD_RESET_TOKEN_TTL_SECONDS: i64 = 1800; // 30 minutes
+
+// Keeps reset tokens from being accepted as auth tokens, and the other way around.
+const PASSWORD_RESET_AUDIENCE: &str = "password-reset";
+
+// Create the signed token of a password reset link. It is bound to the current
+// password, so it can only be used once.
+#[tracing::instrument(name = "Generate password reset token", skip_all)]
+pub async fn generate_password_reset_token(
+    key_ring: KeyRingType,
+    email: &Email,
+    password: &Password,
+) -> Result<Secret<String>> {
+    let claims = PasswordResetClaims {
+        sub: email.as_ref().expose_secret().clone(),
+        exp: expires_in(PASSWORD_RESET_TOKEN_TTL_SECONDS)?,
+        aud: PASSWORD_RESET_AUDIENCE.to_owned(),
+        pwd: password_fingerprint(password),
+    };
+    create_token(key_ring, &claims).await.map(Secret::new)
+}
+
+// Check the signature and expiry of a password reset token. Callers still have to
+// compare its fingerprint with the user's current password.
+#[tracing::instrument(name = "Validate password reset token", skip_all)]
+pub async fn validate_password_reset_token(
+    key_ring: KeyRingType,
+    token: &Secret<String>,
+) -> Result<PasswordResetClaims> {
+    decode_token(key_ring, token, Some(PASSWORD_RESET_AUDIENCE)).await
+}
+
+// Short digest of the stored password (hash), which changes with every new password.
+pub fn password_fingerprint(password: &Password) -> String {
+    let digest = ring::digest::digest(
+        &ring::digest::SHA256,
+        password.as_ref().expose_secret().as_bytes(),
+    );
+    URL_SAFE_NO_PAD.encode(&digest.as_ref()[..16])
+}
+
+// This value determines how long an email verification link is valid for
+pub const EMAIL_VERIFICATION_TOKEN_TTL_SECONDS: i64 = 86_400; // 24 hours
+
+const EMAIL_VERIFICATION_AUDIENCE: &str = "email-verification";
+
+// Create the signed token of an email verification link.
+#[tracing::instrument(name = "Generate email verification token", skip_all)]
+pub async fn generate_email_verification_token(
+    key_ring: KeyRingType,
+    email: &Email,
+) -> Result<Secret<String>> {
+    let claims = EmailVerificationClaims {
+        sub: email.as_ref().expose_secret().clone(),
+        exp: expires_in(EMAIL_VERIFICATION_TOKEN_TTL_SECONDS)?,
+        aud: EMAIL_VERIFICATION_AUDIENCE.to_owned(),
+    };
+    create_token(key_ring, &claims).await.map(Secret::new)
+}
+
+#[tracing::instrument(name = "Validate email verification token", skip_all)]
+pub async fn validate_email_verification_token(
+    key_ring: KeyRingType,
+    token: &Secret<String>,
+) -> Result<EmailVerificationClaims> {
+    decode_token(key_ring, token, Some(EMAIL_VERIFICATION_AUDIENCE)).await
+}
+
+const EMAIL_CHANGE_AUDIENCE: &str = "email-change";
+
+// Create the signed token of the link confirming a new email address. It names the
+// current address, so it can only be used while the user still has it.
+#[tracing::instrument(name = "Generate email change token", skip_all)]
+pub async fn generate_email_change_token(
+    key_ring: KeyRingType,
+    email: &Email,
+    new_email: &Email,
+) -> Result<Secret<String>> {
+    let claims = EmailChangeClaims {
+        sub: email.as_ref().expose_secret().clone(),
+        exp: expires_in(EMAIL_VERIFICATION_TOKEN_TTL_SECONDS)?,
+        aud: EMAIL_CHANGE_AUDIENCE.to_owned(),
+        new_email: new_email.as_ref().expose_secret().clone(),
+    };
+    create_token(key_ring, &claims).await.map(Secret::new)
+}
+
+#[tracing::instrument(name = "Validate email change token", skip_all)]
+pub async fn validate_email_change_token(
+    key_ring: KeyRingType,
+    token: &Secret<String>,
+) -> Result<EmailChangeClaims> {
+    decode_token(key_ring, token, Some(EMAIL_CHANGE_AUDIENCE)).await
+}
+
+fn expires_in(ttl_seconds: i64) -> Result<usize> {
+    let delta = chrono::Duration::try_seconds(ttl_seconds)
+        .ok_or_eyre(format!("failed to create {}s time delta", ttl_seconds))?;
 
     let exp = Utc::now()
         .checked_add_signed(delta)
-        .ok_or_eyre("failed to add 10 minutes to current time")?
+        .ok_or_eyre(format!("failed to add {}s to current time", ttl_seconds))?
         .timestamp();
 
-    let exp: usize = exp.try_into().wrap_err(format!(
+    exp.try_into().wrap_err(format!(
         "failed to cast exp time to usize. exp time: {}",
         exp
-    ))?;
+    ))
+}
 
-    let sub = email.as_ref().expose_secret().clone();
-    let claims = Claims { sub, exp };
-    create_token(&claims)
+// The issue time in seconds, and in milliseconds so that a token issued right after
+// a ban is told apart from the tokens it banned.
+fn issued_now() -> Result<(usize, i64)> {
+    let iat_ms = Utc::now().timestamp_millis();
+    let iat = iat_ms / 1000;
+    let iat = iat.try_into().wrap_err(format!(
+        "failed to cast iat time to usize. iat time: {}",
+        iat
+    ))?;
+    Ok((iat, iat_ms))
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
+// How often the last-seen time of a session is written, at most.
+const SESSION_LAST_SEEN_INTERVAL_SECONDS: i64 = 60;
+
+// Check if JWT auth token is valid by decoding it with the key named by its `kid`,
+// and that its session has not been revoked
 #[tracing::instrument(name = "Generate JWT token", skip_all)]
 pub async fn validate_token(
     banned_token_store: BannedTokenStoreType,
+    session_store: SessionStoreType,
+    key_ring: KeyRingType,
     token: &Secret<String>,
 ) -> Result<Claims> {
-    match banned_token_store
-        .read()
-        .await
-        .has_token(token.clone())
-        .await
-    {
+    match banned_token_store.has_token(token.clone()).await {
         Ok(true) => {
             return Err(eyre!("token is banned"));
         }
@@ -72,48 +254,223 @@ pub async fn validate_token(
         Err(e) => return Err(e.into()),
     }
 
-    decode::<Claims>(
-        token.expose_secret(),
-        &DecodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .wrap_err("failed to decode token")
+    let claims: Claims = decode_token(key_ring, token, None).await?;
+
+    // Tokens issued before e.g. a password reset are no longer accepted.
+    let user_id = UserId::parse(&claims.sub).wrap_err("invalid token subject")?;
+    let banned_before = banned_token_store
+        .get_tokens_banned_before(&user_id)
+        .await?;
+    if banned_before.is_some_and(|banned_before| claims.iat_ms <= banned_before) {
+        return Err(eyre!(
+            "token was issued before the user's tokens were banned"
+        ));
+    }
+
+    let session_id =
+        RefreshTokenFamilyId::parse(claims.sid.clone()).wrap_err("invalid token session id")?;
+    let session = session_store
+        .get_session(&session_id)
+        .await
+        .wrap_err("token session was not found")?;
+    if session.user_id != user_id {
+        return Err(eyre!("token session belongs to another user"));
+    }
+    let now = Utc::now().timestamp();
+    // The last-seen time is only informative, so failing to write it is not an error.
+    if now - session.last_seen_at >= SESSION_LAST_SEEN_INTERVAL_SECONDS
+        && let Err(e) = session_store.touch_session(&session_id, now).await
+    {
+        tracing::warn!("failed to update the session last-seen time: {:?}", e);
+    }
+
+    Ok(claims)
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
+// Decode a token with the key named by its `kid`
+async fn decode_token<T: DeserializeOwned>(
+    key_ring: KeyRingType,
+    token: &Secret<String>,
+    audience: Option<&str>,
+) -> Result<T> {
+    let header = decode_header(token.expose_secret()).wrap_err("failed to decode token header")?;
+    let kid = header.kid.ok_or_eyre("token has no key id")?;
+    let key_ring = key_ring.read().await;
+    let key = key_ring
+        .find(&kid)
+        .ok_or_eyre("token was not signed with a known key")?;
+
+    let mut validation = Validation::new(key.algorithm());
+    if let Some(audience) = audience {
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+    }
+
+    decode::<T>(token.expose_secret(), key.decoding_key(), &validation)
+        .map(|data| data.claims)
+        .wrap_err("failed to decode token")
+}
+
+// Read the JWT auth cookie from the jar and validate it
+#[tracing::instrument(name = "Validate auth cookie", skip_all)]
+pub async fn validate_auth_cookie(
+    jar: &CookieJar,
+    banned_token_store: BannedTokenStoreType,
+    session_store: SessionStoreType,
+    key_ring: KeyRingType,
+) -> Result<Claims, AuthAPIError> {
+    let cookie = jar.get(JWT_COOKIE_NAME).ok_or(AuthAPIError::MissingToken)?;
+    let token = Secret::new(cookie.value().to_owned());
+
+    validate_token(banned_token_store, session_store, key_ring, &token)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)
+}
+
+// Check the admin API key header against the configured key. Without one, the
+// API key is never accepted.
+#[tracing::instrument(name = "Validate admin API key", skip_all)]
+pub fn validate_admin_api_key(
+    headers: &HeaderMap,
+    admin_api_key: Option<&Secret<String>>,
+) -> Result<(), AuthAPIError> {
+    let provided = headers
+        .get(ADMIN_API_KEY_HEADER)
+        .ok_or(AuthAPIError::MissingToken)?;
+    let expected = admin_api_key.ok_or(AuthAPIError::InvalidToken)?;
+
+    // Comparing digests keeps the comparison time independent of where the keys differ.
+    let digest = |key: &[u8]| ring::digest::digest(&ring::digest::SHA256, key);
+    if digest(provided.as_bytes()).as_ref() != digest(expected.expose_secret().as_bytes()).as_ref()
+    {
+        return Err(AuthAPIError::InvalidToken);
+    }
+    Ok(())
+}
+
+// Create JWT auth token by encoding claims using the signing key
 #[tracing::instrument(name = "Create JWT token", skip_all)]
-fn create_token(claims: &Claims) -> Result<String> {
-    encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
-    )
-    .wrap_err("failed to create token")
+async fn create_token<T: Serialize>(key_ring: KeyRingType, claims: &T) -> Result<String> {
+    let key_ring = key_ring.read().await;
+    let key = key_ring.signing_key();
+    encode(&key.header(), &claims, key.encoding_key()).wrap_err("failed to create token")
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    // Id of the user. Tokens naming users by email are no longer accepted.
+    pub sub: String,
+    pub exp: usize,
+    // Tokens issued before this field existed count as issued at the epoch.
+    #[serde(default)]
+    pub iat: usize,
+    // `iat` in milliseconds, which is what bans are compared against.
+    #[serde(default)]
+    pub iat_ms: i64,
+    // Unique id of the token.
+    pub jti: String,
+    // Id of the session the token was issued for.
+    pub sid: String,
+    // Roles of the user when the token was issued. Changes show in the next token.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    // Permissions granted by those roles, separated by spaces.
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl Claims {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.scope
+            .split_whitespace()
+            .any(|granted| granted == permission)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetClaims {
     pub sub: String,
     pub exp: usize,
+    pub aud: String,
+    // Fingerprint of the password the token was issued for.
+    pub pwd: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerificationClaims {
+    pub sub: String,
+    pub exp: usize,
+    pub aud: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailChangeClaims {
+    pub sub: String,
+    pub exp: usize,
+    pub aud: String,
+    pub new_email: String,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::services::hashset_banned_token_store::HashsetBannedTokenStore;
+    use crate::domain::{ClientInfo, Permission, Role, Session};
+    use crate::services::{HashmapRefreshTokenStore, HashmapSessionStore, HashsetBannedTokenStore};
+    use crate::utils::{
+        key_ring::{KeyRing, KeySource},
+        signing_key::SigningKey,
+    };
     use secrecy::Secret;
     use std::sync::Arc;
     use tokio::sync::RwLock;
 
     fn get_empty_store() -> BannedTokenStoreType {
-        Arc::new(RwLock::new(HashsetBannedTokenStore::default()))
+        Arc::new(HashsetBannedTokenStore::default())
+    }
+
+    fn get_empty_session_store() -> SessionStoreType {
+        Arc::new(HashmapSessionStore::default())
+    }
+
+    // A session store holding a session of the user, and the id of that session.
+    async fn start_session(user_id: &UserId) -> (SessionStoreType, RefreshTokenFamilyId) {
+        let client = ClientInfo {
+            ip: "127.0.0.1".parse().unwrap(),
+            user_agent: None,
+        };
+        let session = Session::new(*user_id, client, Utc::now().timestamp());
+        let session_id = session.id.clone();
+        let store = get_empty_session_store();
+        store.add_session(session).await.unwrap();
+        (store, session_id)
+    }
+
+    fn get_key_ring() -> KeyRingType {
+        get_key_ring_with_ttl(600)
+    }
+
+    fn get_key_ring_with_ttl(token_ttl_seconds: i64) -> KeyRingType {
+        let source = KeySource {
+            secret: Some(Secret::new("test-secret".to_owned())),
+            ..KeySource::default()
+        };
+        Arc::new(RwLock::new(
+            KeyRing::load(source, token_ttl_seconds).unwrap(),
+        ))
+    }
+
+    fn hmac_key(kid: &str) -> SigningKey {
+        SigningKey::from_secret(&Secret::new(format!("secret-{}", kid)), kid.to_owned())
     }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let user_id = UserId::default();
+        let session_id = RefreshTokenFamilyId::default();
+        let cookie =
+            generate_auth_cookie(get_key_ring(), &user_id, &Grants::default(), &session_id)
+                .await
+                .unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -132,20 +489,84 @@ mod tests {
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
     }
 
+    #[tokio::test]
+    async fn test_generate_refresh_cookie() {
+        let store: RefreshTokenStoreType = Arc::new(HashmapRefreshTokenStore::default());
+        let user_id = UserId::default();
+        let family_id = RefreshTokenFamilyId::default();
+
+        let cookie = generate_refresh_cookie(store.clone(), &user_id, family_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(cookie.name(), REFRESH_TOKEN_COOKIE_NAME);
+        assert_eq!(cookie.path(), Some(REFRESH_TOKEN_COOKIE_PATH));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(
+            cookie.max_age(),
+            Some(time::Duration::seconds(REFRESH_TOKEN_TTL_SECONDS))
+        );
+
+        // The token is stored server-side, in the requested family.
+        let token = RefreshToken::parse(Secret::new(cookie.value().to_owned())).unwrap();
+        let entry = store.get_token(&token).await.unwrap();
+        assert_eq!(entry, RefreshTokenEntry::new(user_id, family_id));
+    }
+
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let key_ring = get_key_ring();
+        let session_id = RefreshTokenFamilyId::default();
+        let result = generate_auth_token(
+            key_ring.clone(),
+            &UserId::default(),
+            &Grants::default(),
+            &session_id,
+        )
+        .await
+        .unwrap();
         assert_eq!(result.split('.').count(), 3);
+
+        let header = decode_header(&result).unwrap();
+        let kid = key_ring.read().await.signing_key().kid().to_owned();
+        assert_eq!(header.kid, Some(kid));
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_lives_as_long_as_the_key_ring_says() {
+        let key_ring = get_key_ring_with_ttl(60);
+        let session_id = RefreshTokenFamilyId::default();
+        let token = generate_auth_token(
+            key_ring.clone(),
+            &UserId::default(),
+            &Grants::default(),
+            &session_id,
+        )
+        .await
+        .unwrap();
+
+        let claims: Claims = decode_token(key_ring, &Secret::new(token), None)
+            .await
+            .unwrap();
+        let expected = Utc::now().timestamp() + 60;
+        assert!((claims.exp as i64 - expected).abs() <= 1);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
         let empty_banned_store = get_empty_store();
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let token = Secret::new(generate_auth_token(&email).unwrap());
-        let result = validate_token(empty_banned_store, &token).await.unwrap();
-        assert_eq!(result.sub, "test@example.com");
+        let key_ring = get_key_ring();
+        let user_id = UserId::default();
+        let (session_store, session_id) = start_session(&user_id).await;
+        let token = Secret::new(
+            generate_auth_token(key_ring.clone(), &user_id, &Grants::default(), &session_id)
+                .await
+                .unwrap(),
+        );
+        let result = validate_token(empty_banned_store, session_store, key_ring, &token)
+            .await
+            .unwrap();
+        assert_eq!(result.sub, user_id.to_string());
+        assert_eq!(result.sid, session_id.as_ref());
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -153,13 +574,333 @@ mod tests {
             .timestamp();
 
         assert!(result.exp > exp as usize);
+        assert!(result.roles.is_empty());
+        assert!(!result.has_permission(""));
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_carries_grants() {
+        let key_ring = get_key_ring();
+        let user_id = UserId::default();
+        let (session_store, session_id) = start_session(&user_id).await;
+        let grants = Grants {
+            roles: vec![Role::parse("admin").unwrap()],
+            permissions: vec![
+                Permission::parse("users:read").unwrap(),
+                Permission::parse("users:write").unwrap(),
+            ],
+        };
+        let token = Secret::new(
+            generate_auth_token(key_ring.clone(), &user_id, &grants, &session_id)
+                .await
+                .unwrap(),
+        );
+
+        let claims = validate_token(get_empty_store(), session_store, key_ring, &token)
+            .await
+            .unwrap();
+        assert_eq!(claims.roles, vec!["admin"]);
+        assert_eq!(claims.scope, "users:read users:write");
+        assert!(claims.has_permission("users:write"));
+        assert!(!claims.has_permission("users"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_unknown_kid() {
+        let empty_banned_store = get_empty_store();
+        let key = hmac_key("unknown");
+        let claims = Claims {
+            sub: UserId::default().to_string(),
+            exp: 10_000_000_000,
+            iat: 0,
+            iat_ms: 0,
+            jti: Uuid::new_v4().to_string(),
+            sid: RefreshTokenFamilyId::default().as_ref().to_owned(),
+            roles: Vec::new(),
+            scope: String::new(),
+        };
+        let token = Secret::new(encode(&key.header(), &claims, key.encoding_key()).unwrap());
+        let result = validate_token(
+            empty_banned_store,
+            get_empty_session_store(),
+            get_key_ring(),
+            &token,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_signed_by_rotated_key() {
+        let empty_banned_store = get_empty_store();
+        let key_ring = get_key_ring();
+        let user_id = UserId::default();
+        let (session_store, session_id) = start_session(&user_id).await;
+        let old_token = Secret::new(
+            generate_auth_token(key_ring.clone(), &user_id, &Grants::default(), &session_id)
+                .await
+                .unwrap(),
+        );
+
+        key_ring
+            .write()
+            .await
+            .rotate(hmac_key("next"), Vec::new())
+            .unwrap();
+        let new_token = Secret::new(
+            generate_auth_token(key_ring.clone(), &user_id, &Grants::default(), &session_id)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(
+            decode_header(new_token.expose_secret())
+                .unwrap()
+                .kid
+                .as_deref(),
+            Some("next")
+        );
+
+        // Tokens signed before the rotation stay valid until they expire.
+        for token in [old_token, new_token] {
+            let result = validate_token(
+                empty_banned_store.clone(),
+                session_store.clone(),
+                key_ring.clone(),
+                &token,
+            )
+            .await;
+            assert_eq!(result.unwrap().sub, user_id.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_expired_token_signed_by_rotated_key() {
+        let empty_banned_store = get_empty_store();
+        let key_ring = get_key_ring();
+        let claims = Claims {
+            sub: UserId::default().to_string(),
+            exp: (Utc::now().timestamp() - 3600) as usize,
+            iat: (Utc::now().timestamp() - 7200) as usize,
+            iat_ms: (Utc::now().timestamp() - 7200) * 1000,
+            jti: Uuid::new_v4().to_string(),
+            sid: RefreshTokenFamilyId::default().as_ref().to_owned(),
+            roles: Vec::new(),
+            scope: String::new(),
+        };
+        let token = Secret::new(create_token(key_ring.clone(), &claims).await.unwrap());
+
+        key_ring
+            .write()
+            .await
+            .rotate(hmac_key("next"), Vec::new())
+            .unwrap();
+        let result = validate_token(
+            empty_banned_store,
+            get_empty_session_store(),
+            key_ring,
+            &token,
+        )
+        .await;
+        assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let empty_banned_store = get_empty_store();
         let token = Secret::new("invalid_token".to_owned());
-        let result = validate_token(empty_banned_store, &token).await;
+        let result = validate_token(
+            empty_banned_store,
+            get_empty_session_store(),
+            get_key_ring(),
+            &token,
+        )
+        .await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_validate_token_issued_before_ban() {
+        let banned_store = get_empty_store();
+        let key_ring = get_key_ring();
+        let user_id = UserId::default();
+        let (session_store, session_id) = start_session(&user_id).await;
+        let token = Secret::new(
+            generate_auth_token(key_ring.clone(), &user_id, &Grants::default(), &session_id)
+                .await
+                .unwrap(),
+        );
+
+        banned_store
+            .ban_tokens_issued_before(&user_id, Utc::now().timestamp_millis())
+            .await
+            .unwrap();
+        let result = validate_token(banned_store, session_store, key_ring, &token).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_issued_right_after_ban() {
+        let banned_store = get_empty_store();
+        let key_ring = get_key_ring();
+        let user_id = UserId::default();
+        let (session_store, session_id) = start_session(&user_id).await;
+
+        banned_store
+            .ban_tokens_issued_before(&user_id, Utc::now().timestamp_millis())
+            .await
+            .unwrap();
+        // Most likely within the same second as the ban.
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let token = Secret::new(
+            generate_auth_token(key_ring.clone(), &user_id, &Grants::default(), &session_id)
+                .await
+                .unwrap(),
+        );
+        let result = validate_token(banned_store, session_store, key_ring, &token).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_revoked_session() {
+        let key_ring = get_key_ring();
+        let user_id = UserId::default();
+        let (session_store, session_id) = start_session(&user_id).await;
+        let token = Secret::new(
+            generate_auth_token(key_ring.clone(), &user_id, &Grants::default(), &session_id)
+                .await
+                .unwrap(),
+        );
+
+        session_store.remove_session(&session_id).await.unwrap();
+        let result = validate_token(get_empty_store(), session_store, key_ring, &token).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_another_users_session() {
+        let key_ring = get_key_ring();
+        let (session_store, session_id) = start_session(&UserId::default()).await;
+        let token = Secret::new(
+            generate_auth_token(
+                key_ring.clone(),
+                &UserId::default(),
+                &Grants::default(),
+                &session_id,
+            )
+            .await
+            .unwrap(),
+        );
+
+        let result = validate_token(get_empty_store(), session_store, key_ring, &token).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_token_round_trip() {
+        let key_ring = get_key_ring();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+        let token = generate_password_reset_token(key_ring.clone(), &email, &password)
+            .await
+            .unwrap();
+
+        let claims = validate_password_reset_token(key_ring, &token)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "test@example.com");
+        assert_eq!(claims.pwd, password_fingerprint(&password));
+
+        let other = Password::parse(Secret::new("password456".to_owned())).unwrap();
+        assert_ne!(claims.pwd, password_fingerprint(&other));
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_and_auth_tokens_are_not_interchangeable() {
+        let key_ring = get_key_ring();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+
+        let reset_token = generate_password_reset_token(key_ring.clone(), &email, &password)
+            .await
+            .unwrap();
+        let result = validate_token(
+            get_empty_store(),
+            get_empty_session_store(),
+            key_ring.clone(),
+            &reset_token,
+        )
+        .await;
+        assert!(result.is_err());
+
+        let session_id = RefreshTokenFamilyId::default();
+        let auth_token = Secret::new(
+            generate_auth_token(
+                key_ring.clone(),
+                &UserId::default(),
+                &Grants::default(),
+                &session_id,
+            )
+            .await
+            .unwrap(),
+        );
+        let result = validate_password_reset_token(key_ring, &auth_token).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_email_verification_token_round_trip() {
+        let key_ring = get_key_ring();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let token = generate_email_verification_token(key_ring.clone(), &email)
+            .await
+            .unwrap();
+
+        let claims = validate_email_verification_token(key_ring.clone(), &token)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "test@example.com");
+
+        // Neither an auth token nor a password reset token verifies an email.
+        let password = Password::parse(Secret::new("password123".to_owned())).unwrap();
+        let session_id = RefreshTokenFamilyId::default();
+        let auth_token = Secret::new(
+            generate_auth_token(
+                key_ring.clone(),
+                &UserId::default(),
+                &Grants::default(),
+                &session_id,
+            )
+            .await
+            .unwrap(),
+        );
+        let reset_token = generate_password_reset_token(key_ring.clone(), &email, &password)
+            .await
+            .unwrap();
+        for token in [auth_token, reset_token] {
+            let result = validate_email_verification_token(key_ring.clone(), &token).await;
+            assert!(result.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_email_change_token_round_trip() {
+        let key_ring = get_key_ring();
+        let email = Email::parse(Secret::new("old@example.com".to_owned())).unwrap();
+        let new_email = Email::parse(Secret::new("new@example.com".to_owned())).unwrap();
+        let token = generate_email_change_token(key_ring.clone(), &email, &new_email)
+            .await
+            .unwrap();
+
+        let claims = validate_email_change_token(key_ring.clone(), &token)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "old@example.com");
+        assert_eq!(claims.new_email, "new@example.com");
+
+        // A verification link does not change an email.
+        let token = generate_email_verification_token(key_ring.clone(), &new_email)
+            .await
+            .unwrap();
+        assert!(validate_email_change_token(key_ring, &token).await.is_err());
+    }
 }
@@ -0,0 +1,242 @@
+use std::marker::PhantomData;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    AppState,
+    domain::{
+        AUDIT_READ_PERMISSION, AuditActor, AuthAPIError, USERS_READ_PERMISSION,
+        USERS_WRITE_PERMISSION, UserId,
+    },
+    utils::{
+        auth::{Claims, validate_admin_api_key, validate_auth_cookie},
+        constants::ADMIN_API_KEY_HEADER,
+    },
+};
+
+// A permission a handler requires, named by a type so that extractors can carry it.
+pub trait RequiredPermission {
+    const PERMISSION: &'static str;
+}
+
+// Read any user's account.
+pub struct UsersRead;
+
+impl RequiredPermission for UsersRead {
+    const PERMISSION: &'static str = USERS_READ_PERMISSION;
+}
+
+// Change or delete any user's account.
+pub struct UsersWrite;
+
+impl RequiredPermission for UsersWrite {
+    const PERMISSION: &'static str = USERS_WRITE_PERMISSION;
+}
+
+// Read the audit log.
+pub struct AuditRead;
+
+impl RequiredPermission for AuditRead {
+    const PERMISSION: &'static str = AUDIT_READ_PERMISSION;
+}
+
+// Who made a request that needs a permission.
+pub enum Actor {
+    // A logged in user, whose token grants the permission.
+    User(Claims),
+    // An operator holding the admin API key, which grants every permission.
+    AdminApiKey,
+}
+
+impl Actor {
+    // Who the audit log says made the request.
+    pub fn audit_actor(&self) -> AuditActor {
+        match self {
+            Self::User(claims) => {
+                UserId::parse(&claims.sub).map_or(AuditActor::Anonymous, AuditActor::User)
+            }
+            Self::AdminApiKey => AuditActor::AdminApiKey,
+        }
+    }
+}
+
+// The actor of a request granted the permission `P`, by their token or the admin API key.
+// Without a valid token or key, the request is rejected as by other routes, and with a
+// token lacking `P`, with a 403.
+pub struct Authorized<P> {
+    pub actor: Actor,
+    permission: PhantomData<fn() -> P>,
+}
+
+#[async_trait]
+impl<P: RequiredPermission> FromRequestParts<AppState> for Authorized<P> {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if parts.headers.contains_key(ADMIN_API_KEY_HEADER) {
+            validate_admin_api_key(&parts.headers, state.settings.admin.api_key.as_ref())?;
+            return Ok(Self {
+                actor: Actor::AdminApiKey,
+                permission: PhantomData,
+            });
+        }
+
+        let jar = CookieJar::from_headers(&parts.headers);
+        let claims = validate_auth_cookie(
+            &jar,
+            state.banned_tokens_store.clone(),
+            state.session_store.clone(),
+            state.key_ring.clone(),
+        )
+        .await?;
+        if !claims.has_permission(P::PERMISSION) {
+            return Err(AuthAPIError::MissingPermission);
+        }
+
+        Ok(Self {
+            actor: Actor::User(claims),
+            permission: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{ClientInfo, Grants, Permission, Session, UserId};
+    use crate::services::{
+        HashmapRateLimitStore, HashmapRefreshTokenStore, HashmapSessionStore,
+        HashmapTwoFACodeStore, HashmapUserStore, HashsetBannedTokenStore, VecAuditSink,
+        mock_email_client::MockEmailClient,
+    };
+    use crate::utils::{auth::generate_auth_cookie, key_ring::KeyRing, settings::Settings};
+    use axum::http::{Request, header::COOKIE};
+    use secrecy::Secret;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const ADMIN_API_KEY: &str = "admin-api-key";
+
+    fn get_app_state() -> AppState {
+        let mut settings = Settings::default();
+        settings.jwt.secret = Secret::new("test-secret".to_owned());
+        settings.admin.api_key = Some(Secret::new(ADMIN_API_KEY.to_owned()));
+        let key_ring = KeyRing::load(settings.jwt.key_source(), settings.jwt.token_ttl_seconds);
+
+        AppState::new(
+            Arc::new(HashmapUserStore::default()),
+            Arc::new(HashsetBannedTokenStore::default()),
+            Arc::new(HashmapTwoFACodeStore::default()),
+            Arc::new(HashmapRefreshTokenStore::default()),
+            Arc::new(HashmapSessionStore::default()),
+            Arc::new(HashmapRateLimitStore::default()),
+            Arc::new(VecAuditSink::default()),
+            Arc::new(RwLock::new(key_ring.unwrap())),
+            Arc::new(MockEmailClient),
+            Arc::new(settings),
+        )
+    }
+
+    // Request parts carrying the auth cookie of a new session with these grants.
+    async fn get_parts(state: &AppState, grants: &Grants) -> Parts {
+        let client = ClientInfo {
+            ip: "127.0.0.1".parse().unwrap(),
+            user_agent: None,
+        };
+        let user_id = UserId::default();
+        let session = Session::new(user_id, client, chrono::Utc::now().timestamp());
+        let session_id = session.id.clone();
+        state.session_store.add_session(session).await.unwrap();
+        let cookie = generate_auth_cookie(state.key_ring.clone(), &user_id, grants, &session_id)
+            .await
+            .unwrap();
+
+        let request = Request::builder()
+            .header(COOKIE, cookie.encoded().to_string())
+            .body(())
+            .unwrap();
+        request.into_parts().0
+    }
+
+    #[tokio::test]
+    async fn test_authorized_with_permission() {
+        let state = get_app_state();
+        let grants = Grants {
+            roles: Vec::new(),
+            permissions: vec![Permission::parse(USERS_READ_PERMISSION).unwrap()],
+        };
+        let mut parts = get_parts(&state, &grants).await;
+
+        let result = Authorized::<UsersRead>::from_request_parts(&mut parts, &state).await;
+        assert!(matches!(
+            result,
+            Ok(Authorized {
+                actor: Actor::User(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            result.unwrap().actor.audit_actor(),
+            AuditActor::User(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_authorized_without_permission() {
+        let state = get_app_state();
+        let grants = Grants {
+            roles: Vec::new(),
+            permissions: vec![Permission::parse(USERS_READ_PERMISSION).unwrap()],
+        };
+        let mut parts = get_parts(&state, &grants).await;
+
+        let result = Authorized::<UsersWrite>::from_request_parts(&mut parts, &state).await;
+        assert!(matches!(result, Err(AuthAPIError::MissingPermission)));
+    }
+
+    #[tokio::test]
+    async fn test_authorized_without_token() {
+        let state = get_app_state();
+        let mut parts = Request::builder().body(()).unwrap().into_parts().0;
+
+        let result = Authorized::<UsersRead>::from_request_parts(&mut parts, &state).await;
+        assert!(matches!(result, Err(AuthAPIError::MissingToken)));
+    }
+
+    #[tokio::test]
+    async fn test_authorized_with_wrong_admin_api_key() {
+        let state = get_app_state();
+        let request = Request::builder()
+            .header(ADMIN_API_KEY_HEADER, "wrong-key")
+            .body(())
+            .unwrap();
+        let mut parts = request.into_parts().0;
+
+        let result = Authorized::<UsersWrite>::from_request_parts(&mut parts, &state).await;
+        assert!(matches!(result, Err(AuthAPIError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_authorized_with_admin_api_key() {
+        let state = get_app_state();
+        let request = Request::builder()
+            .header(ADMIN_API_KEY_HEADER, ADMIN_API_KEY)
+            .body(())
+            .unwrap();
+        let mut parts = request.into_parts().0;
+
+        let result = Authorized::<UsersWrite>::from_request_parts(&mut parts, &state).await;
+        assert!(matches!(
+            result,
+            Ok(Authorized {
+                actor: Actor::AdminApiKey,
+                ..
+            })
+        ));
+        assert_eq!(result.unwrap().actor.audit_actor(), AuditActor::AdminApiKey);
+    }
+}
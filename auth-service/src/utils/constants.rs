@@ -1,71 +1,65 @@
-use dotenvy::dotenv;
-use lazy_static::lazy_static;
-use secrecy::Secret;
-use std::env as std_env;
+use crate::domain::LocalPartPolicy;
 
-lazy_static! {
-    pub static ref JWT_SECRET: Secret<String> = set_token();
-    pub static ref DATABASE_URL: Secret<String> = set_db_url();
-    pub static ref REDIS_HOST_NAME: String = set_redis_host();
-    pub static ref POSTMARK_AUTH_TOKEN: Secret<String> = set_postmark_auth_token();
-}
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const REFRESH_TOKEN_COOKIE_NAME: &str = "refresh_token";
+// Only the refresh route reads the refresh token, so no other request carries it.
+pub const REFRESH_TOKEN_COOKIE_PATH: &str = "/refresh";
+pub const ADMIN_API_KEY_HEADER: &str = "x-admin-api-key";
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// `kid` of the shared JWT_SECRET, when no signing key file is configured.
+pub const DEFAULT_HMAC_KEY_ID: &str = "hmac";
 pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
-
-fn set_token() -> Secret<String> {
-    dotenv().ok();
-    let secret = std_env::var(env::JWT_SECRET_ENV_VAR).expect("JWT_SECRET must be set.");
-    if secret.is_empty() {
-        panic!("JWT_SECRET must not be empty.");
-    }
-    Secret::new(secret)
-}
-
-fn set_db_url() -> Secret<String> {
-    dotenv().ok();
-    let secret = std_env::var(env::DATABASE_URL_ENV_VAR).expect("DATABASE_URL must be set.");
-    if secret.is_empty() {
-        panic!("DATABASE_URL must not be empty.");
-    }
-    Secret::new(secret)
-}
-
-fn set_redis_host() -> String {
-    dotenv().ok();
-    std_env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME.to_owned())
-}
-
-fn set_postmark_auth_token() -> Secret<String> {
-    dotenv().ok();
-    Secret::new(
-        std_env::var(env::POSTMARK_AUTH_TOKEN_ENV_VAR).expect("POSTMARK_AUTH_TOKEN must be set."),
-    )
-}
+pub const TOTP_ISSUER: &str = "Live Bootcamp";
+// Page of the UI that asks for a new password, and posts it to `/password-reset/confirm`.
+pub const DEFAULT_PASSWORD_RESET_URL: &str = "http://localhost:3000/password-reset";
+pub const DEFAULT_EMAIL_VERIFICATION_URL: &str = "http://localhost:3000/verify-email";
+pub const DEFAULT_EMAIL_CHANGE_URL: &str = "http://localhost:3000/account/email/confirm";
+pub const DEFAULT_EMAIL_LOCAL_PART_POLICY: LocalPartPolicy = LocalPartPolicy::Lowercase;
+// Number of 30s time steps accepted before and after the current one.
+pub const DEFAULT_TOTP_SKEW: u8 = 1;
 
 pub mod env {
+    // TOML file read before the other variables, also given with `--config`.
+    pub const CONFIG_FILE_ENV_VAR: &str = "CONFIG_FILE";
+    pub const APP_ADDRESS_ENV_VAR: &str = "APP_ADDRESS";
+    // Comma-separated.
+    pub const ALLOWED_ORIGINS_ENV_VAR: &str = "ALLOWED_ORIGINS";
+    pub const PRE_STOP_DELAY_MILLISECONDS_ENV_VAR: &str = "PRE_STOP_DELAY_MILLISECONDS";
+    pub const DRAIN_TIMEOUT_MILLISECONDS_ENV_VAR: &str = "DRAIN_TIMEOUT_MILLISECONDS";
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_SIGNING_KEY_FILE_ENV_VAR: &str = "JWT_SIGNING_KEY_FILE";
+    pub const JWT_KEY_ID_ENV_VAR: &str = "JWT_KEY_ID";
+    pub const JWT_VERIFICATION_KEY_FILES_ENV_VAR: &str = "JWT_VERIFICATION_KEY_FILES";
+    pub const TOKEN_TTL_SECONDS_ENV_VAR: &str = "TOKEN_TTL_SECONDS";
+    pub const ARGON2_MEMORY_KIB_ENV_VAR: &str = "ARGON2_MEMORY_KIB";
+    pub const ARGON2_ITERATIONS_ENV_VAR: &str = "ARGON2_ITERATIONS";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const ADMIN_API_KEY_ENV_VAR: &str = "ADMIN_API_KEY";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
     pub const POSTMARK_AUTH_TOKEN_ENV_VAR: &str = "POSTMARK_AUTH_TOKEN";
-}
-
-pub mod prod {
-    pub const APP_ADDRESS: &str = "0.0.0.0:3000";
-    pub mod email_client {
-        use std::time::Duration;
-
-        pub const BASE_URL: &str = "https://api.postmarkapp.com/email";
-        pub const SENDER: &str = "oz+postmark@cyprio.net";
-        pub const TIMEOUT: Duration = std::time::Duration::from_secs(10);
-    }
-}
-
-pub mod test {
-    pub const APP_ADDRESS: &str = "127.0.0.1:0";
-    pub mod email_client {
-        use std::time::Duration;
-
-        pub const SENDER: &str = "test@email.com";
-        pub const TIMEOUT: Duration = std::time::Duration::from_millis(200);
-    }
+    // `postmark` (the default) or `smtp`.
+    pub const EMAIL_PROVIDER_ENV_VAR: &str = "EMAIL_PROVIDER";
+    pub const EMAIL_BASE_URL_ENV_VAR: &str = "EMAIL_BASE_URL";
+    pub const EMAIL_SENDER_ENV_VAR: &str = "EMAIL_SENDER";
+    pub const EMAIL_TIMEOUT_MILLISECONDS_ENV_VAR: &str = "EMAIL_TIMEOUT_MILLISECONDS";
+    pub const SMTP_HOST_ENV_VAR: &str = "SMTP_HOST";
+    pub const SMTP_PORT_ENV_VAR: &str = "SMTP_PORT";
+    // `starttls` (the default), `tls` for implicit TLS, or `none`.
+    pub const SMTP_TLS_ENV_VAR: &str = "SMTP_TLS";
+    pub const SMTP_USERNAME_ENV_VAR: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD_ENV_VAR: &str = "SMTP_PASSWORD";
+    pub const SMTP_MAX_CONNECTIONS_ENV_VAR: &str = "SMTP_MAX_CONNECTIONS";
+    pub const TOTP_SKEW_ENV_VAR: &str = "TOTP_SKEW";
+    pub const PASSWORD_RESET_URL_ENV_VAR: &str = "PASSWORD_RESET_URL";
+    pub const EMAIL_VERIFICATION_URL_ENV_VAR: &str = "EMAIL_VERIFICATION_URL";
+    pub const EMAIL_CHANGE_URL_ENV_VAR: &str = "EMAIL_CHANGE_URL";
+    pub const EMAIL_LOCAL_PART_POLICY_ENV_VAR: &str = "EMAIL_LOCAL_PART_POLICY";
+    pub const AUDIT_LOG_FILE_ENV_VAR: &str = "AUDIT_LOG_FILE";
+    // `true` to include the email provider in readiness checks.
+    pub const HEALTH_CHECK_EMAIL_ENV_VAR: &str = "HEALTH_CHECK_EMAIL";
+    // OpenTelemetry collector spans are exported to, e.g. `http://localhost:4318`.
+    pub const OTLP_ENDPOINT_ENV_VAR: &str = "OTLP_ENDPOINT";
+    // `compact` (the default) or `json`.
+    pub const LOG_FORMAT_ENV_VAR: &str = "LOG_FORMAT";
 }
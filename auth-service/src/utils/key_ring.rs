@@ -0,0 +1,394 @@
+use chrono::Utc;
+use color_eyre::eyre::{Context, Result, eyre};
+use jsonwebtoken::{Validation, jwk::JwkSet};
+use secrecy::Secret;
+use std::path::{Path, PathBuf};
+use tokio::signal::unix::{SignalKind, signal};
+
+use crate::app_state::KeyRingType;
+
+use super::{
+    auth::{EMAIL_VERIFICATION_TOKEN_TTL_SECONDS, PASSWORD_RESET_TOKEN_TTL_SECONDS},
+    constants::DEFAULT_HMAC_KEY_ID,
+    signing_key::{SigningKey, VerificationKey},
+};
+
+// Where the key ring is (re)loaded from.
+#[derive(Debug, Clone, Default)]
+pub struct KeySource {
+    // Shared secret new tokens are signed with when no signing key file is set.
+    pub secret: Option<Secret<String>>,
+    // Private key new tokens are signed with.
+    pub signing_key_file: Option<PathBuf>,
+    pub key_id: Option<String>,
+    // Keys only used to verify tokens, e.g. issued before a rotation: public or private
+    // keys in PEM files, or JWKs in JSON files, which keep their `kid`.
+    pub verification_key_files: Vec<PathBuf>,
+}
+
+impl KeySource {
+    fn read(&self) -> Result<(SigningKey, Vec<VerificationKey>)> {
+        let signing_key = match &self.signing_key_file {
+            Some(path) => read_key(path, self.key_id.clone())?,
+            None => SigningKey::from_secret(
+                self.secret
+                    .as_ref()
+                    .ok_or_else(|| eyre!("no JWT secret or signing key file is set"))?,
+                self.key_id
+                    .clone()
+                    .unwrap_or(DEFAULT_HMAC_KEY_ID.to_owned()),
+            ),
+        };
+        let verification_keys = self
+            .verification_key_files
+            .iter()
+            .map(|path| read_verification_key(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((signing_key, verification_keys))
+    }
+}
+
+fn read_key(path: &Path, kid: Option<String>) -> Result<SigningKey> {
+    let pem = std::fs::read(path)
+        .wrap_err_with(|| format!("failed to read JWT key {}", path.display()))?;
+    SigningKey::from_pem(&pem, kid)
+        .wrap_err_with(|| format!("failed to load JWT key {}", path.display()))
+}
+
+fn read_verification_key(path: &Path) -> Result<VerificationKey> {
+    let contents = std::fs::read(path)
+        .wrap_err_with(|| format!("failed to read JWT key {}", path.display()))?;
+    let key = match contents.trim_ascii_start().starts_with(b"{") {
+        true => VerificationKey::from_jwk(&contents),
+        false => VerificationKey::from_pem(&contents, None),
+    };
+    key.wrap_err_with(|| format!("failed to load JWT key {}", path.display()))
+}
+
+// One key signs new tokens; older keys keep verifying the tokens they signed.
+// Each token names its key with the `kid` header.
+pub struct KeyRing {
+    source: KeySource,
+    signing_key: SigningKey,
+    // Keys listed in the key source, kept until they are removed from it.
+    verification_keys: Vec<VerificationKey>,
+    // Keys that signed tokens before a rotation, with the time they were retired.
+    // They are only kept in memory: to verify their tokens after a restart, list them
+    // in the key source too.
+    retired_keys: Vec<(VerificationKey, i64)>,
+    // How long the auth tokens signed by the ring are valid for.
+    token_ttl_seconds: i64,
+}
+
+impl KeyRing {
+    pub fn load(source: KeySource, token_ttl_seconds: i64) -> Result<Self> {
+        let (signing_key, verification_keys) = source.read()?;
+        Ok(Self {
+            source,
+            signing_key,
+            verification_keys,
+            retired_keys: Vec::new(),
+            token_ttl_seconds,
+        })
+    }
+
+    // Read the key source again. On error the ring is left untouched.
+    pub fn reload(&mut self) -> Result<()> {
+        let (signing_key, verification_keys) = self.source.read()?;
+        self.rotate(signing_key, verification_keys)
+    }
+
+    // Start signing with `signing_key`. The previous signing key keeps verifying
+    // tokens until the last one it signed has expired.
+    pub fn rotate(
+        &mut self,
+        signing_key: SigningKey,
+        verification_keys: Vec<VerificationKey>,
+    ) -> Result<()> {
+        // Tokens signed by the current key would no longer verify.
+        if signing_key.kid() == self.signing_key.kid()
+            && signing_key.jwk() != self.signing_key.jwk()
+        {
+            return Err(eyre!(
+                "signing key changed but kept its key id {}",
+                signing_key.kid()
+            ));
+        }
+
+        let now = Utc::now().timestamp();
+        if signing_key.kid() != self.signing_key.kid() {
+            let previous = std::mem::replace(&mut self.signing_key, signing_key);
+            self.retired_keys
+                .push((previous.verification_key().clone(), now));
+        }
+        self.verification_keys = verification_keys;
+
+        // Keys listed in the source verify on their own, even after a restart.
+        let listed = |kid: &str| {
+            kid == self.signing_key.kid()
+                || self.verification_keys.iter().any(|key| key.kid() == kid)
+        };
+        let lifetime = self.longest_token_ttl_seconds();
+        self.retired_keys.retain(|(key, retired_at)| {
+            !listed(key.kid()) && !is_expired(*retired_at, now, lifetime)
+        });
+        Ok(())
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    pub fn token_ttl_seconds(&self) -> i64 {
+        self.token_ttl_seconds
+    }
+
+    // The ring also signs the tokens of email links, which can outlive auth tokens.
+    fn longest_token_ttl_seconds(&self) -> i64 {
+        self.token_ttl_seconds
+            .max(EMAIL_VERIFICATION_TOKEN_TTL_SECONDS)
+            .max(PASSWORD_RESET_TOKEN_TTL_SECONDS)
+    }
+
+    // Key a token with the given `kid` must have been signed with.
+    pub fn find(&self, kid: &str) -> Option<&VerificationKey> {
+        let now = Utc::now().timestamp();
+        self.verification_only_keys(now)
+            .chain(std::iter::once(self.signing_key.verification_key()))
+            .find(|key| key.kid() == kid)
+    }
+
+    // Identifiers of the keys that still verify tokens, but no longer sign them.
+    pub fn verification_key_ids(&self) -> Vec<String> {
+        let now = Utc::now().timestamp();
+        self.verification_only_keys(now)
+            .map(|key| key.kid().to_owned())
+            .collect()
+    }
+
+    // Public halves of every asymmetric key that can verify a live token.
+    pub fn jwks(&self) -> JwkSet {
+        let now = Utc::now().timestamp();
+        let keys = std::iter::once(self.signing_key.verification_key())
+            .chain(self.verification_only_keys(now))
+            .filter_map(|key| key.jwk().cloned())
+            .collect();
+        JwkSet { keys }
+    }
+
+    fn verification_only_keys(&self, now: i64) -> impl Iterator<Item = &VerificationKey> {
+        let lifetime = self.longest_token_ttl_seconds();
+        let retired = self
+            .retired_keys
+            .iter()
+            .filter(move |(_, retired_at)| !is_expired(*retired_at, now, lifetime))
+            .map(|(key, _)| key);
+        self.verification_keys
+            .iter()
+            .chain(retired)
+            .filter(|key| key.kid() != self.signing_key.kid())
+    }
+}
+
+// Reload the key ring from its files whenever the process receives SIGHUP,
+// so that keys can be rotated without a restart.
+#[tracing::instrument(name = "Reload key ring on SIGHUP", skip_all)]
+pub async fn reload_on_sighup(key_ring: KeyRingType) -> std::io::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        match key_ring.write().await.reload() {
+            Ok(()) => tracing::info!("reloaded JWT signing keys"),
+            Err(e) => tracing::error!("failed to reload JWT signing keys: {:?}", e),
+        }
+    }
+    Ok(())
+}
+
+// A retired key is dropped once every token it signed has expired, allowing
+// for the clock skew tolerated when validating `exp`.
+fn is_expired(retired_at: i64, now: i64, lifetime_seconds: i64) -> bool {
+    let leeway = Validation::default().leeway as i64;
+    now > retired_at + lifetime_seconds + leeway
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::email::Email,
+        utils::auth::{generate_email_verification_token, validate_email_verification_token},
+    };
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const TOKEN_TTL_SECONDS: i64 = 600;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn hmac_key(kid: &str) -> SigningKey {
+        SigningKey::from_secret(&Secret::new(format!("secret-{}", kid)), kid.to_owned())
+    }
+
+    fn ring_with(signing_key: SigningKey) -> KeyRing {
+        KeyRing {
+            source: KeySource::default(),
+            signing_key,
+            verification_keys: Vec::new(),
+            retired_keys: Vec::new(),
+            token_ttl_seconds: TOKEN_TTL_SECONDS,
+        }
+    }
+
+    #[test]
+    fn loads_signing_and_verification_keys_from_files() {
+        let source = KeySource {
+            secret: None,
+            signing_key_file: Some(fixture("jwt_ed25519_private.pem")),
+            key_id: Some("current".to_owned()),
+            verification_key_files: vec![fixture("jwt_rsa_private.pem")],
+        };
+        let ring = KeyRing::load(source, TOKEN_TTL_SECONDS).unwrap();
+
+        assert_eq!(ring.signing_key().kid(), "current");
+        let ids = ring.verification_key_ids();
+        assert_eq!(ids.len(), 1);
+        assert!(ring.find(&ids[0]).is_some());
+        assert_eq!(ring.jwks().keys.len(), 2);
+    }
+
+    // After a restart, only the key source remembers the keys that signed before.
+    #[test]
+    fn loads_public_verification_keys_from_files() {
+        let source = KeySource {
+            secret: None,
+            signing_key_file: Some(fixture("jwt_ed25519_private.pem")),
+            key_id: None,
+            verification_key_files: vec![
+                fixture("jwt_rsa_public.pem"),
+                fixture("jwt_rsa_public.jwk.json"),
+            ],
+        };
+        let ring = KeyRing::load(source, TOKEN_TTL_SECONDS).unwrap();
+
+        let rsa = SigningKey::from_pem(
+            &std::fs::read(fixture("jwt_rsa_private.pem")).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            ring.verification_key_ids(),
+            vec![rsa.kid().to_owned(), "2026-09".to_owned()]
+        );
+        assert_eq!(ring.find(rsa.kid()).unwrap().jwk(), rsa.jwk());
+        assert_eq!(ring.jwks().keys.len(), 3);
+    }
+
+    #[test]
+    fn missing_key_file_is_an_error() {
+        let source = KeySource {
+            signing_key_file: Some(fixture("missing.pem")),
+            ..KeySource::default()
+        };
+        assert!(KeyRing::load(source, TOKEN_TTL_SECONDS).is_err());
+    }
+
+    #[test]
+    fn missing_secret_is_an_error() {
+        assert!(KeyRing::load(KeySource::default(), TOKEN_TTL_SECONDS).is_err());
+    }
+
+    #[test]
+    fn rotated_key_still_verifies_but_no_longer_signs() {
+        let mut ring = ring_with(hmac_key("old"));
+        ring.rotate(hmac_key("new"), Vec::new()).unwrap();
+
+        assert_eq!(ring.signing_key().kid(), "new");
+        assert!(ring.find("old").is_some());
+        assert!(ring.find("new").is_some());
+        assert!(ring.find("unknown").is_none());
+        assert_eq!(ring.verification_key_ids(), vec!["old".to_owned()]);
+    }
+
+    #[test]
+    fn retired_key_listed_in_the_source_is_not_kept_twice() {
+        let old = hmac_key("old");
+        let mut ring = ring_with(old.clone());
+        ring.rotate(hmac_key("new"), vec![old.verification_key().clone()])
+            .unwrap();
+
+        assert!(ring.retired_keys.is_empty());
+        assert_eq!(ring.verification_key_ids(), vec!["old".to_owned()]);
+    }
+
+    #[test]
+    fn rotating_to_the_same_key_keeps_the_ring_unchanged() {
+        let mut ring = ring_with(hmac_key("current"));
+        ring.rotate(hmac_key("current"), Vec::new()).unwrap();
+
+        assert_eq!(ring.signing_key().kid(), "current");
+        assert!(ring.verification_key_ids().is_empty());
+    }
+
+    #[test]
+    fn changing_a_key_without_changing_its_kid_is_rejected() {
+        let read = |name: &str| std::fs::read(fixture(name)).unwrap();
+        let rsa = SigningKey::from_pem(&read("jwt_rsa_private.pem"), Some("key".to_owned()));
+        let ed25519 =
+            SigningKey::from_pem(&read("jwt_ed25519_private.pem"), Some("key".to_owned()));
+
+        let mut ring = ring_with(rsa.unwrap());
+        assert!(ring.rotate(ed25519.unwrap(), Vec::new()).is_err());
+        assert_eq!(
+            ring.signing_key().algorithm(),
+            jsonwebtoken::Algorithm::RS256
+        );
+    }
+
+    #[test]
+    fn retired_key_is_dropped_after_its_tokens_expired() {
+        let mut ring = ring_with(hmac_key("current"));
+        let retired_at = Utc::now().timestamp() - EMAIL_VERIFICATION_TOKEN_TTL_SECONDS - 3600;
+        ring.retired_keys
+            .push((hmac_key("expired").verification_key().clone(), retired_at));
+
+        assert!(ring.find("expired").is_none());
+        ring.rotate(hmac_key("current"), Vec::new()).unwrap();
+        assert!(ring.retired_keys.is_empty());
+    }
+
+    // Email links stay valid far longer than the auth tokens signed with the same key.
+    #[tokio::test]
+    async fn retired_key_verifies_email_links_after_the_auth_tokens_expired() {
+        let key_ring = Arc::new(RwLock::new(ring_with(hmac_key("old"))));
+        let email = Email::parse(Secret::new("user@example.com".to_owned())).unwrap();
+        let token = generate_email_verification_token(key_ring.clone(), &email)
+            .await
+            .unwrap();
+
+        let mut ring = key_ring.write().await;
+        ring.rotate(hmac_key("new"), Vec::new()).unwrap();
+        let leeway = Validation::default().leeway as i64;
+        ring.retired_keys[0].1 -= TOKEN_TTL_SECONDS + leeway + 1;
+        drop(ring);
+
+        let claims = validate_email_verification_token(key_ring, &token)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "user@example.com");
+    }
+
+    #[test]
+    fn rolling_back_to_a_retired_key_signs_with_it_again() {
+        let mut ring = ring_with(hmac_key("first"));
+        ring.rotate(hmac_key("second"), Vec::new()).unwrap();
+        ring.rotate(hmac_key("first"), Vec::new()).unwrap();
+
+        assert_eq!(ring.signing_key().kid(), "first");
+        assert_eq!(ring.verification_key_ids(), vec!["second".to_owned()]);
+    }
+}
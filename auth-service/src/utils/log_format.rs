@@ -0,0 +1,370 @@
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    error::Error,
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+    span,
+};
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter, format::Writer},
+    registry::LookupSpan,
+};
+
+// Fields with these names, or names ending with them, are never logged. Secrets are
+// redacted by their type already: this catches a plain string logged by mistake.
+const REDACTED_FIELDS: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "email",
+    "2fa_code",
+    "login_attempt_id",
+];
+const REDACTED: &str = "[REDACTED]";
+
+fn is_redacted(name: &str) -> bool {
+    REDACTED_FIELDS
+        .iter()
+        .any(|redacted| name.ends_with(redacted))
+}
+
+// How events are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // One short line per event, for people.
+    Compact,
+    // One JSON object per line, for log shippers.
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "compact" => Ok(Self::Compact),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("`{}` is not compact or json", s)),
+        }
+    }
+}
+
+// Format of JSON logs: an event per line, with its fields, and the fields of the
+// spans it happened in, outermost first.
+pub struct JsonFormat;
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    fields: Map<String, Value>,
+    spans: Vec<Value>,
+}
+
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+        let spans = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let mut fields: Map<String, Value> = span
+                    .extensions()
+                    .get::<FormattedFields<JsonFields>>()
+                    .and_then(|formatted| serde_json::from_str(&formatted.fields).ok())
+                    .unwrap_or_default();
+                fields.insert("name".to_owned(), Value::from(span.name()));
+                Value::Object(fields)
+            })
+            .collect();
+        let metadata = event.metadata();
+        let line = LogLine {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            level: metadata.level().as_str(),
+            target: metadata.target(),
+            fields: fields.0,
+            spans,
+        };
+
+        let line = serde_json::to_string(&line).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{}", line)
+    }
+}
+
+// Fields of spans, kept as JSON objects for `JsonFormat`.
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    // Merge fields recorded once the span exists into the object.
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(serde_json::from_str(&current.fields).unwrap_or_default());
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl JsonVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let name = field.name();
+        let value = match is_redacted(name) {
+            true => Value::from(REDACTED),
+            false => value,
+        };
+        self.0.insert(name.to_owned(), value);
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    // An error is logged with its sources, outermost first.
+    fn record_error(&mut self, field: &Field, value: &(dyn Error + 'static)) {
+        let chain = std::iter::successors(Some(value), |&e| e.source())
+            .map(|e| Value::from(e.to_string()))
+            .collect();
+        self.insert(field, Value::Array(chain));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::from(format!("{:?}", value)));
+    }
+}
+
+// Fields of events and spans for the compact format: `name=value`, separated by
+// spaces, with the message first and the same fields redacted as in JSON logs.
+pub struct CompactFields;
+
+impl<'writer> FormatFields<'writer> for CompactFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut visitor = CompactVisitor {
+            writer,
+            result: Ok(()),
+            empty: true,
+        };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+struct CompactVisitor<'writer> {
+    writer: Writer<'writer>,
+    result: fmt::Result,
+    empty: bool,
+}
+
+impl Visit for CompactVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.record_debug(field, &format_args!("{}", value)),
+            _ => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.result.is_err() {
+            return;
+        }
+        let separator = if self.empty { "" } else { " " };
+        self.empty = false;
+        self.result = match field.name() {
+            "message" => write!(self.writer, "{}{:?}", separator, value),
+            name if is_redacted(name) => write!(self.writer, "{}{}={}", separator, name, REDACTED),
+            name => write!(self.writer, "{}{}={:?}", separator, name, value),
+        };
+    }
+}
+
+// Logs kept in memory, to read them back, e.g. in tests.
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl LogBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogBuffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tracing::{compact_log_layer, json_log_layer};
+    use tracing_subscriber::prelude::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("failed to add user")]
+    struct StoreError(#[source] std::io::Error);
+
+    // The lines logged by `log`, as JSON.
+    fn get_log_lines(log: impl FnOnce()) -> Vec<Value> {
+        let buffer = LogBuffer::default();
+        let subscriber = tracing_subscriber::registry().with(json_log_layer(buffer.clone()));
+        tracing::subscriber::with_default(subscriber, log);
+
+        buffer
+            .contents()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Invalid log line"))
+            .collect()
+    }
+
+    #[test]
+    fn test_log_format_parse() {
+        assert_eq!(LogFormat::parse("json"), Ok(LogFormat::Json));
+        assert_eq!(LogFormat::parse(" Compact "), Ok(LogFormat::Compact));
+        assert!(LogFormat::parse("pretty").is_err());
+    }
+
+    #[test]
+    fn test_events_are_logged_one_per_line() {
+        let lines = get_log_lines(|| {
+            tracing::info!(status = 200, "first\nline");
+            tracing::warn!(latency = 1.5, "second");
+        });
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["fields"]["message"], "first\nline");
+        assert_eq!(lines[0]["fields"]["status"], 200);
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["fields"]["latency"], 1.5);
+    }
+
+    #[test]
+    fn test_error_chains_are_arrays() {
+        let error = StoreError(std::io::Error::other("connection refused"));
+        let lines = get_log_lines(|| {
+            tracing::error!(error = &error as &(dyn Error + 'static), "request failed");
+        });
+
+        assert_eq!(
+            lines[0]["fields"]["error"],
+            serde_json::json!(["failed to add user", "connection refused"])
+        );
+    }
+
+    #[test]
+    fn test_spans_fields_are_logged_with_events() {
+        let lines = get_log_lines(|| {
+            let span =
+                tracing::info_span!("request", request_id = "42", status = tracing::field::Empty);
+            let _guard = span.enter();
+            span.record("status", 201);
+            tracing::info!("done");
+        });
+
+        assert_eq!(
+            lines[0]["spans"],
+            serde_json::json!([{ "name": "request", "request_id": "42", "status": 201 }])
+        );
+    }
+
+    #[test]
+    fn test_sensitive_fields_are_redacted() {
+        let lines = get_log_lines(|| {
+            let span = tracing::info_span!("login", email = "user@example.com");
+            let _guard = span.enter();
+            tracing::info!(
+                password = "hunter22",
+                refresh_token = "abc",
+                user_id = "1",
+                "logged in"
+            );
+        });
+
+        assert_eq!(lines[0]["spans"][0]["email"], REDACTED);
+        assert_eq!(lines[0]["fields"]["password"], REDACTED);
+        assert_eq!(lines[0]["fields"]["refresh_token"], REDACTED);
+        assert_eq!(lines[0]["fields"]["user_id"], "1");
+    }
+
+    #[test]
+    fn test_sensitive_fields_are_redacted_in_compact_logs() {
+        let buffer = LogBuffer::default();
+        let subscriber = tracing_subscriber::registry().with(compact_log_layer(buffer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("login", email = "user@example.com");
+            let _guard = span.enter();
+            tracing::info!(password = "hunter22", user_id = "1", "logged in");
+        });
+
+        let logs = buffer.contents();
+        assert!(logs.contains("logged in"));
+        assert!(logs.contains("email=[REDACTED]"));
+        assert!(logs.contains("password=[REDACTED]"));
+        assert!(logs.contains("user_id=\"1\""));
+        assert!(!logs.contains("user@example.com"));
+        assert!(!logs.contains("hunter22"));
+    }
+}
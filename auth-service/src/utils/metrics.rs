@@ -0,0 +1,310 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec, Opts,
+    Registry, TextEncoder,
+};
+use std::{sync::LazyLock, time::Duration};
+
+use crate::domain::{AuditEventKind, AuditOutcome};
+
+// Media type of the Prometheus text format.
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// Operations timed by `password_hashing_timer`.
+pub const HASH_OPERATION: &str = "hash";
+pub const VERIFY_OPERATION: &str = "verify";
+
+// Label of requests that matched no route, so that unknown paths do not each get a series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+// Every metric of the process, in a registry of its own.
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+struct Metrics {
+    registry: Registry,
+    signups: IntCounterVec,
+    logins: IntCounterVec,
+    two_fa_challenges: IntCounterVec,
+    two_fa_verifications: IntCounterVec,
+    logouts: IntCounterVec,
+    token_verifications: IntCounterVec,
+    admin_actions: IntCounterVec,
+    email_send_failures: IntCounter,
+    request_duration: HistogramVec,
+    password_hashing_duration: HistogramVec,
+    redis_duration: HistogramVec,
+    postgres_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let outcomes = |name: &str, help: &str| {
+            let counter =
+                IntCounterVec::new(Opts::new(name, help), &["outcome"]).expect("Invalid counter");
+            // Series are only exported once created: start them at zero, so that
+            // rates are right from the first event.
+            for outcome in [AuditOutcome::Success, AuditOutcome::Failure] {
+                counter.with_label_values(&[outcome.as_str()]);
+            }
+            registry
+                .register(Box::new(counter.clone()))
+                .expect("Counter registered twice");
+            counter
+        };
+        let durations = |name: &str, help: &str, labels: &[&str]| {
+            let histogram = HistogramVec::new(HistogramOpts::new(name, help), labels)
+                .expect("Invalid histogram");
+            registry
+                .register(Box::new(histogram.clone()))
+                .expect("Histogram registered twice");
+            histogram
+        };
+
+        let signups = outcomes("auth_signups_total", "Signups, by outcome");
+        let logins = outcomes(
+            "auth_logins_total",
+            "Logins that started a session or failed, by outcome",
+        );
+        let two_fa_challenges = outcomes(
+            "auth_2fa_challenges_total",
+            "Logins that waited for a 2FA code, by outcome",
+        );
+        let two_fa_verifications = outcomes(
+            "auth_2fa_verifications_total",
+            "2FA codes checked, by outcome",
+        );
+        let logouts = outcomes("auth_logouts_total", "Logouts, by outcome");
+        let token_verifications = outcomes(
+            "auth_token_verifications_total",
+            "Tokens checked through /verify-token, by outcome",
+        );
+        let admin_actions = IntCounterVec::new(
+            Opts::new(
+                "auth_admin_actions_total",
+                "Changes made through the admin API, by action and outcome",
+            ),
+            &["action", "outcome"],
+        )
+        .expect("Invalid counter");
+        registry
+            .register(Box::new(admin_actions.clone()))
+            .expect("Counter registered twice");
+        let email_send_failures = IntCounter::new(
+            "auth_email_send_failures_total",
+            "Emails the provider failed to send",
+        )
+        .expect("Invalid counter");
+        registry
+            .register(Box::new(email_send_failures.clone()))
+            .expect("Counter registered twice");
+        let request_duration = durations(
+            "http_request_duration_seconds",
+            "Time to respond to a request, by route",
+            &["method", "route", "status"],
+        );
+        let password_hashing_duration = durations(
+            "auth_password_hashing_duration_seconds",
+            "Time to hash a new password or verify one with argon2",
+            &["operation"],
+        );
+        for operation in [HASH_OPERATION, VERIFY_OPERATION] {
+            password_hashing_duration.with_label_values(&[operation]);
+        }
+        let redis_duration = durations(
+            "auth_redis_command_duration_seconds",
+            "Time for Redis to answer a command or pipeline",
+            &["command"],
+        );
+        let postgres_duration = durations(
+            "auth_postgres_query_duration_seconds",
+            "Time for PostgreSQL to answer the queries of a store operation",
+            &["operation"],
+        );
+
+        Self {
+            registry,
+            signups,
+            logins,
+            two_fa_challenges,
+            two_fa_verifications,
+            logouts,
+            token_verifications,
+            admin_actions,
+            email_send_failures,
+            request_duration,
+            password_hashing_duration,
+            redis_duration,
+            postgres_duration,
+        }
+    }
+}
+
+// Count the result of an audited request.
+pub fn record_auth_event(kind: AuditEventKind, outcome: AuditOutcome) {
+    let counter = match kind {
+        AuditEventKind::Signup => &METRICS.signups,
+        AuditEventKind::Login => &METRICS.logins,
+        AuditEventKind::TwoFAChallenge => &METRICS.two_fa_challenges,
+        AuditEventKind::TwoFAVerification => &METRICS.two_fa_verifications,
+        AuditEventKind::Logout => &METRICS.logouts,
+        AuditEventKind::TokenVerification => &METRICS.token_verifications,
+        AuditEventKind::AdminUserCreation
+        | AuditEventKind::AdminUserDeletion
+        | AuditEventKind::AdminPasswordReset
+        | AuditEventKind::AdminTwoFAChange
+        | AuditEventKind::AdminLock
+        | AuditEventKind::AdminUnlock
+        | AuditEventKind::AdminSessionRevocation
+        | AuditEventKind::SigningKeyRotation => {
+            METRICS
+                .admin_actions
+                .with_label_values(&[kind.as_str(), outcome.as_str()])
+                .inc();
+            return;
+        }
+    };
+    counter.with_label_values(&[outcome.as_str()]).inc();
+}
+
+pub fn record_email_send_failure() {
+    METRICS.email_send_failures.inc();
+}
+
+// Record how long a request took. `route` is the pattern it matched, e.g. `/sessions/:id`.
+pub fn observe_request(method: &str, route: Option<&str>, status: u16, latency: Duration) {
+    METRICS
+        .request_duration
+        .with_label_values(&[
+            method,
+            route.unwrap_or(UNMATCHED_ROUTE),
+            &status.to_string(),
+        ])
+        .observe(latency.as_secs_f64());
+}
+
+// Timers record the time elapsed when they are dropped, or `observe_duration` is called.
+pub fn password_hashing_timer(operation: &str) -> HistogramTimer {
+    METRICS
+        .password_hashing_duration
+        .with_label_values(&[operation])
+        .start_timer()
+}
+
+pub fn redis_timer(command: &str) -> HistogramTimer {
+    METRICS
+        .redis_duration
+        .with_label_values(&[command])
+        .start_timer()
+}
+
+pub fn postgres_timer(operation: &str) -> HistogramTimer {
+    METRICS
+        .postgres_duration
+        .with_label_values(&[operation])
+        .start_timer()
+}
+
+// The method and route of the request a response answers, for `on_response`.
+#[derive(Debug, Clone)]
+pub struct RouteInfo {
+    pub method: String,
+    pub route: Option<String>,
+}
+
+// Copy the method and the matched route of the request to its response. It must
+// run inside the `TraceLayer`, whose `on_response` only sees the response.
+pub async fn set_route_info(request: Request, next: Next) -> Response {
+    let info = RouteInfo {
+        method: request.method().to_string(),
+        route: request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_owned()),
+    };
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(info);
+    response
+}
+
+// Every metric, in the Prometheus text format.
+pub fn encode() -> Result<String, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer)?;
+    String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Value of the series with exactly these labels, from the text format.
+    fn get_value(series: &str) -> f64 {
+        encode()
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .map(|value| value.parse().unwrap())
+            .unwrap_or(0.0)
+    }
+
+    #[test]
+    fn test_known_series_are_exported_before_use() {
+        let text = encode().unwrap();
+        for series in [
+            "auth_email_send_failures_total",
+            r#"auth_signups_total{outcome="success"}"#,
+            r#"auth_token_verifications_total{outcome="failure"}"#,
+            r#"auth_password_hashing_duration_seconds_count{operation="verify"}"#,
+        ] {
+            assert!(text.contains(&format!("{} ", series)), "{}", series);
+        }
+    }
+
+    #[test]
+    fn test_auth_events_are_counted_by_kind_and_outcome() {
+        let series = r#"auth_logins_total{outcome="failure"}"#;
+        let before = get_value(series);
+
+        record_auth_event(AuditEventKind::Login, AuditOutcome::Failure);
+        record_auth_event(AuditEventKind::Login, AuditOutcome::Failure);
+        record_auth_event(AuditEventKind::Signup, AuditOutcome::Failure);
+
+        assert_eq!(get_value(series), before + 2.0);
+    }
+
+    #[test]
+    fn test_admin_actions_are_counted_by_action_and_outcome() {
+        let series = r#"auth_admin_actions_total{action="admin_lock",outcome="success"}"#;
+        let before = get_value(series);
+
+        record_auth_event(AuditEventKind::AdminLock, AuditOutcome::Success);
+
+        assert_eq!(get_value(series), before + 1.0);
+    }
+
+    #[test]
+    fn test_requests_without_a_route_share_a_label() {
+        let series =
+            r#"http_request_duration_seconds_count{method="GET",route="unmatched",status="404"}"#;
+        let before = get_value(series);
+
+        observe_request("GET", None, 404, Duration::from_millis(3));
+
+        assert_eq!(get_value(series), before + 1.0);
+    }
+
+    #[test]
+    fn test_timers_observe_when_dropped() {
+        let series = r#"auth_postgres_query_duration_seconds_count{operation="test"}"#;
+        let before = get_value(series);
+
+        drop(postgres_timer("test"));
+
+        assert_eq!(get_value(series), before + 1.0);
+    }
+}
@@ -1,3 +1,12 @@
+pub mod audit;
 pub mod auth;
+pub mod authorization;
 pub mod constants;
+pub mod key_ring;
+pub mod log_format;
+pub mod metrics;
+pub mod rate_limit;
+pub mod settings;
+pub mod shutdown;
+pub mod signing_key;
 pub mod tracing;
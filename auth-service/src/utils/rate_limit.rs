@@ -0,0 +1,131 @@
+use chrono::Utc;
+use tracing;
+
+use crate::{
+    app_state::RateLimitStoreType,
+    domain::{
+        AuthAPIError, EMAIL_RATE_LIMIT, Failures, IP_RATE_LIMIT, RateLimitKey, RateLimitPolicy,
+    },
+};
+
+// An attempt counted against a key by `try_acquire`.
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    key: RateLimitKey,
+    // Failures of the key before the attempt, restored when it is released.
+    previous: Option<Failures>,
+    // Failures of the key, including the attempt.
+    pub failures: Failures,
+}
+
+// Count the attempt against every key, and refuse it if any of the keys has to wait,
+// for as long as the longest wait. Each key is counted and checked in one step, so
+// that concurrent attempts cannot all get through. The attempt counts as failed until
+// it is released, or the failures are cleared.
+#[tracing::instrument(name = "Acquire rate limit", skip_all)]
+pub async fn try_acquire(
+    rate_limit_store: RateLimitStoreType,
+    keys: &[RateLimitKey],
+) -> Result<Vec<Reservation>, AuthAPIError> {
+    let now = Utc::now().timestamp();
+    let mut reservations = Vec::with_capacity(keys.len());
+    let mut refused = false;
+    let mut retry_after = 0;
+    for key in keys {
+        let previous = rate_limit_store
+            .reserve_attempt(key, now)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        let key_failures = Failures {
+            count: previous.map_or(0, |previous| previous.count) + 1,
+            last_failure_at: now,
+        };
+        reservations.push(Reservation {
+            key: key.clone(),
+            previous,
+            failures: key_failures,
+        });
+
+        let Some(policy) = policy(key) else {
+            continue;
+        };
+        refused |= previous.is_some_and(|previous| policy.retry_after(&previous, now).is_some());
+        // Attempts refused while waiting count too, and make the wait longer.
+        retry_after = retry_after.max(policy.retry_after(&key_failures, now).unwrap_or(0));
+    }
+
+    match refused {
+        true => Err(AuthAPIError::TooManyRequests { retry_after }),
+        false => Ok(reservations),
+    }
+}
+
+// Stop counting an attempt that succeeded, without forgetting earlier failures.
+#[tracing::instrument(name = "Release rate limit", skip_all)]
+pub async fn release(
+    rate_limit_store: RateLimitStoreType,
+    reservations: &[Reservation],
+) -> Result<(), AuthAPIError> {
+    for reservation in reservations {
+        rate_limit_store
+            .release_attempt(&reservation.key, reservation.previous)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Clear failed attempts", skip_all)]
+pub async fn clear_failures(
+    rate_limit_store: RateLimitStoreType,
+    key: &RateLimitKey,
+) -> Result<(), AuthAPIError> {
+    rate_limit_store
+        .clear_failures(key)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+// Login attempts are capped by `MAX_2FA_GUESSES` instead of a delay.
+fn policy(key: &RateLimitKey) -> Option<RateLimitPolicy> {
+    match key {
+        RateLimitKey::Email(_) => Some(EMAIL_RATE_LIMIT),
+        RateLimitKey::Ip(_) => Some(IP_RATE_LIMIT),
+        RateLimitKey::LoginAttempt(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::data_stores::HashmapRateLimitStore;
+    use std::sync::Arc;
+
+    fn ip_key() -> RateLimitKey {
+        RateLimitKey::Ip("127.0.0.1".parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_attempts_are_refused_after_free_attempts() {
+        let store: RateLimitStoreType = Arc::new(HashmapRateLimitStore::default());
+        for count in 1..=IP_RATE_LIMIT.free_attempts {
+            let reservations = try_acquire(store.clone(), &[ip_key()]).await.unwrap();
+            assert_eq!(reservations[0].failures.count, count);
+        }
+
+        let result = try_acquire(store.clone(), &[ip_key()]).await;
+        assert!(matches!(
+            result,
+            Err(AuthAPIError::TooManyRequests { retry_after: 2 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_released_attempts_are_not_refused() {
+        let store: RateLimitStoreType = Arc::new(HashmapRateLimitStore::default());
+        for _ in 0..2 * IP_RATE_LIMIT.free_attempts {
+            let reservations = try_acquire(store.clone(), &[ip_key()]).await.unwrap();
+            release(store.clone(), &reservations).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,1041 @@
+use argon2::Params;
+use axum::http::HeaderValue;
+use lettre::transport::smtp::{SMTP_PORT, SUBMISSION_PORT, SUBMISSIONS_PORT};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+use super::{
+    constants::{
+        DEFAULT_EMAIL_CHANGE_URL, DEFAULT_EMAIL_LOCAL_PART_POLICY, DEFAULT_EMAIL_VERIFICATION_URL,
+        DEFAULT_PASSWORD_RESET_URL, DEFAULT_REDIS_HOSTNAME, DEFAULT_TOTP_SKEW, env,
+    },
+    key_ring::KeySource,
+    log_format::LogFormat,
+};
+use crate::domain::{Email, LocalPartPolicy};
+
+// Everything the server can be configured with. Settings are read from a TOML
+// file, then from environment variables, then from command line flags, each
+// overriding the one before.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub application: ApplicationSettings,
+    pub database: DatabaseSettings,
+    pub redis: RedisSettings,
+    pub jwt: JwtSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub email_client: EmailClientSettings,
+    pub smtp: SmtpSettings,
+    pub email: EmailSettings,
+    pub links: LinkSettings,
+    pub totp: TotpSettings,
+    pub admin: AdminSettings,
+    pub audit: AuditSettings,
+    pub health: HealthSettings,
+    pub tracing: TracingSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApplicationSettings {
+    // Address to listen on, e.g. `0.0.0.0:3000`. Port 0 picks a free port.
+    pub address: String,
+    // Origins whose pages may call the API with their cookies.
+    pub allowed_origins: Vec<String>,
+    // How long the server keeps accepting requests on shutdown, while unready, so that
+    // load balancers stop sending new ones before the listener closes.
+    pub pre_stop_delay_milliseconds: u64,
+    // Longest wait, after the pre-stop delay, for requests in flight and background
+    // tasks to finish.
+    pub drain_timeout_milliseconds: u64,
+}
+
+impl Default for ApplicationSettings {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0:3000".to_owned(),
+            allowed_origins: vec![
+                "http://localhost:8000".to_owned(),
+                "http://206.189.177.178:8000".to_owned(),
+            ],
+            pre_stop_delay_milliseconds: 5_000,
+            drain_timeout_milliseconds: 30_000,
+        }
+    }
+}
+
+impl ApplicationSettings {
+    pub fn pre_stop_delay(&self) -> Duration {
+        Duration::from_millis(self.pre_stop_delay_milliseconds)
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_millis(self.drain_timeout_milliseconds)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    // Required.
+    pub url: Secret<String>,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self { url: unset() }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisSettings {
+    pub host_name: String,
+}
+
+impl Default for RedisSettings {
+    fn default() -> Self {
+        Self {
+            host_name: DEFAULT_REDIS_HOSTNAME.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtSettings {
+    // Shared secret tokens are signed with. Required unless a signing key file is set.
+    pub secret: Secret<String>,
+    pub signing_key_file: Option<PathBuf>,
+    pub key_id: Option<String>,
+    // Public or private PEM keys, or JWKs, that verify tokens without signing any.
+    pub verification_key_files: Vec<PathBuf>,
+    // How long an auth token is valid for.
+    pub token_ttl_seconds: i64,
+}
+
+impl Default for JwtSettings {
+    fn default() -> Self {
+        Self {
+            secret: unset(),
+            signing_key_file: None,
+            key_id: None,
+            verification_key_files: Vec::new(),
+            token_ttl_seconds: 600, // 10 minutes
+        }
+    }
+}
+
+impl JwtSettings {
+    pub fn key_source(&self) -> KeySource {
+        KeySource {
+            secret: Some(self.secret.clone()).filter(|secret| !is_unset(secret)),
+            signing_key_file: self.signing_key_file.clone(),
+            key_id: self.key_id.clone(),
+            verification_key_files: self.verification_key_files.clone(),
+        }
+    }
+}
+
+// Argon2id cost of new password hashes. Existing hashes keep the cost they were made with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordHashingSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashingSettings {
+    fn default() -> Self {
+        Self {
+            memory_kib: 15000,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<Params, argon2::Error> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+// Service emails are sent through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    // Postmark's HTTP API.
+    Postmark,
+    // An SMTP server, configured by `SmtpSettings`.
+    Smtp,
+}
+
+impl EmailProvider {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "postmark" => Ok(Self::Postmark),
+            "smtp" => Ok(Self::Smtp),
+            _ => Err(format!("`{}` is not postmark or smtp", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailClientSettings {
+    pub provider: EmailProvider,
+    pub base_url: String,
+    // Address emails are sent from.
+    pub sender: String,
+    // Postmark server token. Required with Postmark.
+    pub auth_token: Secret<String>,
+    // Longest wait for the provider, to connect or for each answer.
+    pub timeout_milliseconds: u64,
+}
+
+impl Default for EmailClientSettings {
+    fn default() -> Self {
+        Self {
+            provider: EmailProvider::Postmark,
+            base_url: "https://api.postmarkapp.com/email".to_owned(),
+            sender: "oz+postmark@cyprio.net".to_owned(),
+            auth_token: unset(),
+            timeout_milliseconds: 10_000,
+        }
+    }
+}
+
+impl EmailClientSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+// How the connection to the SMTP server is encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // Connect in plain text, then upgrade with STARTTLS. Servers that do not offer
+    // it are refused.
+    StartTls,
+    // Connect over TLS.
+    Tls,
+    // Never encrypt, e.g. for a relay on the same host.
+    None,
+}
+
+impl SmtpTls {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            "none" => Ok(Self::None),
+            _ => Err(format!("`{}` is not starttls, tls or none", s)),
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::StartTls => SUBMISSION_PORT,
+            Self::Tls => SUBMISSIONS_PORT,
+            Self::None => SMTP_PORT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpSettings {
+    pub host: String,
+    // Defaults to the usual port of the TLS mode: 587, 465, or 25 without TLS.
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    // Emails are sent without AUTH unless both are set.
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    // Connections kept open to the server, and reused between emails.
+    pub max_connections: u32,
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_owned(),
+            port: None,
+            tls: SmtpTls::StartTls,
+            username: None,
+            password: None,
+            max_connections: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailSettings {
+    pub local_part_policy: LocalPartPolicy,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        Self {
+            local_part_policy: DEFAULT_EMAIL_LOCAL_PART_POLICY,
+        }
+    }
+}
+
+// Pages of the UI that emailed links point to. Each gets the token as `?token=`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkSettings {
+    pub password_reset_url: String,
+    pub email_verification_url: String,
+    pub email_change_url: String,
+}
+
+impl Default for LinkSettings {
+    fn default() -> Self {
+        Self {
+            password_reset_url: DEFAULT_PASSWORD_RESET_URL.to_owned(),
+            email_verification_url: DEFAULT_EMAIL_VERIFICATION_URL.to_owned(),
+            email_change_url: DEFAULT_EMAIL_CHANGE_URL.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TotpSettings {
+    pub skew: u8,
+}
+
+impl Default for TotpSettings {
+    fn default() -> Self {
+        Self {
+            skew: DEFAULT_TOTP_SKEW,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminSettings {
+    // Admin endpoints only accept user tokens unless an API key is configured. Rotating
+    // signing keys and scraping metrics require it.
+    pub api_key: Option<Secret<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSettings {
+    // The audit log goes to Postgres unless a JSON lines file is configured.
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSettings {
+    // The email provider is not needed to log in, so it is only checked on request,
+    // and never makes the service unready.
+    pub check_email: bool,
+    // Longest wait for a dependency to answer a readiness check.
+    pub timeout_milliseconds: u64,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            check_email: false,
+            timeout_milliseconds: 2_000,
+        }
+    }
+}
+
+impl HealthSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracingSettings {
+    // Base URL of an OpenTelemetry collector, e.g. `http://localhost:4318`. Spans are
+    // exported to its `/v1/traces` over OTLP/HTTP when set, and only logged otherwise.
+    pub otlp_endpoint: Option<String>,
+    // Name the service's spans are exported under.
+    pub service_name: String,
+    pub log_format: LogFormat,
+}
+
+impl Default for TracingSettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "auth-service".to_owned(),
+            log_format: LogFormat::Compact,
+        }
+    }
+}
+
+impl TracingSettings {
+    // Where spans are sent, if anywhere.
+    pub fn traces_endpoint(&self) -> Option<String> {
+        self.otlp_endpoint
+            .as_ref()
+            .map(|endpoint| format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+    }
+}
+
+// Environment variables, and the setting each one overrides.
+const ENV_VARS: &[(&str, &str)] = &[
+    (env::APP_ADDRESS_ENV_VAR, "application.address"),
+    (env::ALLOWED_ORIGINS_ENV_VAR, "application.allowed_origins"),
+    (
+        env::PRE_STOP_DELAY_MILLISECONDS_ENV_VAR,
+        "application.pre_stop_delay_milliseconds",
+    ),
+    (
+        env::DRAIN_TIMEOUT_MILLISECONDS_ENV_VAR,
+        "application.drain_timeout_milliseconds",
+    ),
+    (env::DATABASE_URL_ENV_VAR, "database.url"),
+    (env::REDIS_HOST_NAME_ENV_VAR, "redis.host_name"),
+    (env::JWT_SECRET_ENV_VAR, "jwt.secret"),
+    (env::JWT_SIGNING_KEY_FILE_ENV_VAR, "jwt.signing_key_file"),
+    (env::JWT_KEY_ID_ENV_VAR, "jwt.key_id"),
+    (
+        env::JWT_VERIFICATION_KEY_FILES_ENV_VAR,
+        "jwt.verification_key_files",
+    ),
+    (env::TOKEN_TTL_SECONDS_ENV_VAR, "jwt.token_ttl_seconds"),
+    (
+        env::ARGON2_MEMORY_KIB_ENV_VAR,
+        "password_hashing.memory_kib",
+    ),
+    (
+        env::ARGON2_ITERATIONS_ENV_VAR,
+        "password_hashing.iterations",
+    ),
+    (
+        env::ARGON2_PARALLELISM_ENV_VAR,
+        "password_hashing.parallelism",
+    ),
+    (env::EMAIL_PROVIDER_ENV_VAR, "email_client.provider"),
+    (env::EMAIL_BASE_URL_ENV_VAR, "email_client.base_url"),
+    (env::EMAIL_SENDER_ENV_VAR, "email_client.sender"),
+    (env::POSTMARK_AUTH_TOKEN_ENV_VAR, "email_client.auth_token"),
+    (
+        env::EMAIL_TIMEOUT_MILLISECONDS_ENV_VAR,
+        "email_client.timeout_milliseconds",
+    ),
+    (env::SMTP_HOST_ENV_VAR, "smtp.host"),
+    (env::SMTP_PORT_ENV_VAR, "smtp.port"),
+    (env::SMTP_TLS_ENV_VAR, "smtp.tls"),
+    (env::SMTP_USERNAME_ENV_VAR, "smtp.username"),
+    (env::SMTP_PASSWORD_ENV_VAR, "smtp.password"),
+    (env::SMTP_MAX_CONNECTIONS_ENV_VAR, "smtp.max_connections"),
+    (
+        env::EMAIL_LOCAL_PART_POLICY_ENV_VAR,
+        "email.local_part_policy",
+    ),
+    (env::PASSWORD_RESET_URL_ENV_VAR, "links.password_reset_url"),
+    (
+        env::EMAIL_VERIFICATION_URL_ENV_VAR,
+        "links.email_verification_url",
+    ),
+    (env::EMAIL_CHANGE_URL_ENV_VAR, "links.email_change_url"),
+    (env::TOTP_SKEW_ENV_VAR, "totp.skew"),
+    (env::ADMIN_API_KEY_ENV_VAR, "admin.api_key"),
+    (env::AUDIT_LOG_FILE_ENV_VAR, "audit.log_file"),
+    (env::HEALTH_CHECK_EMAIL_ENV_VAR, "health.check_email"),
+    (env::OTLP_ENDPOINT_ENV_VAR, "tracing.otlp_endpoint"),
+    (env::LOG_FORMAT_ENV_VAR, "tracing.log_format"),
+];
+
+const CONFIG_FLAG: &str = "--config";
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("failed to read config file {path}: {message}")]
+    File { path: PathBuf, message: String },
+    #[error("invalid value for {key} from {origin}: {message}")]
+    Value {
+        key: String,
+        origin: String,
+        message: String,
+    },
+    #[error("invalid command line: {0}")]
+    Flag(String),
+    #[error("invalid settings:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+impl Settings {
+    // Settings of the server process: the file named by `--config` or CONFIG_FILE,
+    // if any, then the environment (including `.env`), then the other flags.
+    pub fn load() -> Result<Self, SettingsError> {
+        dotenvy::dotenv().ok();
+        let vars: HashMap<String, String> = std::env::vars().collect();
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        Self::from_sources(&vars, &args)
+    }
+
+    // Layer the sources over the defaults, and validate the result. Flags are
+    // `--config <file>` and `--<section>.<name> <value>`, or `--flag=value`.
+    pub fn from_sources(
+        vars: &HashMap<String, String>,
+        args: &[String],
+    ) -> Result<Self, SettingsError> {
+        let (config_file, flags) = parse_flags(args)?;
+        let config_file = config_file.or_else(|| {
+            vars.get(env::CONFIG_FILE_ENV_VAR)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        });
+
+        let mut settings = match config_file {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+        for (name, key) in ENV_VARS {
+            if let Some(value) = vars.get(*name).filter(|value| !value.is_empty()) {
+                settings
+                    .set(key, value)
+                    .map_err(|message| SettingsError::Value {
+                        key: key.to_string(),
+                        origin: name.to_string(),
+                        message,
+                    })?;
+            }
+        }
+        for (key, value) in flags {
+            settings
+                .set(&key, &value)
+                .map_err(|message| SettingsError::Value {
+                    origin: format!("--{}", key),
+                    key,
+                    message,
+                })?;
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    // Settings from a TOML file, with defaults for what it leaves out. Not validated.
+    pub fn from_file(path: &Path) -> Result<Self, SettingsError> {
+        let content = std::fs::read_to_string(path).map_err(|e| SettingsError::File {
+            path: path.to_owned(),
+            message: e.to_string(),
+        })?;
+        Self::from_toml(&content).map_err(|message| SettingsError::File {
+            path: path.to_owned(),
+            message,
+        })
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        basic_toml::from_str(content).map_err(|e| e.to_string())
+    }
+
+    // Override one setting, named `<section>.<name>`, with a value given as text.
+    // Lists are separated by commas.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "application.address" => self.application.address = value.to_owned(),
+            "application.allowed_origins" => self.application.allowed_origins = list(value),
+            "application.pre_stop_delay_milliseconds" => {
+                self.application.pre_stop_delay_milliseconds = number(value)?
+            }
+            "application.drain_timeout_milliseconds" => {
+                self.application.drain_timeout_milliseconds = number(value)?
+            }
+            "database.url" => self.database.url = Secret::new(value.to_owned()),
+            "redis.host_name" => self.redis.host_name = value.to_owned(),
+            "jwt.secret" => self.jwt.secret = Secret::new(value.to_owned()),
+            "jwt.signing_key_file" => self.jwt.signing_key_file = Some(PathBuf::from(value)),
+            "jwt.key_id" => self.jwt.key_id = Some(value.to_owned()),
+            "jwt.verification_key_files" => {
+                self.jwt.verification_key_files =
+                    list(value).into_iter().map(PathBuf::from).collect()
+            }
+            "jwt.token_ttl_seconds" => self.jwt.token_ttl_seconds = number(value)?,
+            "password_hashing.memory_kib" => self.password_hashing.memory_kib = number(value)?,
+            "password_hashing.iterations" => self.password_hashing.iterations = number(value)?,
+            "password_hashing.parallelism" => self.password_hashing.parallelism = number(value)?,
+            "email_client.provider" => self.email_client.provider = EmailProvider::parse(value)?,
+            "email_client.base_url" => self.email_client.base_url = value.to_owned(),
+            "email_client.sender" => self.email_client.sender = value.to_owned(),
+            "email_client.auth_token" => {
+                self.email_client.auth_token = Secret::new(value.to_owned())
+            }
+            "email_client.timeout_milliseconds" => {
+                self.email_client.timeout_milliseconds = number(value)?
+            }
+            "smtp.host" => self.smtp.host = value.to_owned(),
+            "smtp.port" => self.smtp.port = Some(number(value)?),
+            "smtp.tls" => self.smtp.tls = SmtpTls::parse(value)?,
+            "smtp.username" => self.smtp.username = Some(value.to_owned()),
+            "smtp.password" => self.smtp.password = Some(Secret::new(value.to_owned())),
+            "smtp.max_connections" => self.smtp.max_connections = number(value)?,
+            "email.local_part_policy" => {
+                self.email.local_part_policy =
+                    LocalPartPolicy::parse(value).map_err(|e| e.to_string())?
+            }
+            "links.password_reset_url" => self.links.password_reset_url = value.to_owned(),
+            "links.email_verification_url" => self.links.email_verification_url = value.to_owned(),
+            "links.email_change_url" => self.links.email_change_url = value.to_owned(),
+            "totp.skew" => self.totp.skew = number(value)?,
+            "admin.api_key" => self.admin.api_key = Some(Secret::new(value.to_owned())),
+            "audit.log_file" => self.audit.log_file = Some(PathBuf::from(value)),
+            "health.check_email" => self.health.check_email = boolean(value)?,
+            "health.timeout_milliseconds" => self.health.timeout_milliseconds = number(value)?,
+            "tracing.otlp_endpoint" => self.tracing.otlp_endpoint = Some(value.to_owned()),
+            "tracing.service_name" => self.tracing.service_name = value.to_owned(),
+            "tracing.log_format" => self.tracing.log_format = LogFormat::parse(value)?,
+            _ => return Err("unknown setting".to_owned()),
+        }
+        Ok(())
+    }
+
+    // Check every setting, and report all the problems at once.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let mut problems = Vec::new();
+
+        if !is_host_and_port(&self.application.address) {
+            problems.push(format!(
+                "application.address must be a host and port, e.g. 0.0.0.0:3000, not `{}`",
+                self.application.address
+            ));
+        }
+        for origin in &self.application.allowed_origins {
+            let is_http = origin.starts_with("http://") || origin.starts_with("https://");
+            if !is_http || HeaderValue::from_str(origin).is_err() {
+                problems.push(format!(
+                    "application.allowed_origins: `{}` is not an http(s) origin",
+                    origin
+                ));
+            }
+        }
+        if is_unset(&self.database.url) {
+            problems.push(required("database.url", env::DATABASE_URL_ENV_VAR));
+        }
+        if self.redis.host_name.is_empty() {
+            problems.push(required("redis.host_name", env::REDIS_HOST_NAME_ENV_VAR));
+        }
+        if is_unset(&self.jwt.secret) && self.jwt.signing_key_file.is_none() {
+            problems.push(format!(
+                "{}, unless jwt.signing_key_file is",
+                required("jwt.secret", env::JWT_SECRET_ENV_VAR)
+            ));
+        }
+        if self.jwt.token_ttl_seconds <= 0 {
+            problems.push("jwt.token_ttl_seconds must be positive".to_owned());
+        }
+        if let Err(e) = self.password_hashing.params() {
+            problems.push(format!("password_hashing: {}", e));
+        }
+        if self.email_client.provider == EmailProvider::Postmark
+            && self.email_client.base_url.is_empty()
+        {
+            problems.push(required(
+                "email_client.base_url",
+                env::EMAIL_BASE_URL_ENV_VAR,
+            ));
+        }
+        if Email::parse_with_policy(
+            Secret::new(self.email_client.sender.clone()),
+            self.email.local_part_policy,
+        )
+        .is_err()
+        {
+            problems.push(format!(
+                "email_client.sender: `{}` is not a valid email",
+                self.email_client.sender
+            ));
+        }
+        if self.email_client.provider == EmailProvider::Postmark
+            && is_unset(&self.email_client.auth_token)
+        {
+            problems.push(format!(
+                "{}, unless email_client.provider is smtp",
+                required("email_client.auth_token", env::POSTMARK_AUTH_TOKEN_ENV_VAR)
+            ));
+        }
+        if self.email_client.timeout_milliseconds == 0 {
+            problems.push("email_client.timeout_milliseconds must be positive".to_owned());
+        }
+        if self.email_client.provider == EmailProvider::Smtp {
+            if self.smtp.host.is_empty() {
+                problems.push(required("smtp.host", env::SMTP_HOST_ENV_VAR));
+            }
+            if self.smtp.username.is_some() != self.smtp.password.is_some() {
+                problems.push("smtp.username and smtp.password must be set together".to_owned());
+            }
+            if self.smtp.max_connections == 0 {
+                problems.push("smtp.max_connections must be positive".to_owned());
+            }
+        }
+        for (key, url) in [
+            ("links.password_reset_url", &self.links.password_reset_url),
+            (
+                "links.email_verification_url",
+                &self.links.email_verification_url,
+            ),
+            ("links.email_change_url", &self.links.email_change_url),
+        ] {
+            if url.is_empty() {
+                problems.push(format!("{} must not be empty", key));
+            }
+        }
+        if self
+            .admin
+            .api_key
+            .as_ref()
+            .is_some_and(|key| key.expose_secret().is_empty())
+        {
+            problems.push("admin.api_key must not be empty when set".to_owned());
+        }
+        if self.health.timeout_milliseconds == 0 {
+            problems.push("health.timeout_milliseconds must be positive".to_owned());
+        }
+        if let Some(endpoint) = &self.tracing.otlp_endpoint {
+            let is_http = endpoint.starts_with("http://") || endpoint.starts_with("https://");
+            if !is_http {
+                problems.push(format!(
+                    "tracing.otlp_endpoint: `{}` is not an http(s) URL",
+                    endpoint
+                ));
+            }
+        }
+        if self.tracing.service_name.is_empty() {
+            problems.push("tracing.service_name must not be empty".to_owned());
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(SettingsError::Invalid(problems)),
+        }
+    }
+}
+
+// Keys and values given as flags, in the order they were given.
+type Overrides = Vec<(String, String)>;
+
+// Split `--config` from the overrides.
+fn parse_flags(args: &[String]) -> Result<(Option<PathBuf>, Overrides), SettingsError> {
+    let mut config_file = None;
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(SettingsError::Flag(format!(
+                "unexpected argument `{}`",
+                arg
+            )));
+        };
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_owned(), value.to_owned()),
+            None => match args.next() {
+                Some(value) => (flag.to_owned(), value.to_owned()),
+                None => return Err(SettingsError::Flag(format!("missing value for `{}`", arg))),
+            },
+        };
+        if format!("--{}", key) == CONFIG_FLAG {
+            config_file = Some(PathBuf::from(value));
+        } else {
+            flags.push((key, value));
+        }
+    }
+    Ok((config_file, flags))
+}
+
+fn unset() -> Secret<String> {
+    Secret::new(String::new())
+}
+
+fn is_unset(secret: &Secret<String>) -> bool {
+    secret.expose_secret().is_empty()
+}
+
+fn required(key: &str, env_var: &str) -> String {
+    format!("{} must be set, e.g. with {}", key, env_var)
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid number", value))
+}
+
+fn boolean(value: &str) -> Result<bool, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not true or false", value))
+}
+
+// Host names are resolved when binding, so only the port can be checked here.
+fn is_host_and_port(address: &str) -> bool {
+    if address.parse::<SocketAddr>().is_ok() {
+        return true;
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) => {
+            !host.is_empty() && !host.contains(':') && port.parse::<u16>().is_ok()
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_vars() -> HashMap<String, String> {
+        HashMap::from([
+            (
+                env::DATABASE_URL_ENV_VAR.to_owned(),
+                "postgres://db".to_owned(),
+            ),
+            (env::JWT_SECRET_ENV_VAR.to_owned(), "secret".to_owned()),
+            (
+                env::POSTMARK_AUTH_TOKEN_ENV_VAR.to_owned(),
+                "token".to_owned(),
+            ),
+        ])
+    }
+
+    fn get_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn write_config(content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("settings-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn defaults_need_only_the_secrets() {
+        let settings = Settings::from_sources(&get_vars(), &[]).unwrap();
+        assert_eq!(settings.application.address, "0.0.0.0:3000");
+        assert_eq!(settings.jwt.token_ttl_seconds, 600);
+        assert_eq!(settings.password_hashing.memory_kib, 15000);
+        assert_eq!(settings.database.url.expose_secret(), "postgres://db");
+        assert!(settings.admin.api_key.is_none());
+    }
+
+    #[test]
+    fn env_overrides_file_and_flags_override_env() {
+        let path = write_config(
+            r#"
+            [application]
+            address = "127.0.0.1:4000"
+            allowed_origins = ["https://example.com"]
+
+            [jwt]
+            token_ttl_seconds = 300
+
+            [password_hashing]
+            iterations = 3
+            "#,
+        );
+        let mut vars = get_vars();
+        vars.insert(env::TOKEN_TTL_SECONDS_ENV_VAR.to_owned(), "120".to_owned());
+        vars.insert(
+            env::APP_ADDRESS_ENV_VAR.to_owned(),
+            "127.0.0.1:5000".to_owned(),
+        );
+        let args = get_args(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--application.address=127.0.0.1:6000",
+        ]);
+
+        let settings = Settings::from_sources(&vars, &args).unwrap();
+        assert_eq!(settings.application.address, "127.0.0.1:6000");
+        assert_eq!(
+            settings.application.allowed_origins,
+            vec!["https://example.com"]
+        );
+        assert_eq!(settings.jwt.token_ttl_seconds, 120);
+        assert_eq!(settings.password_hashing.iterations, 3);
+        assert_eq!(settings.password_hashing.memory_kib, 15000);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn config_file_can_come_from_env() {
+        let path = write_config("[totp]\nskew = 2\n");
+        let mut vars = get_vars();
+        vars.insert(
+            env::CONFIG_FILE_ENV_VAR.to_owned(),
+            path.to_str().unwrap().to_owned(),
+        );
+
+        let settings = Settings::from_sources(&vars, &[]).unwrap();
+        assert_eq!(settings.totp.skew, 2);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lists_are_split_on_commas() {
+        let mut vars = get_vars();
+        vars.insert(
+            env::ALLOWED_ORIGINS_ENV_VAR.to_owned(),
+            "http://a.example, http://b.example".to_owned(),
+        );
+        let settings = Settings::from_sources(&vars, &[]).unwrap();
+        assert_eq!(
+            settings.application.allowed_origins,
+            vec!["http://a.example", "http://b.example"]
+        );
+    }
+
+    #[test]
+    fn booleans_are_true_or_false() {
+        let mut vars = get_vars();
+        vars.insert(
+            env::HEALTH_CHECK_EMAIL_ENV_VAR.to_owned(),
+            "true".to_owned(),
+        );
+        let settings = Settings::from_sources(&vars, &[]).unwrap();
+        assert!(settings.health.check_email);
+
+        vars.insert(env::HEALTH_CHECK_EMAIL_ENV_VAR.to_owned(), "yes".to_owned());
+        assert!(Settings::from_sources(&vars, &[]).is_err());
+    }
+
+    #[test]
+    fn spans_are_exported_to_the_traces_path_of_the_collector() {
+        let settings = Settings::from_sources(&get_vars(), &[]).unwrap();
+        assert_eq!(settings.tracing.traces_endpoint(), None);
+
+        let mut vars = get_vars();
+        vars.insert(
+            env::OTLP_ENDPOINT_ENV_VAR.to_owned(),
+            "http://collector:4318/".to_owned(),
+        );
+        let settings = Settings::from_sources(&vars, &[]).unwrap();
+        assert_eq!(
+            settings.tracing.traces_endpoint().as_deref(),
+            Some("http://collector:4318/v1/traces")
+        );
+    }
+
+    #[test]
+    fn smtp_needs_no_postmark_token() {
+        let mut vars = get_vars();
+        vars.remove(env::POSTMARK_AUTH_TOKEN_ENV_VAR);
+        vars.insert(env::EMAIL_PROVIDER_ENV_VAR.to_owned(), "smtp".to_owned());
+        vars.insert(env::SMTP_HOST_ENV_VAR.to_owned(), "mail.example".to_owned());
+        vars.insert(env::SMTP_TLS_ENV_VAR.to_owned(), "tls".to_owned());
+        let settings = Settings::from_sources(&vars, &[]).unwrap();
+        assert_eq!(settings.email_client.provider, EmailProvider::Smtp);
+        assert_eq!(settings.smtp.tls, SmtpTls::Tls);
+        assert_eq!(settings.smtp.port, None);
+
+        vars.insert(env::SMTP_USERNAME_ENV_VAR.to_owned(), "user".to_owned());
+        let error = Settings::from_sources(&vars, &[]).unwrap_err().to_string();
+        assert!(
+            error.contains("smtp.username and smtp.password"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_smtp_tls_parse() {
+        assert_eq!(SmtpTls::parse("STARTTLS"), Ok(SmtpTls::StartTls));
+        assert_eq!(SmtpTls::parse(" tls "), Ok(SmtpTls::Tls));
+        assert_eq!(SmtpTls::parse("none"), Ok(SmtpTls::None));
+        assert!(SmtpTls::parse("ssl").is_err());
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let args = get_args(&[
+            "--application.address",
+            "nowhere",
+            "--email_client.sender",
+            "not-an-email",
+        ]);
+        let Err(SettingsError::Invalid(problems)) = Settings::from_sources(&HashMap::new(), &args)
+        else {
+            panic!("expected invalid settings");
+        };
+        let message = problems.join("\n");
+        assert!(message.contains("application.address"));
+        assert!(message.contains("database.url must be set, e.g. with DATABASE_URL"));
+        assert!(message.contains("jwt.secret"));
+        assert!(message.contains("email_client.sender"));
+        assert!(message.contains("email_client.auth_token"));
+    }
+
+    #[test]
+    fn bad_values_name_their_origin() {
+        let mut vars = get_vars();
+        vars.insert(env::TOKEN_TTL_SECONDS_ENV_VAR.to_owned(), "ten".to_owned());
+        let error = Settings::from_sources(&vars, &[]).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "invalid value for jwt.token_ttl_seconds from TOKEN_TTL_SECONDS: `ten` is not a valid number"
+        );
+
+        let args = get_args(&["--jwt.nope", "1"]);
+        let error = Settings::from_sources(&get_vars(), &args)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "invalid value for jwt.nope from --jwt.nope: unknown setting"
+        );
+
+        let args = get_args(&["--totp.skew"]);
+        assert!(matches!(
+            Settings::from_sources(&get_vars(), &args),
+            Err(SettingsError::Flag(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_keys_in_the_file_are_refused() {
+        let path = write_config("[jwt]\ntoken_ttl = 300\n");
+        let args = get_args(&["--config", path.to_str().unwrap()]);
+        let error = Settings::from_sources(&get_vars(), &args)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("unknown field `token_ttl`"), "{}", error);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn weak_password_hashing_is_refused() {
+        let mut settings = Settings::from_sources(&get_vars(), &[]).unwrap();
+        settings.password_hashing.iterations = 0;
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::watch,
+};
+
+// Tells the server to stop, and whoever asks that it is stopping. Clones share the
+// state, so that a signal handler or a test can stop the server it was taken from.
+#[derive(Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+}
+
+impl ShutdownHandle {
+    // Fail readiness, then stop accepting connections once the pre-stop delay is over,
+    // and let the requests in flight finish.
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    // Resolves once `shutdown` is called, right away if it already was.
+    pub async fn wait(&self) {
+        let mut draining = self.0.subscribe();
+        // The sender lives as long as `self`, so waiting cannot fail.
+        let _ = draining.wait_for(|draining| *draining).await;
+    }
+}
+
+// Shut the server down on SIGTERM, sent by orchestrators before killing a process,
+// or on SIGINT, sent by Ctrl-C.
+#[tracing::instrument(name = "Shut down on SIGTERM or SIGINT", skip_all)]
+pub async fn shutdown_on_signal(shutdown: ShutdownHandle) -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        _ = terminate.recv() => tracing::info!("received SIGTERM"),
+        _ = interrupt.recv() => tracing::info!("received SIGINT"),
+    }
+    shutdown.shutdown();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_clones_share_the_shutdown() {
+        let shutdown = ShutdownHandle::default();
+        let handle = shutdown.clone();
+        assert!(!handle.is_draining());
+
+        let waiting = tokio::spawn(async move { handle.wait().await });
+        shutdown.shutdown();
+
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("Still waiting after the shutdown")
+            .unwrap();
+        assert!(shutdown.is_draining());
+    }
+
+    #[tokio::test]
+    async fn test_wait_returns_once_shut_down() {
+        let shutdown = ShutdownHandle::default();
+        shutdown.shutdown();
+
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait())
+            .await
+            .expect("Still waiting after the shutdown");
+    }
+}
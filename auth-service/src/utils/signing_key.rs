@@ -0,0 +1,450 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use color_eyre::eyre::{Context, Result, eyre};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use ring::{
+    digest,
+    rsa::PublicKeyComponents,
+    signature::{Ed25519KeyPair, KeyPair, RsaKeyPair},
+};
+use secrecy::{ExposeSecret, Secret};
+
+// Key used to sign JWT auth tokens. Every token carries its `kid` in the header.
+#[derive(Clone)]
+pub struct SigningKey {
+    encoding_key: EncodingKey,
+    verification_key: VerificationKey,
+}
+
+impl SigningKey {
+    // Load an RSA (RS256) or Ed25519 (EdDSA) private key from a PEM file.
+    // Without an explicit `kid`, the RFC 7638 thumbprint of the public key is used.
+    pub fn from_pem(pem: &[u8], kid: Option<String>) -> Result<Self> {
+        let parsed = pem::parse(pem).wrap_err("failed to parse PEM signing key")?;
+
+        let (encoding_key, params) = match parsed.tag() {
+            // PKCS#1, only used for RSA keys.
+            "RSA PRIVATE KEY" => {
+                let key_pair = RsaKeyPair::from_der(parsed.contents())
+                    .map_err(|e| eyre!("invalid RSA private key: {}", e))?;
+                rsa_key(pem, &key_pair)?
+            }
+            // PKCS#8, may hold either kind of key.
+            "PRIVATE KEY" => {
+                if let Ok(key_pair) = RsaKeyPair::from_pkcs8(parsed.contents()) {
+                    rsa_key(pem, &key_pair)?
+                } else if let Ok(key_pair) =
+                    Ed25519KeyPair::from_pkcs8_maybe_unchecked(parsed.contents())
+                {
+                    ed25519_key(pem, &key_pair)?
+                } else {
+                    return Err(eyre!("signing key must be an RSA or Ed25519 private key"));
+                }
+            }
+            tag => return Err(eyre!("unsupported PEM block for a signing key: {}", tag)),
+        };
+
+        Ok(Self {
+            encoding_key,
+            verification_key: VerificationKey::from_params(params, kid)?,
+        })
+    }
+
+    // Shared HMAC secret (HS256). It has no public half, so nothing is published for it.
+    pub fn from_secret(secret: &Secret<String>, kid: String) -> Self {
+        let secret = secret.expose_secret().as_bytes();
+        Self {
+            encoding_key: EncodingKey::from_secret(secret),
+            verification_key: VerificationKey {
+                kid,
+                algorithm: Algorithm::HS256,
+                decoding_key: DecodingKey::from_secret(secret),
+                jwk: None,
+            },
+        }
+    }
+
+    pub fn kid(&self) -> &str {
+        self.verification_key.kid()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.verification_key.algorithm()
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn verification_key(&self) -> &VerificationKey {
+        &self.verification_key
+    }
+
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.verification_key.jwk()
+    }
+
+    // Header for tokens signed with this key.
+    pub fn header(&self) -> Header {
+        Header {
+            kid: Some(self.kid().to_owned()),
+            ..Header::new(self.algorithm())
+        }
+    }
+}
+
+// Key that verifies the tokens signed with a signing key, without being able to sign.
+#[derive(Clone)]
+pub struct VerificationKey {
+    kid: String,
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    // Public half of an asymmetric key, published on the JWKS route.
+    jwk: Option<Jwk>,
+}
+
+impl VerificationKey {
+    // Load an RSA or Ed25519 public key from a PEM file, as SPKI (`openssl pkey -pubout`),
+    // or PKCS#1 for RSA. A private key is accepted too, and only its public half kept.
+    // Without an explicit `kid`, the thumbprint is used, as for signing keys.
+    pub fn from_pem(pem: &[u8], kid: Option<String>) -> Result<Self> {
+        let parsed = pem::parse(pem).wrap_err("failed to parse PEM verification key")?;
+        let params = match parsed.tag() {
+            "PUBLIC KEY" => spki_params(parsed.contents())?,
+            "RSA PUBLIC KEY" => rsa_public_params(parsed.contents())?,
+            "PRIVATE KEY" | "RSA PRIVATE KEY" => {
+                return Ok(SigningKey::from_pem(pem, kid)?.verification_key);
+            }
+            tag => return Err(eyre!("unsupported PEM block for a public key: {}", tag)),
+        };
+        Self::from_params(params, kid)
+    }
+
+    // Load a public key from a JWK, e.g. as published on the JWKS route before a
+    // rotation. Its `kid` is kept, so it also verifies tokens of a key with an explicit id.
+    pub fn from_jwk(json: &[u8]) -> Result<Self> {
+        let jwk: Jwk = serde_json::from_slice(json).wrap_err("failed to parse JWK")?;
+        Self::from_params(jwk.algorithm, jwk.common.key_id)
+    }
+
+    fn from_params(params: AlgorithmParameters, kid: Option<String>) -> Result<Self> {
+        let algorithm = match &params {
+            AlgorithmParameters::RSA(_) => Algorithm::RS256,
+            AlgorithmParameters::OctetKeyPair(okp) if okp.curve == EllipticCurve::Ed25519 => {
+                Algorithm::EdDSA
+            }
+            _ => return Err(eyre!("verification key must be an RSA or Ed25519 key")),
+        };
+        let kid = kid.unwrap_or_else(|| thumbprint(&params));
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm(algorithm)),
+                key_id: Some(kid.clone()),
+                ..CommonParameters::default()
+            },
+            algorithm: params,
+        };
+        let decoding_key =
+            DecodingKey::from_jwk(&jwk).wrap_err("failed to build decoding key from JWK")?;
+
+        Ok(Self {
+            kid,
+            algorithm,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+}
+
+fn rsa_key(pem: &[u8], key_pair: &RsaKeyPair) -> Result<(EncodingKey, AlgorithmParameters)> {
+    let encoding_key = EncodingKey::from_rsa_pem(pem).wrap_err("invalid RSA private key")?;
+    let public = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+    Ok((encoding_key, rsa_params(&public.n, &public.e)))
+}
+
+fn ed25519_key(
+    pem: &[u8],
+    key_pair: &Ed25519KeyPair,
+) -> Result<(EncodingKey, AlgorithmParameters)> {
+    let encoding_key = EncodingKey::from_ed_pem(pem).wrap_err("invalid Ed25519 private key")?;
+    Ok((encoding_key, ed25519_params(key_pair.public_key().as_ref())))
+}
+
+// Big-endian integers, without the leading zeros DER may add.
+fn rsa_params(n: &[u8], e: &[u8]) -> AlgorithmParameters {
+    let unsigned = |bytes: &[u8]| {
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+        URL_SAFE_NO_PAD.encode(&bytes[start..])
+    };
+    AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: unsigned(n),
+        e: unsigned(e),
+    })
+}
+
+fn ed25519_params(public_key: &[u8]) -> AlgorithmParameters {
+    AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(public_key),
+    })
+}
+
+// DER object identifiers of the key types, as found in SubjectPublicKeyInfo.
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+
+// SubjectPublicKeyInfo: SEQUENCE { SEQUENCE { OID, parameters }, BIT STRING }.
+fn spki_params(der: &[u8]) -> Result<AlgorithmParameters> {
+    let (spki, _) = der_element(der, DER_SEQUENCE)?;
+    let (algorithm, rest) = der_element(spki, DER_SEQUENCE)?;
+    let (oid, _) = der_element(algorithm, DER_OID)?;
+    let (bits, _) = der_element(rest, DER_BIT_STRING)?;
+    // The first byte counts the unused bits of the last one, none for keys.
+    let public_key = match bits.split_first() {
+        Some((0, public_key)) => public_key,
+        _ => return Err(eyre!("invalid public key bit string")),
+    };
+
+    match oid {
+        RSA_ENCRYPTION_OID => rsa_public_params(public_key),
+        ED25519_OID if public_key.len() == 32 => Ok(ed25519_params(public_key)),
+        _ => Err(eyre!("public key must be an RSA or Ed25519 key")),
+    }
+}
+
+// PKCS#1 RSAPublicKey: SEQUENCE { INTEGER n, INTEGER e }.
+fn rsa_public_params(der: &[u8]) -> Result<AlgorithmParameters> {
+    let (key, _) = der_element(der, DER_SEQUENCE)?;
+    let (n, rest) = der_element(key, DER_INTEGER)?;
+    let (e, _) = der_element(rest, DER_INTEGER)?;
+    Ok(rsa_params(n, e))
+}
+
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OID: u8 = 0x06;
+const DER_SEQUENCE: u8 = 0x30;
+
+// Contents of the DER element at the start of `der`, which must have this tag, and
+// what follows it.
+fn der_element(der: &[u8], tag: u8) -> Result<(&[u8], &[u8])> {
+    let invalid = || eyre!("invalid DER public key");
+    let (&found, rest) = der.split_first().ok_or_else(invalid)?;
+    if found != tag {
+        return Err(invalid());
+    }
+    let (&length, rest) = rest.split_first().ok_or_else(invalid)?;
+    // Lengths from 128 bytes on are big-endian, in as many bytes as the low bits say.
+    let (length, rest) = match length {
+        0..=0x7f => (length as usize, rest),
+        0x81..=0x84 => {
+            let size = (length & 0x7f) as usize;
+            let bytes = rest.get(..size).ok_or_else(invalid)?;
+            let length = bytes.iter().fold(0, |acc, &b| (acc << 8) | b as usize);
+            (length, &rest[size..])
+        }
+        _ => return Err(invalid()),
+    };
+    let contents = rest.get(..length).ok_or_else(invalid)?;
+    Ok((contents, &rest[length..]))
+}
+
+fn key_algorithm(algorithm: Algorithm) -> KeyAlgorithm {
+    match algorithm {
+        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+        _ => KeyAlgorithm::RS256,
+    }
+}
+
+// RFC 7638: SHA-256 over the required members of the public key, in lexicographic order.
+fn thumbprint(params: &AlgorithmParameters) -> String {
+    let canonical = match params {
+        AlgorithmParameters::RSA(rsa) => {
+            format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n)
+        }
+        AlgorithmParameters::OctetKeyPair(okp) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x)
+        }
+        _ => unreachable!("only RSA and Ed25519 keys are supported"),
+    };
+    URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, canonical.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{Validation, decode, decode_header, encode};
+    use serde::{Deserialize, Serialize};
+
+    const RSA_PKCS8: &[u8] = include_bytes!("../../tests/fixtures/jwt_rsa_private.pem");
+    const RSA_PKCS1: &[u8] = include_bytes!("../../tests/fixtures/jwt_rsa_pkcs1_private.pem");
+    const ED25519: &[u8] = include_bytes!("../../tests/fixtures/jwt_ed25519_private.pem");
+    const EC: &[u8] = include_bytes!("../../tests/fixtures/jwt_ec_private.pem");
+    const RSA_PUBLIC: &[u8] = include_bytes!("../../tests/fixtures/jwt_rsa_public.pem");
+    const RSA_PKCS1_PUBLIC: &[u8] = include_bytes!("../../tests/fixtures/jwt_rsa_pkcs1_public.pem");
+    const ED25519_PUBLIC: &[u8] = include_bytes!("../../tests/fixtures/jwt_ed25519_public.pem");
+    const EC_PUBLIC: &[u8] = include_bytes!("../../tests/fixtures/jwt_ec_public.pem");
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn round_trip(key: &SigningKey) -> TestClaims {
+        verify_with(key, key.verification_key())
+    }
+
+    // Sign a token with `key`, and decode it with `verification_key`.
+    fn verify_with(key: &SigningKey, verification_key: &VerificationKey) -> TestClaims {
+        let claims = TestClaims {
+            sub: "test@example.com".to_owned(),
+            exp: 10_000_000_000,
+        };
+        let token = encode(&key.header(), &claims, key.encoding_key()).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.kid.as_deref(), Some(verification_key.kid()));
+        assert_eq!(header.alg, verification_key.algorithm());
+
+        decode::<TestClaims>(
+            &token,
+            verification_key.decoding_key(),
+            &Validation::new(verification_key.algorithm()),
+        )
+        .unwrap()
+        .claims
+    }
+
+    #[test]
+    fn rsa_pkcs8_key_signs_with_rs256() {
+        let key = SigningKey::from_pem(RSA_PKCS8, None).unwrap();
+        assert_eq!(key.algorithm(), Algorithm::RS256);
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+
+        let jwk = key.jwk().unwrap();
+        assert_eq!(jwk.common.key_id.as_deref(), Some(key.kid()));
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::RSA(_)));
+    }
+
+    #[test]
+    fn rsa_pkcs1_key_is_the_same_key_as_pkcs8() {
+        let pkcs8 = SigningKey::from_pem(RSA_PKCS8, None).unwrap();
+        let pkcs1 = SigningKey::from_pem(RSA_PKCS1, None).unwrap();
+        assert_eq!(pkcs1.kid(), pkcs8.kid());
+        assert_eq!(pkcs1.jwk(), pkcs8.jwk());
+    }
+
+    #[test]
+    fn ed25519_key_signs_with_eddsa() {
+        let key = SigningKey::from_pem(ED25519, None).unwrap();
+        assert_eq!(key.algorithm(), Algorithm::EdDSA);
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+        assert!(matches!(
+            key.jwk().unwrap().algorithm,
+            AlgorithmParameters::OctetKeyPair(_)
+        ));
+    }
+
+    #[test]
+    fn explicit_kid_is_used() {
+        let key = SigningKey::from_pem(ED25519, Some("2026-10".to_owned())).unwrap();
+        assert_eq!(key.kid(), "2026-10");
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+    }
+
+    #[test]
+    fn secret_key_signs_with_hs256_and_is_not_published() {
+        let key = SigningKey::from_secret(&Secret::new("secret".to_owned()), "hmac".to_owned());
+        assert_eq!(key.algorithm(), Algorithm::HS256);
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+        assert!(key.jwk().is_none());
+    }
+
+    #[test]
+    fn unsupported_or_malformed_keys_are_rejected() {
+        assert!(SigningKey::from_pem(EC, None).is_err());
+        assert!(SigningKey::from_pem(b"not a pem file", None).is_err());
+    }
+
+    #[test]
+    fn public_key_verifies_the_tokens_of_its_private_key() {
+        for (private, public) in [
+            (RSA_PKCS8, RSA_PUBLIC),
+            (RSA_PKCS8, RSA_PKCS1_PUBLIC),
+            (ED25519, ED25519_PUBLIC),
+        ] {
+            let key = SigningKey::from_pem(private, None).unwrap();
+            let verification_key = VerificationKey::from_pem(public, None).unwrap();
+            assert_eq!(verification_key.kid(), key.kid());
+            assert_eq!(verification_key.jwk(), key.jwk());
+            assert_eq!(verify_with(&key, &verification_key).sub, "test@example.com");
+        }
+    }
+
+    #[test]
+    fn private_key_file_verifies_with_its_public_half() {
+        let key = SigningKey::from_pem(ED25519, None).unwrap();
+        let verification_key = VerificationKey::from_pem(ED25519, None).unwrap();
+        assert_eq!(verification_key.jwk(), key.jwk());
+    }
+
+    #[test]
+    fn jwk_keeps_its_kid() {
+        let key = SigningKey::from_pem(RSA_PKCS8, Some("2026-10".to_owned())).unwrap();
+        let json = serde_json::to_vec(key.jwk().unwrap()).unwrap();
+
+        let verification_key = VerificationKey::from_jwk(&json).unwrap();
+        assert_eq!(verification_key.kid(), "2026-10");
+        assert_eq!(verify_with(&key, &verification_key).sub, "test@example.com");
+    }
+
+    #[test]
+    fn unsupported_or_malformed_public_keys_are_rejected() {
+        assert!(VerificationKey::from_pem(EC_PUBLIC, None).is_err());
+        assert!(VerificationKey::from_pem(EC, None).is_err());
+        assert!(VerificationKey::from_jwk(b"{}").is_err());
+
+        let mut truncated = pem::parse(RSA_PUBLIC).unwrap().into_contents();
+        truncated.truncate(100);
+        let truncated = pem::encode(&pem::Pem::new("PUBLIC KEY", truncated));
+        assert!(VerificationKey::from_pem(truncated.as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn thumbprint_matches_rfc_7638_example() {
+        let params = AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw".to_owned(),
+            e: "AQAB".to_owned(),
+        });
+        assert_eq!(
+            thumbprint(&params),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+}
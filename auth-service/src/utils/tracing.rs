@@ -1,35 +1,163 @@
-use axum::{body::Body, extract::Request, response::Response};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
 use color_eyre::eyre::Result;
+use opentelemetry::{propagation::TextMapPropagator, trace::TracerProvider as _};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{SdkTracerProvider, Tracer},
+    Resource,
+};
 use std::time::Duration;
-use tracing::{Level, Span};
+use tracing::{Level, Span, Subscriber};
 use tracing_error::ErrorLayer;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
 use tracing_subscriber;
 use tracing_subscriber::prelude::*;
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{fmt, fmt::MakeWriter, registry::LookupSpan, EnvFilter};
 
-pub fn init_tracing() -> Result<()> {
-    let fmt_layer = fmt::layer().compact();
+use super::{
+    constants::REQUEST_ID_HEADER,
+    log_format::{CompactFields, JsonFields, JsonFormat, LogFormat},
+    metrics::{self, RouteInfo},
+    settings::TracingSettings,
+};
+
+// Longest request id accepted from a client. Longer ones are replaced by a new id.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+// Log to stdout in the configured format, and export spans to the OpenTelemetry
+// collector if one is configured. The tracer provider returned must be shut down on
+// exit, to export the last spans.
+pub fn init_tracing(settings: &TracingSettings) -> Result<Option<SdkTracerProvider>> {
+    let (compact_layer, json_layer) = match settings.log_format {
+        LogFormat::Compact => (Some(compact_log_layer(std::io::stdout)), None),
+        LogFormat::Json => (None, Some(json_log_layer(std::io::stdout))),
+    };
     let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
+    let tracer_provider = settings
+        .traces_endpoint()
+        .map(|endpoint| init_tracer_provider(&endpoint, &settings.service_name))
+        .transpose()?;
 
     tracing_subscriber::registry()
         .with(filter_layer)
-        .with(fmt_layer)
+        .with(compact_layer)
+        .with(json_layer)
+        .with(tracer_provider.as_ref().map(otel_layer))
         .with(ErrorLayer::default())
         .init();
 
-    Ok(())
+    Ok(tracer_provider)
 }
 
+// Write events to `make_writer` as JSON, one per line.
+pub fn json_log_layer<S, W>(make_writer: W) -> fmt::Layer<S, JsonFields, JsonFormat, W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    fmt::layer()
+        .fmt_fields(JsonFields)
+        .event_format(JsonFormat)
+        .with_writer(make_writer)
+}
+
+// Write events to `make_writer` as short lines, redacting the same fields as JSON logs.
+pub fn compact_log_layer<S, W>(
+    make_writer: W,
+) -> fmt::Layer<S, CompactFields, fmt::format::Format<fmt::format::Compact>, W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    fmt::layer()
+        .compact()
+        .fmt_fields(CompactFields)
+        .with_writer(make_writer)
+}
+
+// Export spans in batches to `endpoint`, the OTLP/HTTP traces URL of a collector.
+pub fn init_tracer_provider(endpoint: &str, service_name: &str) -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let resource = Resource::builder()
+        .with_service_name(service_name.to_owned())
+        .build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+// Turn spans into OpenTelemetry spans, exported by `tracer_provider`.
+pub fn otel_layer<S>(tracer_provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("auth-service"))
+}
+
+// Identifies a request in the logs and in the audit log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+// Give every request an id: the one in its X-Request-Id header, so that a request
+// can be followed from the service that made it, or a new one. The id is echoed in
+// the response. It must run before `make_span_with_request_id`.
+pub async fn set_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = get_request_id(request.headers())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let header_value = HeaderValue::from_str(&request_id);
+    request.extensions_mut().insert(RequestId(request_id));
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = header_value {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+// The request id given by the client, if it is short and printable.
+fn get_request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .filter(|id| id.chars().all(|c| c.is_ascii_graphic()))
+        .map(str::to_owned)
+}
+
+// The span of a request continues the trace of the caller, when its `traceparent`
+// and `tracestate` headers carry one.
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
-    let request_id = uuid::Uuid::new_v4();
-    tracing::span!(
+    let request_id = match request.extensions().get::<RequestId>() {
+        Some(RequestId(id)) => id.clone(),
+        None => uuid::Uuid::new_v4().to_string(),
+    };
+    let span = tracing::span!(
         Level::INFO,
         "[REQUEST]",
         method = tracing::field::display(request.method()),
-        uri = tracing::field::display(request.uri()),
+        // Without the query, which may hold emails.
+        path = tracing::field::display(request.uri().path()),
         version = tracing::field::debug(request.version()),
         request_id = tracing::field::display(request_id),
-    )
+    );
+
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    // Fails when spans are not exported, and there is no trace to continue.
+    let _ = span.set_parent(parent);
+    span
 }
 
 // Logs an event indicating the start of a request.
@@ -39,11 +167,16 @@ pub fn on_request(_request: &Request<Body>, _span: &Span) {
 
 // Logs an event indicating the end of a request, including its latency and status code.
 // If the status code indicates an error (4xx or 5xx), it logs at the ERROR level.
+// The latency is also recorded in the metrics, by route.
 pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
     let status = response.status();
     let status_code = status.as_u16();
     let status_code_class = status_code / 100;
 
+    if let Some(info) = response.extensions().get::<RouteInfo>() {
+        metrics::observe_request(&info.method, info.route.as_deref(), status_code, latency);
+    }
+
     match status_code_class {
         4..=5 => {
             tracing::event!(
@@ -0,0 +1,376 @@
+use crate::helpers::{
+    EMAIL_CHANGE_EMAIL_SUBJECT, EMAIL_CHANGED_EMAIL_SUBJECT, TestApp, get_link_token,
+    get_random_email, get_random_password,
+};
+use auth_service::{
+    domain::{ADMIN_ROLE, AuditActor, AuditEventKind, AuditOutcome, Email, Role, TwoFAMethod},
+    routes::{AccountExport, SessionResponse},
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+};
+use secrecy::Secret;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+// Log in, and return the JWT set in the cookie jar.
+async fn login(app: &TestApp, email: &str, password: &str) -> String {
+    let body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned()
+}
+
+async fn verify_token(app: &TestApp, token: &str) -> u16 {
+    app.post_verify_token(&serde_json::json!({ "token": token }))
+        .await
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let body = serde_json::json!({
+        "currentPassword": get_random_password(),
+        "newPassword": get_random_password(),
+    });
+    assert_eq!(app.post_change_password(&body).await.status().as_u16(), 400);
+
+    let body = serde_json::json!({
+        "password": get_random_password(),
+        "newEmail": get_random_email(),
+    });
+    assert_eq!(app.post_change_email(&body).await.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_change_password_and_log_out_other_sessions() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let old_password = get_random_password();
+    let new_password = get_random_password();
+    assert!(app.create_account(&email, &old_password, false).await);
+
+    let old_token = login(&app, &email, &old_password).await;
+    let body = serde_json::json!({
+        "currentPassword": old_password,
+        "newPassword": new_password,
+    });
+    let response = app.post_change_password(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    // This session goes on with its new token, every other one is logged out.
+    assert_eq!(verify_token(&app, &new_token).await, 200);
+    assert_eq!(verify_token(&app, &old_token).await, 401);
+
+    let body = serde_json::json!({ "email": email, "password": old_password });
+    assert_eq!(app.post_login(&body).await.status().as_u16(), 401);
+    login(&app, &email, &new_password).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_password_change_with_incorrect_or_invalid_passwords() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    login(&app, &email, &password).await;
+
+    let body = serde_json::json!({
+        "currentPassword": get_random_password(),
+        "newPassword": get_random_password(),
+    });
+    assert_eq!(app.post_change_password(&body).await.status().as_u16(), 401);
+
+    let body = serde_json::json!({ "currentPassword": password, "newPassword": "short" });
+    assert_eq!(app.post_change_password(&body).await.status().as_u16(), 400);
+
+    let body = serde_json::json!({ "newPassword": get_random_password() });
+    assert_eq!(app.post_change_password(&body).await.status().as_u16(), 422);
+
+    // The password is unchanged.
+    login(&app, &email, &password).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_change_email_once_the_new_address_is_confirmed() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    let new_email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    let old_token = login(&app, &email, &password).await;
+
+    let body = serde_json::json!({ "password": password, "newEmail": new_email });
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Nothing changes until the new address is confirmed.
+    login(&app, &email, &password).await;
+
+    let emails = app.wait_for_emails(EMAIL_CHANGE_EMAIL_SUBJECT, 1).await;
+    let token = get_link_token(&emails[0]);
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&body).await.status().as_u16(), 401);
+    login(&app, &new_email, &password).await;
+    assert_eq!(verify_token(&app, &old_token).await, 401);
+
+    // The old address is told about the change.
+    let emails = app.emails_sent(EMAIL_CHANGED_EMAIL_SUBJECT).await;
+    assert_eq!(emails.len(), 1);
+    assert!(emails[0].contains(&new_email));
+
+    // The link only works once.
+    let response = app
+        .post_confirm_email_change(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_email_change_to_an_existing_account() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    let other_email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    assert!(app.create_account(&other_email, &password, false).await);
+    login(&app, &email, &password).await;
+
+    let body = serde_json::json!({ "password": password, "newEmail": other_email });
+    assert_eq!(app.post_change_email(&body).await.status().as_u16(), 409);
+
+    let body =
+        serde_json::json!({ "password": get_random_password(), "newEmail": get_random_email() });
+    assert_eq!(app.post_change_email(&body).await.status().as_u16(), 401);
+
+    let body = serde_json::json!({ "password": password, "newEmail": "not-an-email" });
+    assert_eq!(app.post_change_email(&body).await.status().as_u16(), 400);
+
+    assert!(app.emails_sent(EMAIL_CHANGE_EMAIL_SUBJECT).await.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_email_change_token() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+
+    let auth_token = app.authenticate_user(&email).await;
+    for token in [auth_token, "invalid".to_owned()] {
+        let response = app
+            .post_confirm_email_change(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_export_account_with_secrets_redacted() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, true).await);
+    let user = app
+        .user_store
+        .get_user(Email::parse(Secret::new(email.clone())).unwrap())
+        .await
+        .unwrap();
+    app.user_store
+        .add_role(user.id, Role::parse(ADMIN_ROLE).unwrap())
+        .await
+        .unwrap();
+    app.authenticate_user(&email).await;
+
+    let response = app.get_account_export().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(!body.contains(&password));
+
+    let export: AccountExport = serde_json::from_str(&body).unwrap();
+    // The signup is the only audited request of the account so far.
+    let audit_events = export.audit_events.clone();
+    assert_eq!(audit_events.len(), 1);
+    assert_eq!(audit_events[0].kind, AuditEventKind::Signup);
+    assert_eq!(audit_events[0].outcome, AuditOutcome::Success);
+    assert_eq!(audit_events[0].actor, AuditActor::Anonymous);
+    let sessions = app
+        .get_sessions()
+        .await
+        .json::<Vec<SessionResponse>>()
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].ip, "127.0.0.1");
+    assert!(sessions[0].current);
+    assert_eq!(
+        export,
+        AccountExport {
+            id: user.id.to_string(),
+            email,
+            password_hash: "[REDACTED]".to_owned(),
+            verified: true,
+            two_fa_method: TwoFAMethod::Email,
+            totp_secret: None,
+            pending_2fa_login: false,
+            locked: false,
+            roles: vec![ADMIN_ROLE.to_owned()],
+            sessions,
+            audit_events,
+        }
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_export_without_jwt_cookie() {
+    let mut app = TestApp::new().await;
+
+    assert_eq!(app.get_account_export().await.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_delete_account_and_log_it_out() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    let body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&body).await;
+    let cookie = |name: &str| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .expect("No cookie found")
+            .value()
+            .to_owned()
+    };
+    let (token, refresh_token) = (cookie(JWT_COOKIE_NAME), cookie(REFRESH_TOKEN_COOKIE_NAME));
+
+    let body = serde_json::json!({ "password": get_random_password() });
+    assert_eq!(app.delete_account(&body).await.status().as_u16(), 401);
+
+    let body = serde_json::json!({ "password": password });
+    assert_eq!(app.delete_account(&body).await.status().as_u16(), 204);
+
+    assert_eq!(verify_token(&app, &token).await, 401);
+    let body = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&body).await.status().as_u16(), 401);
+    app.set_cookie(REFRESH_TOKEN_COOKIE_NAME, &refresh_token);
+    assert_eq!(app.post_refresh().await.status().as_u16(), 401);
+
+    // The email can be used for a new account.
+    assert!(app.create_account(&email, &password, false).await);
+
+    app.clean_up().await;
+}
+
+// Re-entering the password shares the throttling of logins, so a stolen session
+// cannot be used to guess it.
+#[tokio::test]
+async fn should_return_429_after_repeated_incorrect_passwords() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    app.authenticate_user(&email).await;
+
+    let body = serde_json::json!({ "password": get_random_password() });
+    for _ in 0..5 {
+        assert_eq!(app.delete_account(&body).await.status().as_u16(), 401);
+    }
+
+    let body = serde_json::json!({ "password": password });
+    let response = app.delete_account(&body).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+    let body = serde_json::json!({
+        "currentPassword": password,
+        "newPassword": get_random_password(),
+    });
+    assert_eq!(app.post_change_password(&body).await.status().as_u16(), 429);
+    let body = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&body).await.status().as_u16(), 429);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_clear_pending_2fa_login_when_deleting_account() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, true).await);
+
+    let body = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&body).await.status().as_u16(), 206);
+    app.authenticate_user(&email).await;
+    let response = app.get_account_export().await;
+    assert!(
+        response
+            .json::<AccountExport>()
+            .await
+            .unwrap()
+            .pending_2fa_login
+    );
+
+    let body = serde_json::json!({ "password": password });
+    assert_eq!(app.delete_account(&body).await.status().as_u16(), 204);
+
+    let email = Email::parse(Secret::new(email)).unwrap();
+    assert!(
+        app.two_fa_code_store
+            .get_code(&email)
+            .await
+            .is_err()
+    );
+
+    app.clean_up().await;
+}
@@ -0,0 +1,382 @@
+use crate::helpers::{
+    ADMIN_API_KEY, PASSWORD_RESET_EMAIL_SUBJECT, TestApp, VERIFICATION_EMAIL_SUBJECT,
+    get_link_token, get_random_email, get_random_password,
+};
+use auth_service::{
+    ErrorResponse,
+    routes::{AdminUser, AdminUserDetails, UserListResponse},
+    utils::constants::{ADMIN_API_KEY_HEADER, JWT_COOKIE_NAME},
+};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+// Create a verified account through the admin API, and return it.
+async fn create_user(app: &TestApp, email: &str, password: &str) -> AdminUser {
+    let body = serde_json::json!({
+        "email": email,
+        "password": password,
+        "verified": true,
+    });
+    let response = app.post_admin_user(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    response
+        .json()
+        .await
+        .expect("Could not deserialize response body to AdminUser")
+}
+
+// Log in from another client, so that the admin's cookies stay in place.
+async fn login_elsewhere(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_admin_users(&[]).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_user_is_not_an_admin() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+    app.authenticate_user(&email).await;
+
+    let response = app.get_admin_users(&[]).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.post_admin_lock(&app.get_user_id(&email).await).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_admin_api_key_is_wrong() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/users", &app.address))
+        .header(ADMIN_API_KEY_HEADER, "not-the-admin-key")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_the_configured_admin_api_key() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/users", &app.address))
+        .header(ADMIN_API_KEY_HEADER, ADMIN_API_KEY)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let list: UserListResponse = response.json().await.unwrap();
+    assert_eq!(list.total, 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_list_and_search_users_by_page() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    for _ in 0..3 {
+        let email = format!("listed.{}", get_random_email());
+        create_user(&app, &email, &get_random_password()).await;
+    }
+
+    let response = app
+        .get_admin_users(&[("search", "LISTED."), ("perPage", "2")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let first: UserListResponse = response.json().await.unwrap();
+    assert_eq!(first.total, 3);
+    assert_eq!(first.page, 1);
+    assert_eq!(first.users.len(), 2);
+
+    let response = app
+        .get_admin_users(&[("search", "listed."), ("perPage", "2"), ("page", "2")])
+        .await;
+    let second: UserListResponse = response.json().await.unwrap();
+    assert_eq!(second.users.len(), 1);
+    assert!(first.users[1].email < second.users[0].email);
+
+    // Without a search, the admin is listed too.
+    let response = app.get_admin_users(&[]).await;
+    let all: UserListResponse = response.json().await.unwrap();
+    assert_eq!(all.total, 4);
+    assert_eq!(all.per_page, 20);
+
+    for query in [[("page", "0")], [("perPage", "0")], [("perPage", "101")]] {
+        let response = app.get_admin_users(&query).await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_create_and_show_users() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    let email = get_random_email();
+    let body = serde_json::json!({
+        "email": email,
+        "password": get_random_password(),
+        "twoFAMethod": "email",
+    });
+
+    let response = app.post_admin_user(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: AdminUser = response.json().await.unwrap();
+    assert!(!created.verified);
+    assert!(!created.locked);
+
+    // Unverified accounts get the usual verification email.
+    let emails = app.wait_for_emails(VERIFICATION_EMAIL_SUBJECT, 2).await;
+    assert!(!get_link_token(emails.last().unwrap()).is_empty());
+
+    let response = app.get_admin_user(&created.id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let details: AdminUserDetails = response.json().await.unwrap();
+    assert_eq!(details.user, created);
+    assert!(details.roles.is_empty());
+
+    let response = app.post_admin_user(&body).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let invalid_inputs = [
+        serde_json::json!({ "email": "invalid", "password": get_random_password() }),
+        serde_json::json!({ "email": get_random_email(), "password": "short" }),
+        serde_json::json!({
+            "email": get_random_email(),
+            "password": get_random_password(),
+            "twoFAMethod": "totp",
+        }),
+    ];
+    for body in invalid_inputs {
+        let response = app.post_admin_user(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            body
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_for_unknown_users() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+
+    for id in [uuid::Uuid::new_v4().to_string(), "not-an-id".to_owned()] {
+        let response = app.get_admin_user(&id).await;
+        assert_eq!(response.status().as_u16(), 404);
+        let response = app.post_admin_lock(&id).await;
+        assert_eq!(response.status().as_u16(), 404);
+        let response = app.delete_admin_user(&id).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_lock_and_unlock_users() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    let user = create_user(&app, &email, &password).await;
+
+    let response = login_elsewhere(&app, &email, &password).await;
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.post_admin_lock(&user.id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.json::<AdminUser>().await.unwrap().locked);
+
+    // Locking logs the user out, and keeps them out.
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app.post_admin_unlock(&user.id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!response.json::<AdminUser>().await.unwrap().locked);
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_user_sessions() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    let user = create_user(&app, &email, &password).await;
+
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.delete_admin_user_sessions(&user.id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    // The account itself is untouched.
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_force_password_reset() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    let user = create_user(&app, &email, &password).await;
+
+    let response = app.post_admin_password_reset(&user.id).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let emails = app.wait_for_emails(PASSWORD_RESET_EMAIL_SUBJECT, 1).await;
+    let token = get_link_token(emails.last().unwrap());
+    let new_password = get_random_password();
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login_elsewhere(&app, &email, &new_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_set_user_2fa_method() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    let user = create_user(&app, &email, &password).await;
+
+    let response = app
+        .post_admin_two_fa(&user.id, &serde_json::json!({ "twoFAMethod": "email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let updated: AdminUser = response.json().await.unwrap();
+    assert_eq!(
+        updated.two_fa_method,
+        auth_service::domain::TwoFAMethod::Email
+    );
+
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    // TOTP needs an authenticator app the user enrolled.
+    let response = app
+        .post_admin_two_fa(&user.id, &serde_json::json!({ "twoFAMethod": "totp" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error: ErrorResponse = response.json().await.unwrap();
+    assert_eq!(error.error, "TOTP not enrolled");
+
+    let response = app
+        .post_admin_two_fa(&user.id, &serde_json::json!({ "twoFAMethod": "none" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_delete_users() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    let user = create_user(&app, &email, &password).await;
+
+    let response = app.delete_admin_user(&user.id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = app.get_admin_user(&user.id).await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,323 @@
+use crate::helpers::{ADMIN_API_KEY, TestApp, get_random_email, get_random_password};
+use auth_service::{
+    domain::{AuditActor, AuditEventKind, AuditOutcome},
+    routes::{AdminUser, AuditChainResponse, AuditLogResponse},
+    utils::constants::ADMIN_API_KEY_HEADER,
+};
+use secrecy::ExposeSecret;
+use sqlx::{Connection, Executor};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+// Log in from another client, so that the admin's cookies stay in place.
+async fn login_elsewhere(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_audit_log(app: &TestApp, query: &[(&str, &str)]) -> AuditLogResponse {
+    let response = app.get_admin_audit(query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json()
+        .await
+        .expect("Could not deserialize response body to AuditLogResponse")
+}
+
+async fn verify_audit_log(app: &TestApp) -> AuditChainResponse {
+    let response = app.get_admin_audit_verify().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json()
+        .await
+        .expect("Could not deserialize response body to AuditChainResponse")
+}
+
+#[tokio::test]
+async fn should_return_403_if_user_cannot_read_the_audit_log() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+    app.authenticate_user(&email).await;
+
+    let response = app.get_admin_audit(&[]).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_audit_verify().await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_signup_and_login_attempts() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+
+    let response = login_elsewhere(&app, &email, "wrong-password-123").await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = login_elsewhere(&app, &email, &password).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.login_as_admin().await;
+    let user_id = app.get_user_id(&email).await;
+    let log = get_audit_log(&app, &[("userId", &user_id)]).await;
+
+    let events: Vec<_> = log
+        .entries
+        .iter()
+        .map(|entry| (entry.event.kind, entry.event.outcome))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (AuditEventKind::Signup, AuditOutcome::Success),
+            (AuditEventKind::Login, AuditOutcome::Failure),
+            (AuditEventKind::Login, AuditOutcome::Success),
+        ]
+    );
+    let failure = &log.entries[1].event;
+    assert_eq!(failure.reason.as_deref(), Some("Incorrect credentials"));
+    for entry in &log.entries {
+        assert_eq!(entry.event.actor, AuditActor::Anonymous);
+        assert_eq!(
+            entry.event.user_id.map(|id| id.to_string()),
+            Some(user_id.clone())
+        );
+        assert!(entry.event.ip.is_some());
+        assert!(entry.event.request_id.is_some());
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_admin_actions() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    app.login_as_admin().await;
+    let body = serde_json::json!({
+        "email": get_random_email(),
+        "password": get_random_password(),
+        "verified": true,
+    });
+    let response = app.post_admin_user(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let user: AdminUser = response.json().await.unwrap();
+
+    let body = serde_json::json!({ "twoFAMethod": "email" });
+    let response = app.post_admin_two_fa(&user.id, &body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.post_admin_lock(&user.id).await.status().as_u16(), 200);
+    assert_eq!(app.post_admin_unlock(&user.id).await.status().as_u16(), 200);
+    let response = app.delete_admin_user_sessions(&user.id).await;
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app.post_admin_password_reset(&user.id).await;
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(app.delete_admin_user(&user.id).await.status().as_u16(), 204);
+    // Failed actions are recorded too.
+    assert_eq!(app.post_admin_lock(&user.id).await.status().as_u16(), 404);
+
+    let log = get_audit_log(&app, &[("userId", &user.id)]).await;
+    let events: Vec<_> = log
+        .entries
+        .iter()
+        .map(|entry| (entry.event.kind, entry.event.outcome))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (AuditEventKind::AdminUserCreation, AuditOutcome::Success),
+            (AuditEventKind::AdminTwoFAChange, AuditOutcome::Success),
+            (AuditEventKind::AdminLock, AuditOutcome::Success),
+            (AuditEventKind::AdminUnlock, AuditOutcome::Success),
+            (
+                AuditEventKind::AdminSessionRevocation,
+                AuditOutcome::Success
+            ),
+            (AuditEventKind::AdminPasswordReset, AuditOutcome::Success),
+            (AuditEventKind::AdminUserDeletion, AuditOutcome::Success),
+            (AuditEventKind::AdminLock, AuditOutcome::Failure),
+        ]
+    );
+    // The admin made every change, about the user.
+    let actor = log.entries[0].event.actor;
+    assert!(matches!(actor, AuditActor::User(id) if id.to_string() != user.id));
+    for entry in &log.entries {
+        assert_eq!(entry.event.actor, actor);
+        assert!(entry.event.ip.is_some());
+        assert!(entry.event.request_id.is_some());
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_admin_api_key_actions() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+    let user_id = app.get_user_id(&email).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/admin/users/{}/lock", &app.address, user_id))
+        .header(ADMIN_API_KEY_HEADER, ADMIN_API_KEY)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_rotate_signing_keys(Some(ADMIN_API_KEY)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/audit", &app.address))
+        .header(ADMIN_API_KEY_HEADER, ADMIN_API_KEY)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let log: AuditLogResponse = response.json().await.unwrap();
+    let events: Vec<_> = log
+        .entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.event.kind,
+                entry.event.actor,
+                entry.event.user_id.map(|id| id.to_string()),
+            )
+        })
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (
+                AuditEventKind::Signup,
+                AuditActor::Anonymous,
+                Some(user_id.clone())
+            ),
+            (
+                AuditEventKind::AdminLock,
+                AuditActor::AdminApiKey,
+                Some(user_id)
+            ),
+            (
+                AuditEventKind::SigningKeyRotation,
+                AuditActor::AdminApiKey,
+                None
+            ),
+        ]
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_filter_entries_by_time_range() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    for _ in 0..3 {
+        let response = login_elsewhere(&app, &email, "wrong-password-123").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    app.login_as_admin().await;
+    let user_id = app.get_user_id(&email).await;
+    let all = get_audit_log(&app, &[("userId", &user_id)]).await.entries;
+    assert_eq!(all.len(), 4);
+
+    let timestamp = all[1].event.timestamp.to_string();
+    let log = get_audit_log(
+        &app,
+        &[
+            ("userId", &user_id),
+            ("from", &timestamp),
+            ("to", &timestamp),
+        ],
+    )
+    .await;
+    assert!(!log.entries.is_empty());
+    assert!(
+        log.entries
+            .iter()
+            .all(|entry| entry.event.timestamp.to_string() == timestamp)
+    );
+
+    let log = get_audit_log(&app, &[("userId", &user_id), ("limit", "2")]).await;
+    assert_eq!(log.entries, all[..2]);
+
+    let response = app.get_admin_audit(&[("limit", "0")]).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.get_admin_audit(&[("userId", "nobody")]).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_detect_altered_entries() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+    let response = login_elsewhere(&app, &email, "wrong-password-123").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.login_as_admin().await;
+    let chain = verify_audit_log(&app).await;
+    assert!(chain.valid);
+    assert_eq!(chain.broken_at, None);
+    assert!(chain.entries >= 2);
+
+    // Rewrite history, as someone hiding a failed login would.
+    let url = format!(
+        "{}/{}",
+        app.settings.database.url.expose_secret(),
+        app.db_name
+    );
+    let mut connection = sqlx::PgConnection::connect(&url)
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute("UPDATE audit_log SET outcome = 'success', reason = NULL WHERE sequence = 2")
+        .await
+        .expect("Failed to alter the audit log");
+
+    let chain = verify_audit_log(&app).await;
+    assert!(!chain.valid);
+    assert_eq!(chain.broken_at, Some(2));
+
+    app.clean_up().await;
+}
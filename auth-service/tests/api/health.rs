@@ -0,0 +1,116 @@
+use crate::helpers::TestApp;
+use auth_service::routes::{HealthStatus, LivenessResponse, ReadinessResponse};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+// Address nothing listens on, once the listener is dropped.
+fn unreachable_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+async fn get_readiness(app: &TestApp) -> (u16, ReadinessResponse) {
+    let response = app.get_health_ready().await;
+    let status = response.status().as_u16();
+    let body = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to ReadinessResponse");
+    (status, body)
+}
+
+#[tokio::test]
+async fn should_return_200_if_alive() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health_live().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<LivenessResponse>()
+            .await
+            .expect("Could not deserialize response body to LivenessResponse"),
+        LivenessResponse {
+            status: HealthStatus::Up
+        }
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_if_every_dependency_is_up() {
+    let mut app = TestApp::new().await;
+
+    let (status, readiness) = get_readiness(&app).await;
+    assert_eq!(status, 200);
+    assert_eq!(readiness.status, HealthStatus::Up);
+    for name in [
+        "userStore",
+        "sessionStore",
+        "refreshTokenStore",
+        "auditSink",
+    ] {
+        assert_eq!(readiness.checks[name].status, HealthStatus::Up, "{}", name);
+        assert!(readiness.checks[name].critical);
+    }
+    // The email provider is only checked when configured.
+    assert!(!readiness.checks.contains_key("emailClient"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_if_redis_is_down() {
+    let mut app = TestApp::with_settings(|settings| {
+        settings.redis.host_name = unreachable_address();
+    })
+    .await;
+
+    let (status, readiness) = get_readiness(&app).await;
+    assert_eq!(status, 503);
+    assert_eq!(readiness.status, HealthStatus::Down);
+    assert_eq!(readiness.checks["sessionStore"].status, HealthStatus::Down);
+    assert_eq!(
+        readiness.checks["bannedTokenStore"].status,
+        HealthStatus::Down
+    );
+    assert_eq!(readiness.checks["userStore"].status, HealthStatus::Up);
+    // Rate limits fall back to memory, so the rate limit store alone does not make
+    // the service unready.
+    assert_eq!(
+        readiness.checks["rateLimitStore"].status,
+        HealthStatus::Down
+    );
+    assert!(!readiness.checks["rateLimitStore"].critical);
+
+    // Liveness does not depend on anything.
+    assert_eq!(app.get_health_live().await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_stay_ready_if_the_email_provider_is_down() {
+    let mut app = TestApp::with_settings(|settings| {
+        settings.health.check_email = true;
+    })
+    .await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (status, readiness) = get_readiness(&app).await;
+    assert_eq!(status, 200);
+    assert_eq!(readiness.status, HealthStatus::Degraded);
+    let email = &readiness.checks["emailClient"];
+    assert_eq!(email.status, HealthStatus::Down);
+    assert!(!email.critical);
+
+    app.clean_up().await;
+}
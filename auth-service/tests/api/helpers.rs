@@ -8,79 +8,137 @@ use sqlx::{
     Connection, Executor, PgPool,
     postgres::{PgConnectOptions, PgPoolOptions},
 };
+use std::collections::HashMap;
+use std::io;
 use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::{sync::RwLock, task::JoinHandle};
+use tokio_util::task::TaskTracker;
 use uuid::Uuid;
 use wiremock::MockServer;
 
 use auth_service::{
     Application,
-    app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType},
-    domain::email::Email,
+    app_state::{
+        AppState, BannedTokenStoreType, KeyRingType, RefreshTokenStoreType, SessionStoreType,
+        TwoFACodeStoreType, UserStoreType,
+    },
+    domain::{ADMIN_ROLE, ClientInfo, Role, Session, UserId, email::Email},
     get_postgres_pool, get_redis_client,
     services::{
-        PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore,
+        HashmapRateLimitStore, PostgresAuditSink, PostgresUserStore, RedisBannedTokenStore,
+        RedisPool, RedisRefreshTokenStore, RedisSessionStore, RedisTwoFACodeStore,
         postmark_email_client::PostmarkEmailClient,
     },
     utils::{
         self,
-        constants::{JWT_COOKIE_NAME, REDIS_HOST_NAME, test},
+        audit::AuditWriter,
+        constants::{
+            ADMIN_API_KEY_HEADER, JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME,
+            REFRESH_TOKEN_COOKIE_PATH,
+        },
+        key_ring::KeyRing,
+        settings::{EmailClientSettings, Settings},
+        shutdown::ShutdownHandle,
     },
 };
-use utils::constants::DATABASE_URL;
+
+// Admin API key of every test app.
+pub const ADMIN_API_KEY: &str = "test-admin-api-key";
 
 pub struct TestApp {
     pub address: String,
     pub cookie_jar: Arc<Jar>,
     pub http_client: reqwest::Client,
     pub email_server: MockServer,
+    pub user_store: UserStoreType,
     pub banned_tokens_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
+    pub key_ring: KeyRingType,
+    pub settings: Arc<Settings>,
+    // Stops the server, which `server` then returns from.
+    pub shutdown: ShutdownHandle,
+    pub server: JoinHandle<io::Result<()>>,
     pub db_name: String,
     pub clean_up_called: bool,
 }
 
 impl TestApp {
     pub async fn new() -> Self {
-        let pg_pool = configure_postgresql().await;
-        let banned_token_redis_conn = configure_redis();
-        let twofa_redis_conn = configure_redis();
+        Self::with_settings(|_| {}).await
+    }
+
+    // An app whose settings are changed first, e.g. to point at a broken dependency.
+    pub async fn with_settings(configure: impl FnOnce(&mut Settings)) -> Self {
+        let mut settings = get_settings();
+        configure(&mut settings);
+        let settings = Arc::new(settings);
+        let pg_pool = configure_postgresql(&settings).await;
+        let redis_pool = configure_redis(&settings);
 
         // Copy test DB name for cleanup.
         let connect_opts = pg_pool.connect_options();
         let db_name = connect_opts.get_database().expect("Missing database name");
 
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-        let banned_tokens_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(Arc::new(
-            RwLock::new(banned_token_redis_conn),
-        ))));
-        let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(Arc::new(
-            RwLock::new(twofa_redis_conn),
-        ))));
+        let audit_sink = Arc::new(PostgresAuditSink::new(pg_pool.clone()));
+        let hash_params = settings
+            .password_hashing
+            .params()
+            .expect("Invalid password hashing parameters");
+        let user_store = Arc::new(PostgresUserStore::new(pg_pool, hash_params));
+        let banned_tokens_store = Arc::new(RedisBannedTokenStore::new(
+            redis_pool.clone(),
+            settings.jwt.token_ttl_seconds,
+        ));
+        let two_fa_code_store = Arc::new(RedisTwoFACodeStore::new(redis_pool.clone()));
+        let refresh_token_store = Arc::new(RedisRefreshTokenStore::new(redis_pool.clone()));
+        let session_store = Arc::new(RedisSessionStore::new(redis_pool));
+
+        // Each app counts its own failures: tests share Redis and the client IP,
+        // so a shared store would throttle unrelated tests.
+        let rate_limit_store = Arc::new(HashmapRateLimitStore::default());
+
+        let key_ring = Arc::new(RwLock::new(
+            KeyRing::load(settings.jwt.key_source(), settings.jwt.token_ttl_seconds)
+                .expect("Failed to load JWT signing keys"),
+        ));
 
         let email_server = MockServer::start().await;
         let base_url = email_server.uri();
-        let email_client = Arc::new(RwLock::new(configure_postmark_email_client(base_url)));
-        //let email_client = Arc::new(RwLock::new(MockEmailClient {}));
+        let email_client = Arc::new(configure_postmark_email_client(
+            base_url,
+            &settings.email_client,
+        ));
+        //let email_client = Arc::new(MockEmailClient {});
 
         let cookie_jar = Arc::new(Jar::default());
         let app_state = AppState {
-            user_store,
+            user_store: user_store.clone(),
             banned_tokens_store: banned_tokens_store.clone(),
             two_fa_code_store: two_fa_code_store.clone(),
+            refresh_token_store: refresh_token_store.clone(),
+            session_store: session_store.clone(),
+            rate_limit_store,
+            audit_writer: AuditWriter::spawn(audit_sink.clone()),
+            audit_sink,
+            key_ring: key_ring.clone(),
             email_client: email_client.clone(),
+            settings: settings.clone(),
+            background_tasks: TaskTracker::new(),
         };
-        let app = Application::build(app_state, utils::constants::test::APP_ADDRESS)
+        let app = Application::build(app_state)
             .await
             .expect("Failed to build app");
 
         let address = format!("http://{}", app.address.clone());
+        let shutdown = app.shutdown_handle();
 
         // Run the auth service in a separate async task
         // to avoid blocking the main test thread.
-        #[allow(clippy::let_underscore_future)]
-        let _ = tokio::spawn(app.run());
+        let server = tokio::spawn(app.run());
         let http_client = reqwest::Client::builder()
             .cookie_provider(cookie_jar.clone())
             .build()
@@ -92,8 +150,15 @@ impl TestApp {
             cookie_jar,
             http_client,
             email_server,
+            user_store,
             banned_tokens_store,
             two_fa_code_store,
+            refresh_token_store,
+            session_store,
+            key_ring,
+            settings,
+            shutdown,
+            server,
             db_name: db_name.to_owned(),
             clean_up_called: false,
         }
@@ -101,7 +166,163 @@ impl TestApp {
 
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/", &self.address))
+            .get(format!("{}/", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_health_live(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/live", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_health_live_with_headers(
+        &self,
+        headers: &[(&str, &str)],
+    ) -> reqwest::Response {
+        let mut request = self
+            .http_client
+            .get(format!("{}/health/live", &self.address));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_health_ready(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/ready", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self, admin_api_key: Option<&str>) -> reqwest::Response {
+        let mut request = self.http_client.get(format!("{}/metrics", &self.address));
+        if let Some(key) = admin_api_key {
+            request = request.header(ADMIN_API_KEY_HEADER, key);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_jwks(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/jwks.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_rotate_signing_keys(&self, admin_api_key: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .http_client
+            .post(format!("{}/admin/signing-keys/rotate", &self.address));
+        if let Some(key) = admin_api_key {
+            request = request.header(ADMIN_API_KEY_HEADER, key);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_users(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/audit", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit_verify(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/audit/verify", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/users", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_user(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_user(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/users/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_password_reset(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!(
+                "{}/admin/users/{}/password-reset",
+                &self.address, id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_two_fa<Body>(&self, id: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/users/{}/2fa", &self.address, id))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_lock(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/admin/users/{}/lock", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_unlock(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/admin/users/{}/unlock", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_user_sessions(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/users/{}/sessions", &self.address, id))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -112,7 +333,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/signup", &self.address))
+            .post(format!("{}/signup", &self.address))
             .json(body)
             .send()
             .await
@@ -124,7 +345,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .json(body)
             .send()
             .await
@@ -133,27 +354,222 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_refresh(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/refresh", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_session(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions/{}", &self.address, id))
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn delete_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Text of the emails with this subject sent so far, oldest first.
+    pub async fn emails_sent(&self, subject: &str) -> Vec<String> {
+        self.email_server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(|request| {
+                serde_json::from_slice::<serde_json::Value>(&request.body)
+                    .expect("Invalid email body")
+            })
+            .filter(|body| body["Subject"] == subject)
+            .map(|body| body["TextBody"].as_str().unwrap_or_default().to_owned())
+            .collect()
+    }
+
+    // Wait for emails sent in the background.
+    pub async fn wait_for_emails(&self, subject: &str, count: usize) -> Vec<String> {
+        for _ in 0..50 {
+            let emails = self.emails_sent(subject).await;
+            if emails.len() >= count {
+                return emails;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("Expected {} emails to be sent", count);
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/email", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_email_change<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/email/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .delete(format!("{}/account", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_account_export(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/account/export", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-email", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_verification<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/resend-verification", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Follow the link of the latest verification email, as the account owner would.
+    pub async fn verify_email(&self) {
+        let emails = self.wait_for_emails(VERIFICATION_EMAIL_SUBJECT, 1).await;
+        let token = get_link_token(emails.last().unwrap());
+        let response = self
+            .post_verify_email(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/verify-token", &self.address))
+            .post(format!("{}/verify-token", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
-    pub fn authenticate_user(&self, email: &str) -> String {
+    // Log the user in without a password, as a new session. A user without an
+    // account gets a made up id.
+    pub async fn authenticate_user(&self, email: &str) -> String {
         let email = Email::parse(Secret::new(email.to_owned())).unwrap();
-        let token = utils::auth::generate_auth_cookie(&email).unwrap();
+        let user_id = match self.user_store.get_user(email).await {
+            Ok(user) => user.id,
+            Err(_) => UserId::default(),
+        };
+        let grants = self.user_store.get_grants(user_id).await.unwrap();
+        let client = ClientInfo {
+            ip: "127.0.0.1".parse().unwrap(),
+            user_agent: None,
+        };
+        let session = Session::new(user_id, client, chrono::Utc::now().timestamp());
+        let session_id = session.id.clone();
+        self.session_store.add_session(session).await.unwrap();
+        let token = utils::auth::generate_auth_cookie(
+            self.key_ring.clone(),
+            &user_id,
+            &grants,
+            &session_id,
+        )
+        .await
+        .unwrap();
         self.cookie_jar.add_cookie_str(
             &format!(
                 "{}={}; HttpOnly; SameSite=Lax; Secure; Path=/",
@@ -177,6 +593,26 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_totp_enroll(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/totp/enroll", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/totp/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn create_account(&self, email: &str, password: &str, requires2fa: bool) -> bool {
         let signup_body = serde_json::json!({
             "email": email,
@@ -184,12 +620,62 @@ impl TestApp {
             "requires2FA": requires2fa,
         });
         let response = self.post_signup(&signup_body).await;
-        response.status().as_u16() == 201
+        if response.status().as_u16() != 201 {
+            return false;
+        }
+        self.verify_email().await;
+        true
+    }
+
+    // Create a verified account with the admin role, and log in as it.
+    pub async fn login_as_admin(&self) -> String {
+        let email = get_random_email();
+        assert!(
+            self.create_account(&email, &get_random_password(), false)
+                .await
+        );
+        let user = self
+            .user_store
+            .get_user(Email::parse(Secret::new(email.clone())).unwrap())
+            .await
+            .unwrap();
+        self.user_store
+            .add_role(user.id, Role::parse(ADMIN_ROLE).unwrap())
+            .await
+            .unwrap();
+        self.authenticate_user(&email).await
+    }
+
+    // Id of the user with this email.
+    pub async fn get_user_id(&self, email: &str) -> String {
+        let email = Email::parse(Secret::new(email.to_owned())).unwrap();
+        self.user_store
+            .get_user(email)
+            .await
+            .unwrap()
+            .id
+            .to_string()
+    }
+
+    // Replace a cookie in the jar, as a client replaying an old value would.
+    pub fn set_cookie(&self, name: &str, value: &str) {
+        let path = if name == REFRESH_TOKEN_COOKIE_NAME {
+            REFRESH_TOKEN_COOKIE_PATH
+        } else {
+            "/"
+        };
+        self.cookie_jar.add_cookie_str(
+            &format!(
+                "{}={}; HttpOnly; SameSite=Lax; Secure; Path={}",
+                name, value, path
+            ),
+            &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+        );
     }
 
     pub async fn clean_up(&mut self) {
         self.clean_up_called = true;
-        delete_database(&self.db_name).await;
+        delete_database(&self.settings, &self.db_name).await;
     }
 }
 
@@ -201,6 +687,21 @@ impl Drop for TestApp {
     }
 }
 
+pub const VERIFICATION_EMAIL_SUBJECT: &str = "Verify your email address";
+pub const PASSWORD_RESET_EMAIL_SUBJECT: &str = "Reset your password";
+pub const EMAIL_CHANGE_EMAIL_SUBJECT: &str = "Confirm your new email address";
+pub const EMAIL_CHANGED_EMAIL_SUBJECT: &str = "Your email address was changed";
+
+// Token of the first link in an email.
+pub fn get_link_token(email: &str) -> String {
+    let (_, token) = email.split_once("token=").expect("No link in email");
+    token
+        .split_whitespace()
+        .next()
+        .expect("Empty link token")
+        .to_owned()
+}
+
 pub fn get_random_email() -> String {
     SafeEmail().fake()
 }
@@ -209,8 +710,56 @@ pub fn get_random_password() -> String {
     Password(10..15).fake()
 }
 
-async fn configure_postgresql() -> PgPool {
-    let postgresql_conn_url = DATABASE_URL.to_owned();
+// Compute the code an authenticator app would currently display for this secret.
+pub fn get_totp_code(secret: &str) -> String {
+    get_totp(secret)
+        .generate_current()
+        .expect("Failed to generate TOTP code")
+}
+
+// Compute the code of the next time step, which is accepted too. Each time step is
+// only accepted once, so this is the code to use right after the current one.
+pub fn get_next_totp_code(secret: &str) -> String {
+    let totp = get_totp(secret);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the unix epoch")
+        .as_secs();
+    totp.generate(now + totp.step)
+}
+
+fn get_totp(secret: &str) -> totp_rs::TOTP {
+    let secret = totp_rs::Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .expect("Invalid TOTP secret");
+    totp_rs::TOTP::new(
+        totp_rs::Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        None,
+        String::new(),
+    )
+    .expect("Invalid TOTP parameters")
+}
+
+// Settings of the environment, for an app listening on a random port and
+// sending emails through a mock server.
+fn get_settings() -> Settings {
+    let vars: HashMap<String, String> = std::env::vars().collect();
+    let mut settings = Settings::from_sources(&vars, &[]).expect("Invalid test settings");
+    settings.application.address = "127.0.0.1:0".to_owned();
+    settings.application.pre_stop_delay_milliseconds = 0;
+    settings.email_client.sender = "test@email.com".to_owned();
+    settings.email_client.auth_token = Secret::new("auth_token".to_owned());
+    settings.email_client.timeout_milliseconds = 200;
+    settings.admin.api_key = Some(Secret::new(ADMIN_API_KEY.to_owned()));
+    settings
+}
+
+async fn configure_postgresql(settings: &Settings) -> PgPool {
+    let postgresql_conn_url = settings.database.url.to_owned();
 
     // We are creating a new database for each test case, and we need to ensure each database has a unique name!
     let db_name = Uuid::new_v4().to_string();
@@ -257,10 +806,10 @@ async fn configure_database(db_conn_string: &str, db_name: &str) {
         .expect("Failed to migrate the database");
 }
 
-async fn delete_database(db_name: &str) {
-    let postgresql_conn_url = DATABASE_URL.expose_secret();
+async fn delete_database(settings: &Settings, db_name: &str) {
+    let postgresql_conn_url = settings.database.url.expose_secret();
 
-    let connection_options = PgConnectOptions::from_str(&postgresql_conn_url)
+    let connection_options = PgConnectOptions::from_str(postgresql_conn_url)
         .expect("Failed to parse PostgreSQL connection string");
 
     let mut connection = sqlx::PgConnection::connect_with(&connection_options)
@@ -291,21 +840,27 @@ async fn delete_database(db_name: &str) {
         .expect("Failed to drop the database.");
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
+fn configure_redis(settings: &Settings) -> RedisPool {
+    let client =
+        get_redis_client(settings.redis.host_name.to_owned()).expect("Failed to get Redis client");
+    RedisPool::new(client)
 }
 
-fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
-    let postmark_auth_token = Secret::new("auth_token".to_owned());
-    let sender = Email::parse(Secret::new(test::email_client::SENDER.to_owned())).unwrap();
+fn configure_postmark_email_client(
+    base_url: String,
+    settings: &EmailClientSettings,
+) -> PostmarkEmailClient {
+    let sender = Email::parse(Secret::new(settings.sender.to_owned())).unwrap();
 
     let http_client = Client::builder()
-        .timeout(test::email_client::TIMEOUT)
+        .timeout(settings.timeout())
         .build()
         .expect("Failed to build HTTP client");
 
-    PostmarkEmailClient::new(base_url, sender, postmark_auth_token, http_client)
+    PostmarkEmailClient::new(
+        base_url,
+        sender,
+        settings.auth_token.to_owned(),
+        http_client,
+    )
 }
@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+
+use crate::helpers::{ADMIN_API_KEY, TestApp, get_random_email, get_random_password};
+use auth_service::{
+    routes::SigningKeysResponse,
+    utils::{constants::JWT_COOKIE_NAME, key_ring::KeyRing, signing_key::SigningKey},
+};
+use jsonwebtoken::{decode_header, jwk::JwkSet};
+
+const ED25519_KEY: &[u8] = include_bytes!("../fixtures/jwt_ed25519_private.pem");
+const RSA_KEY: &[u8] = include_bytes!("../fixtures/jwt_rsa_private.pem");
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[tokio::test]
+async fn should_return_published_signing_keys() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_jwks().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let jwks = response
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+    // Only asymmetric keys are published; a shared secret never is.
+    assert_eq!(jwks, app.key_ring.read().await.jwks());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn issued_tokens_carry_the_signing_key_id() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+
+    let token = login(&app, &email, &password).await;
+    let header = decode_header(&token).expect("Invalid JWT header");
+    let kid = app.key_ring.read().await.signing_key().kid().to_owned();
+    assert_eq!(header.kid, Some(kid));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn tokens_signed_before_a_rotation_stay_valid() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    let old_token = login(&app, &email, &password).await;
+
+    let new_key = SigningKey::from_pem(ED25519_KEY, None).unwrap();
+    app.key_ring
+        .write()
+        .await
+        .rotate(new_key.clone(), Vec::new())
+        .unwrap();
+
+    // New tokens are signed with the new key, which relying services can fetch.
+    let new_token = login(&app, &email, &password).await;
+    let header = decode_header(&new_token).expect("Invalid JWT header");
+    assert_eq!(header.kid.as_deref(), Some(new_key.kid()));
+
+    let jwks = app.get_jwks().await.json::<JwkSet>().await.unwrap();
+    assert!(jwks.find(new_key.kid()).is_some());
+
+    for token in [old_token, new_token] {
+        let body = serde_json::json!({ "token": token });
+        let response = app.post_verify_token(&body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    app.clean_up().await;
+}
+
+// The old key is listed by its public half, so its tokens outlive a restart too.
+#[tokio::test]
+async fn tokens_signed_before_a_rotation_stay_valid_after_a_restart() {
+    let signing_key_file = std::env::temp_dir().join(format!("{}.pem", uuid::Uuid::new_v4()));
+    std::fs::write(&signing_key_file, ED25519_KEY).unwrap();
+    let mut app = TestApp::with_settings(|settings| {
+        settings.jwt.signing_key_file = Some(signing_key_file.clone());
+        settings.jwt.verification_key_files = vec![fixture("jwt_ed25519_public.pem")];
+    })
+    .await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    let old_token = login(&app, &email, &password).await;
+    let old_key = SigningKey::from_pem(ED25519_KEY, None).unwrap();
+    let new_key = SigningKey::from_pem(RSA_KEY, None).unwrap();
+
+    std::fs::write(&signing_key_file, RSA_KEY).unwrap();
+    let response = app.post_rotate_signing_keys(Some(ADMIN_API_KEY)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let keys = response.json::<SigningKeysResponse>().await.unwrap();
+    assert_eq!(keys.signing_key_id, new_key.kid());
+    assert_eq!(keys.verification_key_ids, vec![old_key.kid().to_owned()]);
+
+    // A new process only knows the keys of its settings.
+    *app.key_ring.write().await = KeyRing::load(
+        app.settings.jwt.key_source(),
+        app.settings.jwt.token_ttl_seconds,
+    )
+    .unwrap();
+
+    let jwks = app.get_jwks().await.json::<JwkSet>().await.unwrap();
+    assert!(jwks.find(old_key.kid()).is_some());
+    assert!(jwks.find(new_key.kid()).is_some());
+    let new_token = login(&app, &email, &password).await;
+    for token in [old_token, new_token] {
+        let body = serde_json::json!({ "token": token });
+        let response = app.post_verify_token(&body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    std::fs::remove_file(&signing_key_file).unwrap();
+    app.clean_up().await;
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password,
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned()
+}
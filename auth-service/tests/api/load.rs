@@ -0,0 +1,118 @@
+use std::time::Instant;
+
+use crate::helpers::{
+    TestApp, VERIFICATION_EMAIL_SUBJECT, get_link_token, get_random_email, get_random_password,
+};
+
+// Requests sent in each run, enough for a run to last well beyond its ramp up.
+const REQUESTS: usize = 64;
+
+// Password hashing dominates both signup and login, so with one client per core the
+// requests must complete clearly faster than one at a time. They did not while the
+// user store sat behind a global lock.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "load test, run alone with `cargo test --test api load -- --ignored --nocapture`"]
+async fn signup_and_login_throughput_scale_with_cores() {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    assert!(
+        cores > 1,
+        "Throughput can only scale with more than one core"
+    );
+    let mut app = TestApp::new().await;
+
+    let users: Vec<(String, String)> = (0..2 * REQUESTS)
+        .map(|_| (get_random_email(), get_random_password()))
+        .collect();
+    let (first_users, second_users) = users.split_at(REQUESTS);
+
+    let signup_bodies = |users: &[(String, String)]| -> Vec<serde_json::Value> {
+        users
+            .iter()
+            .map(|(email, password)| {
+                serde_json::json!({ "email": email, "password": password, "requires2FA": false })
+            })
+            .collect()
+    };
+    let sequential = throughput(&app, "/signup", signup_bodies(first_users), 1, 201).await;
+    let concurrent = throughput(&app, "/signup", signup_bodies(second_users), cores, 201).await;
+    println!("signup: {sequential:.1} req/s with 1 client, {concurrent:.1} req/s with {cores}");
+    assert_scales(sequential, concurrent);
+
+    // Only verified accounts get as far as starting a session.
+    for email in app
+        .wait_for_emails(VERIFICATION_EMAIL_SUBJECT, users.len())
+        .await
+    {
+        let token = get_link_token(&email);
+        let response = app
+            .post_verify_email(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let login_bodies = |users: &[(String, String)]| -> Vec<serde_json::Value> {
+        users
+            .iter()
+            .map(|(email, password)| serde_json::json!({ "email": email, "password": password }))
+            .collect()
+    };
+    let sequential = throughput(&app, "/login", login_bodies(first_users), 1, 200).await;
+    let concurrent = throughput(&app, "/login", login_bodies(second_users), cores, 200).await;
+    println!("login: {sequential:.1} req/s with 1 client, {concurrent:.1} req/s with {cores}");
+    assert_scales(sequential, concurrent);
+
+    app.clean_up().await;
+}
+
+// Post every body to the route, from `clients` clients at once, and return the
+// number of requests completed per second.
+async fn throughput(
+    app: &TestApp,
+    route: &str,
+    bodies: Vec<serde_json::Value>,
+    clients: usize,
+    expected_status: u16,
+) -> f64 {
+    let requests = bodies.len();
+    let mut shares = vec![Vec::new(); clients];
+    for (i, body) in bodies.into_iter().enumerate() {
+        shares[i % clients].push(body);
+    }
+
+    let start = Instant::now();
+    let mut tasks = tokio::task::JoinSet::new();
+    for share in shares {
+        let http_client = app.http_client.clone();
+        let url = format!("{}{}", app.address, route);
+        tasks.spawn(async move {
+            for body in share {
+                let response = http_client
+                    .post(&url)
+                    .json(&body)
+                    .send()
+                    .await
+                    .expect("Failed to execute request.");
+                assert_eq!(response.status().as_u16(), expected_status);
+            }
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        result.expect("Client task failed");
+    }
+
+    requests as f64 / start.elapsed().as_secs_f64()
+}
+
+// Half the ideal speedup leaves room for the clients and the databases sharing the
+// cores. Past 8 cores, the database pool and memory bandwidth get in the way first.
+fn assert_scales(sequential: f64, concurrent: f64) {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let expected = sequential * (cores.min(8) as f64 / 2.0).max(1.5);
+    assert!(
+        concurrent >= expected,
+        "Expected at least {:.1} req/s with {} clients, got {:.1}",
+        expected,
+        cores,
+        concurrent
+    );
+}
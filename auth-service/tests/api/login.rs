@@ -1,6 +1,7 @@
 use crate::helpers::{TestApp, get_random_email, get_random_password};
 use auth_service::{
-    domain::email::Email, routes::TwoFactorAuthResponse, utils::constants::JWT_COOKIE_NAME,
+    ErrorResponse, domain::email::Email, routes::TwoFactorAuthResponse,
+    utils::constants::JWT_COOKIE_NAME,
 };
 use secrecy::{ExposeSecret, Secret};
 use wiremock::{
@@ -60,6 +61,7 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     });
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email().await;
 
     let login_body = serde_json::json!({
         "email": email,
@@ -90,6 +92,7 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     });
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email().await;
 
     // Define an expectation for the mock server
     Mock::given(path("/email")) // Expect an HTTP request to the "/email" path
@@ -111,13 +114,85 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     assert_eq!(json_body.message, "2FA required".to_owned());
 
     let email = Email::parse(Secret::new(email.to_owned())).expect("Email parse error");
-    let two_fa_code_store = app.two_fa_code_store.read().await;
+    let two_fa_code_store = &app.two_fa_code_store;
     let code = two_fa_code_store
         .get_code(&email)
         .await
         .expect("login code not found");
     assert_eq!(code.0.as_ref().expose_secret(), &json_body.login_attempt_id);
-    drop(two_fa_code_store);
 
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_return_403_if_email_not_verified() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": password,
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Email not verified".to_owned()
+    );
+
+    // The password is checked first, so that the state of an account is not disclosed.
+    let login_body = serde_json::json!({ "email": email, "password": get_random_password() });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_429_after_repeated_incorrect_passwords() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+
+    let wrong_body = serde_json::json!({
+        "email": email,
+        "password": get_random_password(),
+    });
+    for _ in 0..5 {
+        let response = app.post_login(&wrong_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Even the right password is refused until the delay is over.
+    let body = serde_json::json!({
+        "email": email,
+        "password": password,
+    });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response
+        .headers()
+        .get("retry-after")
+        .expect("No Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .expect("Retry-After is not a number of seconds");
+    assert!(retry_after >= 1);
+
+    tokio::time::sleep(std::time::Duration::from_secs(retry_after)).await;
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clean_up().await;
+}
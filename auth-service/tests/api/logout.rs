@@ -58,16 +58,15 @@ async fn should_return_401_if_invalid_token() {
 async fn should_return_200_if_valid_jwt_cookie() {
     let mut app = TestApp::new().await;
     let email = get_random_email();
-    let token = app.authenticate_user(&email);
+    let token = app.authenticate_user(&email).await;
 
     let response = app.post_logout().await;
     assert_eq!(response.status().as_u16(), 200);
 
     // On logout, the token is added to our banned store.
-    let store = app.banned_tokens_store.read().await;
+    let store = &app.banned_tokens_store;
     let found = store.has_token(Secret::new(token)).await;
     assert!(found.is_ok() && found.unwrap());
-    drop(store);
 
     app.clean_up().await;
 }
@@ -76,7 +75,7 @@ async fn should_return_200_if_valid_jwt_cookie() {
 async fn should_return_400_if_logout_called_twice_in_a_row() {
     let mut app = TestApp::new().await;
     let email = get_random_email();
-    app.authenticate_user(&email);
+    app.authenticate_user(&email).await;
 
     let response = app.post_logout().await;
     assert_eq!(response.status().as_u16(), 200);
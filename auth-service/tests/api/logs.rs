@@ -0,0 +1,123 @@
+use auth_service::{
+    domain::Email,
+    routes::TwoFactorAuthResponse,
+    utils::{
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+        log_format::LogBuffer,
+        tracing::{compact_log_layer, json_log_layer},
+    },
+};
+use secrecy::{ExposeSecret, Secret};
+use tracing::Subscriber;
+use tracing_subscriber::{filter::LevelFilter, prelude::*};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{
+    TestApp, VERIFICATION_EMAIL_SUBJECT, get_link_token, get_random_email, get_random_password,
+};
+
+// Whether `secret` appears in the logs on its own, not inside a longer word: a 2FA
+// code could be part of a timestamp.
+fn is_logged(logs: &str, secret: &str) -> bool {
+    logs.match_indices(secret).any(|(start, _)| {
+        let before = logs[..start].chars().next_back();
+        let after = logs[start + secret.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric())
+            && !after.is_some_and(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+#[tokio::test]
+async fn should_never_log_secrets_during_signup_login_and_2fa() {
+    let logs = LogBuffer::default();
+    let subscriber = tracing_subscriber::registry()
+        .with(json_log_layer(logs.clone()).with_filter(LevelFilter::DEBUG));
+    log_signup_login_and_2fa(subscriber, &logs).await;
+
+    for line in logs.contents().lines() {
+        serde_json::from_str::<serde_json::Value>(line).expect("Invalid log line");
+    }
+}
+
+#[tokio::test]
+async fn should_never_log_secrets_in_the_compact_format() {
+    let logs = LogBuffer::default();
+    let subscriber = tracing_subscriber::registry()
+        .with(compact_log_layer(logs.clone()).with_filter(LevelFilter::DEBUG));
+    log_signup_login_and_2fa(subscriber, &logs).await;
+}
+
+// Sign up, log in with 2FA and search for the account, checking that none of the
+// secrets involved is written to `logs` by `subscriber`.
+async fn log_signup_login_and_2fa(subscriber: impl Subscriber + Send + Sync, logs: &LogBuffer) {
+    // Tests run the app on their own thread, so only this app's events are captured.
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let mut app = TestApp::new().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, true).await);
+    let emails = app.wait_for_emails(VERIFICATION_EMAIL_SUBJECT, 1).await;
+    let verification_token = get_link_token(emails.last().unwrap());
+
+    let body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let (_, code) = app
+        .two_fa_code_store
+        .get_code(&Email::parse(Secret::new(email.clone())).unwrap())
+        .await
+        .expect("2FA code not found");
+    let code = code
+        .expect("No emailed code")
+        .as_ref()
+        .expose_secret()
+        .to_owned();
+
+    let body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let cookie = |name| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+            .expect("Missing cookie")
+    };
+    let (token, refresh_token) = (cookie(JWT_COOKIE_NAME), cookie(REFRESH_TOKEN_COOKIE_NAME));
+    // The query of a request is not logged either.
+    app.get_admin_users(&[("search", &email)]).await;
+
+    let logs = logs.contents();
+    assert!(logs.contains("/verify-2fa"), "The requests were not logged");
+    for (name, secret) in [
+        ("email", &email),
+        ("password", &password),
+        ("verification token", &verification_token),
+        ("login attempt id", &login_attempt_id),
+        ("2FA code", &code),
+        ("auth token", &token),
+        ("refresh token", &refresh_token),
+    ] {
+        assert!(!is_logged(&logs, secret), "The {} was logged", name);
+    }
+
+    app.clean_up().await;
+}
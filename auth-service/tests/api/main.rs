@@ -1,7 +1,24 @@
+mod admin;
+mod account;
+mod audit;
+mod health;
 mod helpers;
+mod jwks;
+mod load;
 mod login;
 mod logout;
+mod logs;
+mod metrics;
+mod password_reset;
+mod rate_limit;
+mod refresh;
 mod root;
+mod sessions;
+mod shutdown;
+mod signing_keys;
 mod signup;
+mod trace_context;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
@@ -0,0 +1,75 @@
+use crate::helpers::{ADMIN_API_KEY, TestApp, get_random_email, get_random_password};
+
+// Value of the series with exactly these labels. Tests share the metrics of the
+// process, so only lower bounds can be checked.
+async fn get_value(app: &TestApp, series: &str) -> f64 {
+    let response = app.get_metrics(Some(ADMIN_API_KEY)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .text()
+        .await
+        .expect("Failed to read metrics")
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map(|value| value.parse().expect("Invalid metric value"))
+        .unwrap_or(0.0)
+}
+
+#[tokio::test]
+async fn should_return_metrics_in_the_prometheus_format() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_metrics(Some(ADMIN_API_KEY)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let text = response.text().await.unwrap();
+    assert!(text.contains("# TYPE auth_logins_total counter"));
+    assert!(text.contains("# TYPE auth_password_hashing_duration_seconds histogram"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_the_admin_api_key() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_metrics(None).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.get_metrics(Some("not-the-admin-key")).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_count_signups_and_time_them() {
+    let mut app = TestApp::new().await;
+    let body = serde_json::json!({
+        "email": get_random_email(),
+        "password": get_random_password(),
+        "requires2FA": false,
+    });
+
+    let response = app.post_signup(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app.post_signup(&body).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    assert!(get_value(&app, r#"auth_signups_total{outcome="success"}"#).await >= 1.0);
+    assert!(get_value(&app, r#"auth_signups_total{outcome="failure"}"#).await >= 1.0);
+    let series =
+        r#"http_request_duration_seconds_count{method="POST",route="/signup",status="201"}"#;
+    assert!(get_value(&app, series).await >= 1.0);
+    let series = r#"auth_password_hashing_duration_seconds_count{operation="hash"}"#;
+    assert!(get_value(&app, series).await >= 1.0);
+    let series = r#"auth_postgres_query_duration_seconds_count{operation="add_user"}"#;
+    assert!(get_value(&app, series).await >= 1.0);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,187 @@
+use crate::helpers::{
+    PASSWORD_RESET_EMAIL_SUBJECT, TestApp, get_link_token, get_random_email, get_random_password,
+};
+use auth_service::{routes::PasswordResetResponse, utils::constants::JWT_COOKIE_NAME};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+// Request a reset link for the email, and return the token it was sent with.
+async fn request_reset_token(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let emails = app.wait_for_emails(PASSWORD_RESET_EMAIL_SUBJECT, 1).await;
+    get_link_token(emails.last().unwrap())
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "mail": "a@example.com" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({ "token": "token" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_the_same_response_for_unknown_emails() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+
+    let known = app
+        .post_password_reset_request(&serde_json::json!({ "email": email }))
+        .await;
+    let unknown = app
+        .post_password_reset_request(&serde_json::json!({ "email": get_random_email() }))
+        .await;
+
+    assert_eq!(known.status(), unknown.status());
+    assert_eq!(
+        known.json::<PasswordResetResponse>().await.unwrap(),
+        unknown.json::<PasswordResetResponse>().await.unwrap()
+    );
+
+    // Only the existing account gets an email.
+    app.wait_for_emails(PASSWORD_RESET_EMAIL_SUBJECT, 1).await;
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(app.emails_sent(PASSWORD_RESET_EMAIL_SUBJECT).await.len(), 1);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reset_password_and_log_out_everywhere() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    let old_password = get_random_password();
+    let new_password = get_random_password();
+    assert!(app.create_account(&email, &old_password, false).await);
+
+    let login = |password: String| {
+        let body = serde_json::json!({ "email": email, "password": password });
+        let app = &app;
+        async move { app.post_login(&body).await }
+    };
+    let response = login(old_password.clone()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let old_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let token = request_reset_token(&app, &email).await;
+    let body = serde_json::json!({ "token": token, "newPassword": new_password });
+    let response = app.post_password_reset_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Tokens issued before the reset are no longer accepted, and neither is their refresh token.
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": old_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(app.post_refresh().await.status().as_u16(), 401);
+
+    assert_eq!(login(old_password).await.status().as_u16(), 401);
+    let response = login(new_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": new_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_reset_token_is_reused() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+
+    let token = request_reset_token(&app, &email).await;
+    let body = serde_json::json!({ "token": token, "newPassword": get_random_password() });
+    let response = app.post_password_reset_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = serde_json::json!({ "token": token, "newPassword": get_random_password() });
+    let response = app.post_password_reset_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_is_not_a_reset_token() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+
+    // An auth token is signed by the same key, but must not reset a password.
+    let auth_token = app.authenticate_user(&email).await;
+    for token in [auth_token, "invalid".to_owned()] {
+        let body = serde_json::json!({ "token": token, "newPassword": get_random_password() });
+        let response = app.post_password_reset_confirm(&body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+
+    let token = request_reset_token(&app, &email).await;
+    let body = serde_json::json!({ "token": token, "newPassword": "short" });
+    let response = app.post_password_reset_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
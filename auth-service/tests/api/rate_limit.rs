@@ -0,0 +1,49 @@
+use crate::helpers::TestApp;
+use auth_service::{
+    domain::{Failures, RateLimitKey, data_stores::RateLimitStore, email::Email},
+    get_redis_client,
+    services::{RedisPool, RedisRateLimitStore},
+};
+use chrono::Utc;
+use secrecy::Secret;
+use uuid::Uuid;
+
+fn get_store(app: &TestApp) -> RedisRateLimitStore {
+    let client = get_redis_client(app.settings.redis.host_name.to_owned())
+        .expect("Failed to get Redis client");
+    RedisRateLimitStore::new(RedisPool::new(client))
+}
+
+fn get_key() -> RateLimitKey {
+    let email = format!("{}@example.com", Uuid::new_v4());
+    RateLimitKey::Email(Email::parse(Secret::new(email)).unwrap())
+}
+
+#[tokio::test]
+async fn should_keep_the_time_of_a_failure_when_a_success_follows() {
+    let mut app = TestApp::new().await;
+    let store = get_store(&app);
+    let key = get_key();
+    let now = Utc::now().timestamp();
+    let failure = Failures {
+        count: 1,
+        last_failure_at: now - 10,
+    };
+    store.reserve_attempt(&key, now - 10).await.unwrap();
+
+    // The attempt that succeeds is released: it does not delay the next one.
+    let previous = store.reserve_attempt(&key, now).await.unwrap();
+    assert_eq!(previous, Some(failure));
+    store.release_attempt(&key, previous).await.unwrap();
+
+    let previous = store.reserve_attempt(&key, now + 1).await.unwrap();
+    assert_eq!(previous, Some(failure));
+    store.release_attempt(&key, previous).await.unwrap();
+
+    // Releasing the only attempt leaves no failures.
+    store.release_attempt(&key, None).await.unwrap();
+    assert_eq!(store.reserve_attempt(&key, now).await.unwrap(), None);
+
+    store.clear_failures(&key).await.unwrap();
+    app.clean_up().await;
+}
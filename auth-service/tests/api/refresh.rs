@@ -0,0 +1,168 @@
+use auth_service::{
+    ErrorResponse,
+    domain::RefreshToken,
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+};
+
+use secrecy::Secret;
+
+use crate::helpers::{TestApp, get_random_email, get_random_password};
+
+// Sign up and log in without 2FA, returning the refresh token set by login.
+async fn login_new_user(app: &TestApp) -> String {
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+
+    let body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_unknown_refresh_token() {
+    let mut app = TestApp::new().await;
+    app.set_cookie(REFRESH_TOKEN_COOKIE_NAME, &"a".repeat(64));
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_rotate_tokens() {
+    let mut app = TestApp::new().await;
+    let refresh_token = login_new_user(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    let rotated_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned();
+    assert_ne!(rotated_token, refresh_token);
+
+    // The new JWT is valid.
+    let body = serde_json::json!({ "token": auth_cookie.value() });
+    let response = app.post_verify_token(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The rotated token can be used in turn.
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_family_if_rotated_token_is_reused() {
+    let mut app = TestApp::new().await;
+    let refresh_token = login_new_user(&app).await;
+
+    // Legitimate rotation.
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let rotated_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned();
+
+    // Someone replays the original token.
+    app.set_cookie(REFRESH_TOKEN_COOKIE_NAME, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The latest token of the family is now rejected too.
+    app.set_cookie(REFRESH_TOKEN_COOKIE_NAME, &rotated_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_rotate_a_token_presented_concurrently_only_once() {
+    let mut app = TestApp::new().await;
+    login_new_user(&app).await;
+
+    let (first, second) = tokio::join!(app.post_refresh(), app.post_refresh());
+    let mut responses = [first, second];
+    responses.sort_by_key(|response| response.status());
+    let [rotated, reused] = responses;
+    assert_eq!(rotated.status().as_u16(), 200);
+    assert_eq!(reused.status().as_u16(), 401);
+
+    // The other request counts as a reuse, so the rotated token is revoked too.
+    let rotated_token = rotated
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned();
+    app.set_cookie(REFRESH_TOKEN_COOKIE_NAME, &rotated_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_after_logout() {
+    let mut app = TestApp::new().await;
+    let refresh_token = login_new_user(&app).await;
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // On logout, the refresh token family is revoked.
+    let token = RefreshToken::parse(Secret::new(refresh_token.clone())).unwrap();
+    let store = &app.refresh_token_store;
+    let entry = store
+        .get_token(&token)
+        .await
+        .expect("refresh token not found");
+    assert!(store.is_family_revoked(&entry.family_id).await.unwrap());
+
+    app.set_cookie(REFRESH_TOKEN_COOKIE_NAME, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
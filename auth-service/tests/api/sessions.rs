@@ -0,0 +1,189 @@
+use crate::helpers::{TestApp, get_random_email, get_random_password};
+use auth_service::{routes::SessionResponse, utils::constants::JWT_COOKIE_NAME};
+use reqwest::header::USER_AGENT;
+
+// Log in from another client, with its own cookies, and return its JWT.
+async fn login_elsewhere(app: &TestApp, email: &str, password: &str) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .header(USER_AGENT, OTHER_USER_AGENT)
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned()
+}
+
+const OTHER_USER_AGENT: &str = "Other Browser/1.0";
+
+async fn login(app: &TestApp, email: &str, password: &str) {
+    let body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn get_sessions(app: &TestApp) -> Vec<SessionResponse> {
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json()
+        .await
+        .expect("Could not deserialize response body to Vec<SessionResponse>")
+}
+
+async fn is_token_valid(app: &TestApp, token: &str) -> bool {
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    response.status().as_u16() == 200
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.delete_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_list_sessions_of_the_user() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    login_elsewhere(&app, &email, &password).await;
+    login(&app, &email, &password).await;
+
+    // Sessions of other users are not listed.
+    let other_email = get_random_email();
+    assert!(app.create_account(&other_email, &password, false).await);
+    login_elsewhere(&app, &other_email, &password).await;
+
+    let sessions = get_sessions(&app).await;
+    assert_eq!(sessions.len(), 2);
+    let current: Vec<_> = sessions.iter().filter(|session| session.current).collect();
+    assert_eq!(current.len(), 1);
+    let other = sessions
+        .iter()
+        .find(|session| !session.current)
+        .expect("No other session found");
+    assert_eq!(other.user_agent.as_deref(), Some(OTHER_USER_AGENT));
+    for session in &sessions {
+        assert_eq!(session.ip, "127.0.0.1");
+        assert!(session.created_at <= session.last_seen_at);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_another_session() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    let other_token = login_elsewhere(&app, &email, &password).await;
+    login(&app, &email, &password).await;
+
+    let sessions = get_sessions(&app).await;
+    let other = sessions
+        .iter()
+        .find(|session| !session.current)
+        .expect("No other session found");
+    let response = app.delete_session(&other.id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    // The other client is logged out, this one is not.
+    assert!(!is_token_valid(&app, &other_token).await);
+    let sessions = get_sessions(&app).await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+
+    // A revoked session is gone.
+    let response = app.delete_session(&other.id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_log_out_when_revoking_the_current_session() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    login(&app, &email, &password).await;
+
+    let sessions = get_sessions(&app).await;
+    let response = app.delete_session(&sessions[0].id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    // The cookies are removed, and the refresh token is revoked with the session.
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.post_refresh().await;
+    assert_ne!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_if_session_is_unknown_or_not_owned() {
+    let mut app = TestApp::new().await;
+    let password = get_random_password();
+    let other_email = get_random_email();
+    assert!(app.create_account(&other_email, &password, false).await);
+    login(&app, &other_email, &password).await;
+    let other_session = get_sessions(&app).await.remove(0);
+
+    let email = get_random_email();
+    assert!(app.create_account(&email, &password, false).await);
+    login(&app, &email, &password).await;
+
+    let ids = [
+        "not-a-session-id".to_owned(),
+        uuid::Uuid::new_v4().to_string(),
+        other_session.id,
+    ];
+    for id in ids {
+        let response = app.delete_session(&id).await;
+        assert_eq!(response.status().as_u16(), 404, "Failed for id: {}", id);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_all_sessions() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, false).await);
+    let other_token = login_elsewhere(&app, &email, &password).await;
+    login(&app, &email, &password).await;
+
+    let response = app.delete_sessions().await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert!(!is_token_valid(&app, &other_token).await);
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // Logging in again starts a new session.
+    login(&app, &email, &password).await;
+    assert_eq!(get_sessions(&app).await.len(), 1);
+
+    app.clean_up().await;
+}
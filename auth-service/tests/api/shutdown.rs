@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+use crate::helpers::{TestApp, VERIFICATION_EMAIL_SUBJECT, get_random_email, get_random_password};
+use auth_service::{routes::ReadinessResponse, utils::settings::Settings};
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+// An app whose emails may take longer than the delays of these tests.
+async fn get_app(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    TestApp::with_settings(|settings| {
+        settings.email_client.timeout_milliseconds = 10_000;
+        configure(settings);
+    })
+    .await
+}
+
+// Make the email provider answer after `delay`, so that requests sending emails stay
+// in flight.
+async fn delay_emails(app: &TestApp, delay: Duration) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(delay))
+        .mount(&app.email_server)
+        .await;
+}
+
+fn get_signup_body() -> serde_json::Value {
+    serde_json::json!({
+        "email": get_random_email(),
+        "password": get_random_password(),
+        "requires2FA": false,
+    })
+}
+
+#[tokio::test]
+async fn should_finish_requests_in_flight_before_stopping() {
+    let mut app = get_app(|_| {}).await;
+    let email = get_random_email();
+    let password = get_random_password();
+    assert!(app.create_account(&email, &password, true).await);
+    delay_emails(&app, Duration::from_millis(500)).await;
+
+    // Logging in with 2FA waits for the code to be emailed.
+    let login = {
+        let http_client = app.http_client.clone();
+        let url = format!("{}/login", &app.address);
+        let body = serde_json::json!({ "email": email, "password": password });
+        tokio::spawn(async move { http_client.post(url).json(&body).send().await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    app.shutdown.shutdown();
+
+    let response = login.await.unwrap().expect("Login was dropped");
+    assert_eq!(response.status().as_u16(), 206);
+    let server = tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("Server still running after the drain");
+    assert!(server.unwrap().is_ok());
+
+    // The listener is closed: new connections are refused.
+    assert!(
+        reqwest::get(format!("{}/health/live", &app.address))
+            .await
+            .is_err()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_requests_while_unready_during_the_pre_stop_delay() {
+    let mut app = get_app(|settings| {
+        settings.application.pre_stop_delay_milliseconds = 500;
+    })
+    .await;
+
+    app.shutdown.shutdown();
+
+    let response = app.get_health_ready().await;
+    assert_eq!(response.status().as_u16(), 503);
+    let body: ReadinessResponse = response.json().await.unwrap();
+    assert!(body.draining);
+    let response = app.post_signup(&get_signup_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let server = tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("Server still running after the pre-stop delay");
+    assert!(server.unwrap().is_ok());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_background_emails_before_stopping() {
+    let mut app = get_app(|_| {}).await;
+    delay_emails(&app, Duration::from_millis(500)).await;
+
+    // The verification email is sent once the response is back.
+    let response = app.post_signup(&get_signup_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let start = Instant::now();
+    app.shutdown.shutdown();
+
+    let server = tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("Server still running after the drain");
+    assert!(server.unwrap().is_ok());
+    assert!(start.elapsed() >= Duration::from_millis(400));
+    assert_eq!(app.emails_sent(VERIFICATION_EMAIL_SUBJECT).await.len(), 1);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_stop_after_the_drain_timeout() {
+    let mut app = get_app(|settings| {
+        settings.application.drain_timeout_milliseconds = 100;
+    })
+    .await;
+    delay_emails(&app, Duration::from_secs(5)).await;
+
+    let response = app.post_signup(&get_signup_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.shutdown.shutdown();
+
+    let server = tokio::time::timeout(Duration::from_secs(2), &mut app.server)
+        .await
+        .expect("Server waited for an email past the drain timeout");
+    assert!(server.unwrap().is_ok());
+
+    app.clean_up().await;
+}
@@ -0,0 +1,23 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn should_return_400_if_admin_api_key_is_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_rotate_signing_keys(None).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_admin_api_key_is_wrong() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_rotate_signing_keys(Some("not-the-admin-key"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -1,4 +1,4 @@
-use auth_service::{ErrorResponse, routes::SignupResponse};
+use auth_service::{ErrorResponse, domain::LocalPartPolicy, routes::SignupResponse};
 
 use crate::helpers::{TestApp, get_random_email, get_random_password};
 
@@ -110,3 +110,96 @@ async fn should_return_409_if_email_already_exists() {
 
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_return_409_for_all_but_one_of_concurrent_duplicate_signups() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let password = get_random_password();
+    let valid_input =
+        serde_json::json!({"email": email, "password": password, "requires2FA": false});
+
+    // Every signup is in flight before any of them is stored.
+    let mut signups = tokio::task::JoinSet::new();
+    for _ in 0..8 {
+        let request = app
+            .http_client
+            .post(format!("{}/signup", &app.address))
+            .json(&valid_input);
+        signups.spawn(async move {
+            request
+                .send()
+                .await
+                .expect("Failed to execute request.")
+                .status()
+                .as_u16()
+        });
+    }
+    let mut statuses = Vec::new();
+    while let Some(status) = signups.join_next().await {
+        statuses.push(status.expect("Signup task failed"));
+    }
+    statuses.sort();
+
+    assert_eq!(statuses, [201, 409, 409, 409, 409, 409, 409, 409]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_email_only_differs_by_case_or_spacing() {
+    let mut app = TestApp::new().await;
+
+    let password = get_random_password();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": "Alice@Example.com",
+            "password": password,
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": " alice@EXAMPLE.COM ",
+            "password": password,
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_the_local_part_case_with_the_preserve_policy() {
+    let mut app = TestApp::with_settings(|settings| {
+        settings.email.local_part_policy = LocalPartPolicy::Preserve;
+    })
+    .await;
+
+    let password = get_random_password();
+    for email in ["Alice@Example.com", "alice@EXAMPLE.COM"] {
+        let response = app
+            .post_signup(&serde_json::json!({
+                "email": email,
+                "password": password,
+                "requires2FA": false
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": " Alice@example.com ",
+            "password": password,
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,181 @@
+use auth_service::{
+    ErrorResponse,
+    routes::{EnrollTotpResponse, TwoFactorAuthResponse},
+    utils::constants::JWT_COOKIE_NAME,
+};
+
+use crate::helpers::{
+    TestApp, get_next_totp_code, get_random_email, get_random_password, get_totp_code,
+};
+
+// Sign up and log in without 2FA, so the cookie jar holds a valid JWT.
+async fn login_new_user(app: &TestApp, email: &str, password: &str) {
+    assert!(app.create_account(email, password, false).await);
+    let body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": "123456" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_otpauth_uri_on_enroll() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    login_new_user(&app, &email, &get_random_password()).await;
+
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<EnrollTotpResponse>()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse");
+    assert!(!body.secret.is_empty());
+    assert!(body.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(body.otpauth_uri.contains(&body.secret));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_confirmation_code() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    login_new_user(&app, &email, &get_random_password()).await;
+
+    let response = app.post_totp_enroll().await;
+    let body = response.json::<EnrollTotpResponse>().await.unwrap();
+    let code = get_totp_code(&body.secret);
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": wrong_code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_confirming_without_enrollment() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    login_new_user(&app, &email, &get_random_password()).await;
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": "123456" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_login_with_totp_once_confirmed() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    login_new_user(&app, &email, &password).await;
+
+    let response = app.post_totp_enroll().await;
+    let enrollment = response.json::<EnrollTotpResponse>().await.unwrap();
+
+    let code = get_totp_code(&enrollment.secret);
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // A second enrollment would silently replace the confirmed secret.
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "TOTP already enabled".to_owned()
+    );
+
+    // Login now asks for a second factor, without sending any email.
+    let body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+
+    // The code that confirmed the enrollment is used up.
+    let body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": get_next_totp_code(&enrollment.secret),
+    });
+    let response = app.post_verify_2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_a_totp_code_is_replayed() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+    login_new_user(&app, &email, &password).await;
+
+    let response = app.post_totp_enroll().await;
+    let enrollment = response.json::<EnrollTotpResponse>().await.unwrap();
+    let code = get_totp_code(&enrollment.secret);
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The code that confirmed the enrollment does not log in, and the next one logs in
+    // once only, although both are still accepted by the clock.
+    let next_code = get_next_totp_code(&enrollment.secret);
+    for (code, expected_status) in [(code, 401), (next_code.clone(), 200), (next_code, 401)] {
+        let body = serde_json::json!({ "email": email, "password": password });
+        let response = app.post_login(&body).await;
+        assert_eq!(response.status().as_u16(), 206);
+        let login_attempt_id = response
+            .json::<TwoFactorAuthResponse>()
+            .await
+            .unwrap()
+            .login_attempt_id;
+
+        let body = serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code,
+        });
+        let response = app.post_verify_2fa(&body).await;
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+
+    app.clean_up().await;
+}
@@ -0,0 +1,97 @@
+use auth_service::utils::{
+    constants::REQUEST_ID_HEADER,
+    tracing::{init_tracer_provider, otel_layer},
+};
+use std::time::Duration;
+use tracing_subscriber::prelude::*;
+use wiremock::{
+    Mock, MockServer, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::TestApp;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+fn contains(body: &[u8], bytes: &[u8]) -> bool {
+    body.windows(bytes.len()).any(|window| window == bytes)
+}
+
+#[tokio::test]
+async fn should_echo_the_request_id_of_the_client() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .get_health_live_with_headers(&[(REQUEST_ID_HEADER, "app-service-42")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()[REQUEST_ID_HEADER], "app-service-42");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_give_a_new_request_id_to_requests_without_a_valid_one() {
+    let mut app = TestApp::new().await;
+    let too_long = "a".repeat(129);
+
+    for headers in [vec![], vec![(REQUEST_ID_HEADER, too_long.as_str())]] {
+        let response = app.get_health_live_with_headers(&headers).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(request_id).is_ok(), "{}", request_id);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_export_the_span_of_a_request_in_the_trace_of_the_caller() {
+    // Stands in for an OpenTelemetry collector.
+    let collector = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/traces"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&collector)
+        .await;
+    let tracer_provider =
+        init_tracer_provider(&format!("{}/v1/traces", collector.uri()), "auth-service").unwrap();
+    // Tests run the app on their own thread, so only this app's spans are exported.
+    let subscriber = tracing_subscriber::registry().with(otel_layer(&tracer_provider));
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let mut app = TestApp::new().await;
+
+    let traceparent = format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID);
+    let response = app
+        .get_health_live_with_headers(&[("traceparent", &traceparent)])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Spans are encoded with protobuf, where ids are raw bytes.
+    let trace_id = u128::from_str_radix(TRACE_ID, 16).unwrap().to_be_bytes();
+    let parent_span_id = u64::from_str_radix(PARENT_SPAN_ID, 16)
+        .unwrap()
+        .to_be_bytes();
+    let mut exported = false;
+    for _ in 0..50 {
+        // The span ends once the response is sent, after the client gets it.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        tracer_provider.force_flush().unwrap();
+        exported = collector
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .any(|request| {
+                contains(&request.body, &trace_id) && contains(&request.body, &parent_span_id)
+            });
+        if exported {
+            break;
+        }
+    }
+    assert!(exported, "Expected a span in the trace of the caller");
+
+    app.clean_up().await;
+    tracer_provider.shutdown().unwrap();
+}
@@ -82,7 +82,7 @@ async fn should_return_401_if_old_code() {
     let password = get_random_password();
     let email_parsed = Email::parse(Secret::new(email.clone())).expect("invalid email");
 
-    assert!(true == app.create_account(&email, &password, true).await);
+    assert!(app.create_account(&email, &password, true).await);
     Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
@@ -95,12 +95,11 @@ async fn should_return_401_if_old_code() {
     let response = app.post_login(&body).await;
     assert_eq!(response.status().as_u16(), 206);
 
-    let two_fa_store = app.two_fa_code_store.read().await;
+    let two_fa_store = &app.two_fa_code_store;
     let code_tuple = two_fa_store
         .get_code(&email_parsed)
         .await
         .expect("2FA codes not found");
-    drop(two_fa_store);
 
     // Try a second login to invalidate the previous codes.
     let body = serde_json::json!({ "email": email, "password": password});
@@ -111,7 +110,7 @@ async fn should_return_401_if_old_code() {
     let body = serde_json::json!({
         "email": email,
         "loginAttemptId": code_tuple.0.as_ref().expose_secret(),
-        "2FACode": code_tuple.1.as_ref().expose_secret(),
+        "2FACode": code_tuple.1.as_ref().unwrap().as_ref().expose_secret(),
     });
     let response = app.post_verify_2fa(&body).await;
     assert_eq!(
@@ -132,7 +131,7 @@ async fn should_return_200_if_correct_code() {
     let email = get_random_email();
     let email_parsed = Email::parse(Secret::new(email.clone())).expect("invalid email");
 
-    assert!(true == app.create_account(&email, "password123", true).await);
+    assert!(app.create_account(&email, "password123", true).await);
     Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
@@ -145,17 +144,16 @@ async fn should_return_200_if_correct_code() {
     let response = app.post_login(&body).await;
     assert_eq!(response.status().as_u16(), 206);
 
-    let two_fa_store = app.two_fa_code_store.read().await;
+    let two_fa_store = &app.two_fa_code_store;
     let code_tuple = two_fa_store
         .get_code(&email_parsed)
         .await
         .expect("2FA codes not found");
-    drop(two_fa_store);
 
     let body = serde_json::json!({
         "email": email,
         "loginAttemptId": code_tuple.0.as_ref().expose_secret(),
-        "2FACode": code_tuple.1.as_ref().expose_secret(),
+        "2FACode": code_tuple.1.as_ref().unwrap().as_ref().expose_secret(),
     });
     let response = app.post_verify_2fa(&body).await;
     assert_eq!(response.status().as_u16(), 200);
@@ -175,7 +173,7 @@ async fn should_return_401_if_same_code_twice() {
     let email = get_random_email();
     let email_parsed = Email::parse(Secret::new(email.clone())).expect("invalid email");
 
-    assert!(true == app.create_account(&email, "password123", true).await);
+    assert!(app.create_account(&email, "password123", true).await);
 
     Mock::given(path("/email"))
         .and(method("POST"))
@@ -189,18 +187,17 @@ async fn should_return_401_if_same_code_twice() {
     let response = app.post_login(&body).await;
     assert_eq!(response.status().as_u16(), 206);
 
-    let two_fa_store = app.two_fa_code_store.read().await;
+    let two_fa_store = &app.two_fa_code_store;
     let code_tuple = two_fa_store
         .get_code(&email_parsed)
         .await
         .expect("2FA codes not found");
-    drop(two_fa_store);
 
     // Verifying the codes once works
     let body = serde_json::json!({
         "email": email,
         "loginAttemptId": code_tuple.0.as_ref().expose_secret(),
-        "2FACode": code_tuple.1.as_ref().expose_secret(),
+        "2FACode": code_tuple.1.as_ref().unwrap().as_ref().expose_secret(),
     });
     let response = app.post_verify_2fa(&body).await;
     assert_eq!(response.status().as_u16(), 200);
@@ -217,3 +214,49 @@ async fn should_return_401_if_same_code_twice() {
 
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_invalidate_login_attempt_after_too_many_incorrect_codes() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let email_parsed = Email::parse(Secret::new(email.clone())).expect("invalid email");
+
+    assert!(app.create_account(&email, "password123", true).await);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({ "email": email, "password": "password123" });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (login_attempt_id, _) = app
+        .two_fa_code_store
+        .get_code(&email_parsed)
+        .await
+        .expect("2FA codes not found");
+
+    // Emailed codes never start with a zero, so this one is always wrong.
+    let body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": "012345",
+    });
+    for _ in 0..5 {
+        let response = app.post_verify_2fa(&body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The code is gone, so the user has to log in again.
+    assert!(
+        app.two_fa_code_store
+            .get_code(&email_parsed)
+            .await
+            .is_err()
+    );
+
+    app.clean_up().await;
+}
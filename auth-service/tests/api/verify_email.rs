@@ -0,0 +1,136 @@
+use crate::helpers::{
+    TestApp, VERIFICATION_EMAIL_SUBJECT, get_link_token, get_random_email, get_random_password,
+};
+use auth_service::routes::ResendVerificationResponse;
+
+#[tokio::test]
+async fn should_send_verification_email_at_signup() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": get_random_password(),
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let emails = app.wait_for_emails(VERIFICATION_EMAIL_SUBJECT, 1).await;
+    assert_eq!(emails.len(), 1);
+    assert!(!get_link_token(&emails[0]).is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_allow_login_once_verified() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let password = get_random_password();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": password,
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&login_body).await.status().as_u16(), 403);
+
+    app.verify_email().await;
+    assert_eq!(app.post_login(&login_body).await.status().as_u16(), 200);
+
+    // Following the link again is harmless.
+    app.verify_email().await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    assert!(
+        app.create_account(&email, &get_random_password(), false)
+            .await
+    );
+
+    // An auth token is signed by the same key, but must not verify an email.
+    let auth_token = app.authenticate_user(&email).await;
+    for token in [auth_token, "invalid".to_owned()] {
+        let response = app
+            .post_verify_email(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_verify_email(&serde_json::json!({ "code": "token" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let response = app
+        .post_resend_verification(&serde_json::json!({ "mail": "a@example.com" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_resend_verification_only_to_unverified_accounts() {
+    let mut app = TestApp::new().await;
+    let unverified = get_random_email();
+    let verified = get_random_email();
+    let password = get_random_password();
+
+    let signup_body = serde_json::json!({
+        "email": unverified,
+        "password": password,
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+    assert!(
+        app.create_account(&verified, &get_random_password(), false)
+            .await
+    );
+    let sent_at_signup = app
+        .wait_for_emails(VERIFICATION_EMAIL_SUBJECT, 2)
+        .await
+        .len();
+
+    let mut responses = Vec::new();
+    for email in [&unverified, &verified, &get_random_email()] {
+        let response = app
+            .post_resend_verification(&serde_json::json!({ "email": email }))
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+        responses.push(response.json::<ResendVerificationResponse>().await.unwrap());
+    }
+    // The response does not disclose whether the account exists, or is verified.
+    assert!(responses.windows(2).all(|pair| pair[0] == pair[1]));
+
+    app.wait_for_emails(VERIFICATION_EMAIL_SUBJECT, sent_at_signup + 1)
+        .await;
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(
+        app.emails_sent(VERIFICATION_EMAIL_SUBJECT).await.len(),
+        sent_at_signup + 1
+    );
+
+    // The new link verifies the account.
+    app.verify_email().await;
+    let login_body = serde_json::json!({ "email": unverified, "password": password });
+    assert_eq!(app.post_login(&login_body).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
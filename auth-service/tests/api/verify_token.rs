@@ -1,7 +1,10 @@
-use auth_service::utils::constants::JWT_COOKIE_NAME;
+use auth_service::{
+    domain::{ADMIN_ROLE, Email, Role, USERS_WRITE_PERMISSION},
+    utils::constants::JWT_COOKIE_NAME,
+};
 use secrecy::Secret;
 
-use crate::helpers::{TestApp, get_random_email};
+use crate::helpers::{TestApp, get_random_email, get_random_password};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
@@ -26,6 +29,7 @@ async fn should_return_200_valid_token() {
     });
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email().await;
 
     // Login to get a token
     let login_body = serde_json::json!({
@@ -61,17 +65,55 @@ async fn should_return_401_if_invalid_token() {
 #[tokio::test]
 async fn should_return_401_if_banned_token() {
     let mut app = TestApp::new().await;
-    let mut banned_store = app.banned_tokens_store.write().await;
+    let banned_store = &app.banned_tokens_store;
     assert!(
         banned_store
             .add_token(Secret::new("banned".to_owned()))
             .await
             .is_ok()
     );
-    drop(banned_store); // release write lock.
 
     let body = serde_json::json!({ "token": "banned", });
     let response = app.post_verify_token(&body).await;
     assert_eq!(response.status().as_u16(), 401);
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_check_required_permission() {
+    let mut app = TestApp::new().await;
+    let admin_email = get_random_email();
+    let user_email = get_random_email();
+    for email in [&admin_email, &user_email] {
+        assert!(
+            app.create_account(email, &get_random_password(), false)
+                .await
+        );
+    }
+    let admin = app
+        .user_store
+        .get_user(Email::parse(Secret::new(admin_email.clone())).unwrap())
+        .await
+        .unwrap();
+    app.user_store
+        .add_role(admin.id, Role::parse(ADMIN_ROLE).unwrap())
+        .await
+        .unwrap();
+
+    let admin_token = app.authenticate_user(&admin_email).await;
+    let user_token = app.authenticate_user(&user_email).await;
+    for (token, status) in [(&admin_token, 200), (&user_token, 403)] {
+        let body = serde_json::json!({
+            "token": token,
+            "requiredPermission": USERS_WRITE_PERMISSION,
+        });
+        let response = app.post_verify_token(&body).await;
+        assert_eq!(response.status().as_u16(), status);
+    }
+
+    // Without a required permission, any valid token is accepted.
+    let body = serde_json::json!({ "token": user_token });
+    assert_eq!(app.post_verify_token(&body).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}